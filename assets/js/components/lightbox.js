@@ -1,3 +1,5 @@
+import { track_event } from '@js/utils/metrics';
+
 export default selector => {
     let lightbox = document.querySelector('.lightbox');
     let img;
@@ -40,6 +42,7 @@ export default selector => {
                     lightbox.classList.add('lightbox--active');
                     img.setAttribute('src', element.getAttribute('src'));
                     body.style.overflow = 'hidden';
+                    track_event('lightbox_opened', { src: element.getAttribute('src') });
                 }
             })
         });
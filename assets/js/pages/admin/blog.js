@@ -8,6 +8,7 @@ import Quill from 'quill';
 import { formatDistance } from 'date-fns';
 import { fr } from 'date-fns/locale';
 import { base64_to_blob } from '../../utils/base642blob';
+import { delta_to_blocks, blocks_to_html } from '@js/utils/blocks';
 import { DropZone } from '@js/components/assets_grid';
 import swal_error, { data_removed } from '@js/utils/swal_error';
 
@@ -120,11 +121,16 @@ router.on('mount', () => {
             description: {
                 validators: [new StringLength(0, 320)]
             },
+            format: {},
             content: {
                 validators: [new Required()]
             },
+            markdown: {
+                validators: [new StringLength(0, 100000)]
+            },
             is_published: {},
-            is_seo: {}
+            is_seo: {},
+            comments_closed: {}
         }
     })
         .on('send', async e => {
@@ -141,72 +147,53 @@ router.on('mount', () => {
             
             for (const [key, value] of Object.entries(e.detail)) {
                 if (article_to_modify) {
-                    if (article_to_modify[key] !== value && value && key !== 'content') {
+                    // Reopening the comments must be sent too
+                    if (
+                        article_to_modify[key] !== value
+                        && (value || key === 'comments_closed')
+                        && key !== 'content'
+                        && key !== 'markdown'
+                    ) {
                         body.append(key, value);
                     }
                 } else {
-                    if (value && key !== 'content') {
+                    if (value && key !== 'content' && key !== 'markdown') {
                         body.append(key, value);
                     }
                 }
             }
 
-            if (article_to_modify) {
-                let i = 0;
-                const images = [];
-
-                let old_content = content_quill.getContents();
-                let content = JSON.parse(JSON.stringify(old_content));
+            if (e.detail.format === 'markdown') {
+                const pictures = markdown_pictures_input.files;
 
-                const a = article_to_modify.images.map(image => `/uploads/${image.path}`);
+                if (
+                    !article_to_modify
+                    || pictures.length
+                    || e.detail.markdown !== article_to_modify.markdown
+                ) {
+                    body.append('markdown', e.detail.markdown);
 
-                for (const ops of content.ops) {
-                    if (
-                        ops.insert !== undefined
-                        && ops.insert.image !== undefined
-                        && !a.includes(ops.insert.image)
-                    ) {
-                        images.push(ops.insert.image);
-                        ops.insert = `[[${i}]]`;
-                        i += 1;
+                    for (const picture of pictures) {
+                        body.append('pictures[]', picture);
                     }
                 }
-                content_quill.setContents(content);
-                let formatted_content = content_quill.root.innerHTML
-                for (const image of article_to_modify.images) {
-                    formatted_content = formatted_content.replace(`<img src="/uploads/${image.path}">`, `[[${image.id}]]`)
-                }
-                content_quill.setContents(old_content);
-
-
-                body.append('content', formatted_content);
-    
-                for (const image of images) {
-                    body.append('pictures[]', base64_to_blob(image));
-                }
             } else {
-                let i = 0;
-                const images = [];
-
-                let old_content = content_quill.getContents();
-                let content = JSON.parse(JSON.stringify(old_content));
-
-                for (const ops of content.ops) {
-                    if (ops.insert !== undefined && ops.insert.image !== undefined) {
-                        images.push(ops.insert.image);
-                        ops.insert = `[[${i}]]`;
-                        i += 1;
+                const { blocks, pictures } = delta_to_blocks(
+                    content_quill.getContents(),
+                    article_to_modify ? article_to_modify.images : []
+                );
+
+                if (
+                    !article_to_modify
+                    || pictures.length
+                    || JSON.stringify(blocks) !== JSON.stringify(article_to_modify.blocks)
+                ) {
+                    body.append('blocks', JSON.stringify(blocks));
+
+                    for (const picture of pictures) {
+                        body.append('pictures[]', base64_to_blob(picture));
                     }
                 }
-                content_quill.setContents(content);
-                const formatted_content = content_quill.root.innerHTML
-                content_quill.setContents(old_content);
-
-                body.append('content', formatted_content);
-    
-                for (const image of images) {
-                    body.append('pictures[]', base64_to_blob(image));
-                }
             }
 
             let i  = 0;
@@ -282,7 +269,7 @@ router.on('mount', () => {
                 article_modal.close();
             } catch(e) {
                 submit_btn.innerHTML = submit_btn_value_before_send;
-                swal_error()
+                swal_error(e)
             }
 
             submit_btn.innerHTML = submit_btn_value_before_send;
@@ -348,20 +335,74 @@ router.on('mount', () => {
     const content_quill = new Quill(document.querySelector('#content_editor'), {
         modules: {
             toolbar: [
-                [{ list: 'ordered' }, { list: 'bullet' }],
-                ['bold', 'link', 'image', 'clean']
+                [{ header: [1, 2, 3, false] }],
+                [{ list: 'ordered' }, { list: 'bullet' }, 'blockquote'],
+                ['bold', 'italic', 'link', 'image', 'video', 'clean']
             ]
         },
         theme: 'snow'
     });
     const content_input = document.querySelector('[name="content"]');
-    content_quill.on('text-change', () => {
-        content_input.value = content_quill.getText().length === 1
-            ? ''
-            : content_quill.root.innerHTML;
+    const format_select = article_modal_container.querySelector('[name="format"]');
+    const markdown_input = article_modal_container.querySelector('[name="markdown"]');
+    const markdown_pictures_input = article_modal_container.querySelector('#markdown_pictures');
+    const markdown_images = article_modal_container.querySelector('.markdown_images');
+
+    // The content is required in the format chosen
+    const sync_content = () => {
+        if (format_select.value === 'markdown') {
+            content_input.value = markdown_input.value.trim();
+        } else {
+            content_input.value = content_quill.getText().length === 1
+                ? ''
+                : content_quill.root.innerHTML;
+        }
 
         content_input.dispatchEvent(new Event('input'));
-    });
+    };
+    const toggle_format = () => {
+        const is_markdown = format_select.value === 'markdown';
+
+        article_modal_container
+            .querySelector('#content_blocks')
+            .classList.toggle('hidden', is_markdown);
+        article_modal_container
+            .querySelector('#content_markdown')
+            .classList.toggle('hidden', !is_markdown);
+
+        sync_content();
+    };
+    // Images of the article, inserted in the source on click
+    const show_markdown_images = images => {
+        markdown_images.innerHTML = '';
+
+        for (const image of images) {
+            const item = document.createElement('li');
+            const thumbnail = document.createElement('img');
+            thumbnail.src = `/uploads/${image.path}`;
+
+            const insert_btn = document.createElement('button');
+            insert_btn.type = 'button';
+            insert_btn.innerText = `image:${image.id}`;
+            insert_btn.addEventListener('click', () => {
+                const reference = `![](image:${image.id})`;
+                const { selectionStart, selectionEnd, value } = markdown_input;
+
+                markdown_input.value = value.slice(0, selectionStart) + reference + value.slice(selectionEnd);
+                markdown_input.focus();
+                markdown_input.selectionStart = markdown_input.selectionEnd = selectionStart + reference.length;
+                markdown_input.dispatchEvent(new Event('input'));
+            });
+
+            item.appendChild(thumbnail);
+            item.appendChild(insert_btn);
+            markdown_images.appendChild(item);
+        }
+    };
+
+    content_quill.on('text-change', sync_content);
+    markdown_input.addEventListener('input', sync_content);
+    format_select.addEventListener('change', toggle_format);
     content_label.addEventListener('click', () => content_quill.focus());
 
     let article_modal = new Modal(article_modal_container)
@@ -395,11 +436,13 @@ router.on('mount', () => {
                 // TODO : set cover
                 article_cover_dropzone.setImage(`/uploads/${article_to_modify.cover}`);
 
-                for (const image of article_to_modify.images) {
-                    article_to_modify.content = article_to_modify.content.replace(`[[${image.id}]]`, `<img src="/uploads/${image.path}" />`);
-                }
-
+                article_to_modify.content = blocks_to_html(
+                    article_to_modify.blocks,
+                    article_to_modify.images
+                );
                 content_quill.root.innerHTML = article_to_modify.content;
+                article_to_modify.markdown = article_to_modify.markdown || '';
+                show_markdown_images(article_to_modify.images);
             }
 
             if (article_to_modify) {
@@ -419,11 +462,17 @@ router.on('mount', () => {
     
             if (article_to_modify) {
                 article_form.fill(article_to_modify);
+            } else {
+                format_select.value = 'blocks';
             }
+
+            toggle_format();
         })
         .on('open', () => document.querySelector('[name="title"]').focus())
         .on('close', () => {
             content_quill.root.innerHTML = '';
+            markdown_pictures_input.value = null;
+            markdown_images.innerHTML = '';
             article_form_submit_btn.classList.remove(`btn__${article_to_modify ? 'blue' : 'green'}`);
             article_to_modify = null;
 
@@ -0,0 +1,160 @@
+import Swal from 'sweetalert2';
+import { del, get, patch, post } from '@js/utils/http';
+import swal_error from '@js/utils/swal_error';
+import 'router';
+
+const { router } = window;
+
+/**
+ * Moderation actions, each one sets the status of the comment
+ */
+const ACTIONS = {
+    approved: 'Approuver',
+    rejected: 'Refuser',
+    spam: 'Indésirable'
+};
+
+/**
+ * @param {string} text
+ * @returns {string}
+ */
+function escape(text) {
+    const element = document.createElement('span');
+    element.textContent = text;
+
+    return element.innerHTML;
+}
+
+/**
+ * @param {string} date
+ * @returns {string}
+ */
+const format_date = date => new Date(date).toLocaleString('fr-FR', {
+    dateStyle: 'short',
+    timeStyle: 'short'
+});
+
+router.on('mount', () => {
+    const filters = document.querySelector('form[name="filters"]');
+    const list = document.getElementById('comments');
+    let page = 1;
+
+    const load = () => {
+        const params = new URLSearchParams({ page, status: filters.status.value });
+
+        get(`/api/comments?${params.toString()}`)
+            .then(response => response.json())
+            .then(({ comments, total, page_size }) => {
+                const pages = Math.max(1, Math.ceil(total / page_size));
+
+                // The content is sanitized by the server, as on the blog
+                list.innerHTML = comments
+                    .map(comment => `<li class="comments__item${comment.parent_id ? ' reply' : ''}" data-id="${comment.id}">
+                        <div>
+                            <strong>${escape(comment.author)}</strong>
+                            ${comment.email ? `<a href="mailto:${escape(comment.email)}">${escape(comment.email)}</a>` : ''}
+                        </div>
+                        <small>
+                            ${format_date(comment.date)} ·
+                            <a href="/blog/articles/${escape(comment.article_uri || '')}" target="_blank">${escape(comment.article_title)}</a>
+                            ${comment.parent_id ? ' · Réponse' : ''}
+                        </small>
+                        <div class="comments__content">${comment.content}</div>
+                        <div class="flex gap_2">
+                            ${Object.entries(ACTIONS)
+                                .filter(([status]) => status !== comment.status)
+                                .map(([status, label]) => `<button type="button" data-status="${status}">${label}</button>`)
+                                .join('')}
+                            <button type="button" data-reply>Répondre</button>
+                            <button type="button" data-delete>Supprimer</button>
+                        </div>
+                        <form name="reply" hidden>
+                            <textarea name="content" rows="4" maxlength="2000" required></textarea>
+                            <button type="submit">Publier la réponse</button>
+                        </form>
+                    </li>`)
+                    .join('') || '<li>Aucun commentaire</li>';
+
+                document.getElementById('page').textContent = `${page} / ${pages}`;
+                document.querySelector('[data-page="previous"]').disabled = page <= 1;
+                document.querySelector('[data-page="next"]').disabled = page >= pages;
+            })
+            .catch(swal_error);
+    };
+
+    const set_status = (id, status) => {
+        patch(`/api/comments/${id}`, {
+            headers: { 'Content-Type': 'application/json' },
+            body: { status }
+        })
+            .then(load)
+            .catch(swal_error);
+    };
+
+    const remove = id => {
+        Swal.fire({
+            title: 'Suppression',
+            text: 'Êtes-vous certain.e de vouloir supprimer ce commentaire et ses réponses ?',
+            icon: 'warning',
+            showCancelButton: true,
+            confirmButtonColor: '#3085d6',
+            cancelButtonColor: '#d33',
+            confirmButtonText: 'Oui, supprimer',
+            cancelButtonText: 'Annuler',
+            reverseButtons: true
+        })
+            .then(res => {
+                if (!res.isConfirmed) return;
+
+                del(`/api/comments/${id}`)
+                    .then(load)
+                    .catch(swal_error);
+            });
+    };
+
+    list.addEventListener('click', e => {
+        const item = e.target.closest('[data-id]');
+        const button = e.target.closest('button');
+
+        if (!item || !button) return;
+
+        if (button.dataset.status) {
+            set_status(item.dataset.id, button.dataset.status);
+        } else if (button.hasAttribute('data-reply')) {
+            const form = item.querySelector('form[name="reply"]');
+
+            form.hidden = !form.hidden;
+            form.content.focus();
+        } else if (button.hasAttribute('data-delete')) {
+            remove(item.dataset.id);
+        }
+    });
+    list.addEventListener('submit', e => {
+        e.preventDefault();
+
+        const form = e.target;
+        const item = form.closest('[data-id]');
+
+        post(`/api/comments/${item.dataset.id}/reply`, {
+            headers: { 'Content-Type': 'application/json' },
+            body: { content: form.content.value }
+        })
+            .then(load)
+            .catch(swal_error);
+    });
+    filters.addEventListener('submit', e => e.preventDefault());
+    filters.status.addEventListener('change', () => {
+        page = 1;
+        load();
+    });
+    document.getElementById('pagination').addEventListener('click', e => {
+        const button = e.target.closest('[data-page]');
+
+        if (!button) return;
+
+        page += button.dataset.page === 'next' ? 1 : -1;
+        load();
+    });
+
+    load();
+});
@@ -0,0 +1,228 @@
+import { get, patch, post, put } from '@js/utils/http';
+import swal_error from '@js/utils/swal_error';
+import 'router';
+
+const { router } = window;
+
+const STATUSES = {
+    new: 'Non lu',
+    read: 'Lu',
+    replied: 'Répondu',
+    archived: 'Archivé',
+    spam: 'Indésirable'
+};
+
+/**
+ * @param {string} text
+ * @returns {string}
+ */
+function escape(text) {
+    const element = document.createElement('span');
+    element.textContent = text;
+
+    return element.innerHTML;
+}
+
+/**
+ * @param {string} date
+ * @returns {string}
+ */
+const format_date = date => new Date(date).toLocaleString('fr-FR', {
+    dateStyle: 'short',
+    timeStyle: 'short'
+});
+
+/**
+ * Refresh the unread badge of the sidenav
+ */
+const update_unread = () => window.dispatchEvent(new CustomEvent('messages:read'));
+
+/**
+ * Emails the outbox could not send, with a button to send them again
+ */
+const load_failed_emails = () => {
+    const container = document.getElementById('failed_emails');
+
+    get('/api/emails/failed')
+        .then(response => response.json())
+        .then(emails => {
+            container.hidden = emails.length === 0;
+            container.querySelector('tbody').innerHTML = emails
+                .map(email => `<tr>
+                    <td>${escape(email.recipient)}</td>
+                    <td>${escape(email.subject || '-')}</td>
+                    <td>${email.attempts}${email.status === 'pending' ? ' (nouvel essai prévu)' : ''}</td>
+                    <td><small>${escape(email.last_error || '-')}</small></td>
+                    <td><button type="button" data-retry="${email.id}">Renvoyer</button></td>
+                </tr>`)
+                .join('');
+        })
+        .catch(swal_error);
+};
+
+/**
+ * Acknowledgement sent to the visitors, editable from its form
+ */
+const load_auto_reply = form => {
+    get('/api/messages/auto-reply')
+        .then(response => response.json())
+        .then(({ enabled, subject, body }) => {
+            form.enabled.checked = enabled;
+            form.subject.value = subject;
+            form.body.value = body;
+        })
+        .catch(swal_error);
+};
+
+router.on('mount', () => {
+    const filters = document.querySelector('form[name="filters"]');
+    const list = document.getElementById('messages');
+    const details = document.getElementById('message');
+    const reply_form = document.querySelector('form[name="reply"]');
+    let page = 1;
+    let current = null;
+
+    const load = () => {
+        const params = new URLSearchParams({ page });
+
+        if (filters.status.value) params.set('status', filters.status.value);
+        if (filters.search.value.trim()) params.set('search', filters.search.value.trim());
+
+        get(`/api/messages?${params.toString()}`)
+            .then(response => response.json())
+            .then(({ messages, total, page_size }) => {
+                const pages = Math.max(1, Math.ceil(total / page_size));
+
+                list.innerHTML = messages
+                    .map(message => `<li class="messages__item ${message.status}" data-id="${message.id}">
+                        <strong>${escape(message.firstname)} ${escape(message.lastname)}</strong>
+                        <small>${format_date(message.date)} · ${STATUSES[message.status]}</small>
+                        <p>${escape(message.content.slice(0, 120))}</p>
+                    </li>`)
+                    .join('') || '<li>Aucun message</li>';
+
+                document.getElementById('page').textContent = `${page} / ${pages}`;
+                document.querySelector('[data-page="previous"]').disabled = page <= 1;
+                document.querySelector('[data-page="next"]').disabled = page >= pages;
+            })
+            .catch(swal_error);
+    };
+
+    const open = id => {
+        get(`/api/messages/${id}`)
+            .then(response => response.json())
+            .then(({ message, replies }) => {
+                current = message;
+
+                details.querySelector('[data-field="name"]').textContent = `${message.firstname} ${message.lastname}`;
+                details.querySelector('[data-field="email"]').textContent = message.email;
+                details.querySelector('[data-field="email"]').href = `mailto:${message.email}`;
+                details.querySelector('[data-field="phone_number"]').textContent = message.phone_number || '';
+                details.querySelector('[data-field="date"]').textContent = format_date(message.date);
+                details.querySelector('[data-field="content"]').textContent = message.content;
+                document.getElementById('replies').innerHTML = replies
+                    .map(reply => `<li>
+                        <small>${format_date(reply.date)}</small>
+                        <p>${escape(reply.content)}</p>
+                    </li>`)
+                    .join('');
+                details.hidden = false;
+
+                load();
+                update_unread();
+            })
+            .catch(swal_error);
+    };
+
+    const set_status = status => {
+        patch(`/api/messages/${current.id}`, {
+            headers: { 'Content-Type': 'application/json' },
+            body: { status }
+        })
+            .then(() => {
+                if (status !== 'read') {
+                    details.hidden = true;
+                    current = null;
+                }
+
+                load();
+                update_unread();
+            })
+            .catch(swal_error);
+    };
+
+    list.addEventListener('click', e => {
+        const item = e.target.closest('[data-id]');
+
+        if (item) open(item.dataset.id);
+    });
+    details.querySelectorAll('[data-status]').forEach(button => {
+        button.addEventListener('click', () => set_status(button.dataset.status));
+    });
+    reply_form.addEventListener('submit', e => {
+        e.preventDefault();
+
+        post(`/api/messages/${current.id}/reply`, {
+            headers: { 'Content-Type': 'application/json' },
+            body: { content: reply_form.content.value }
+        })
+            .then(() => {
+                reply_form.reset();
+                open(current.id);
+            })
+            .catch(swal_error);
+    });
+    filters.addEventListener('submit', e => e.preventDefault());
+    filters.status.addEventListener('change', () => {
+        page = 1;
+        load();
+    });
+    filters.search.addEventListener('change', () => {
+        page = 1;
+        load();
+    });
+    document.getElementById('pagination').addEventListener('click', e => {
+        const button = e.target.closest('[data-page]');
+
+        if (!button) return;
+
+        page += button.dataset.page === 'next' ? 1 : -1;
+        load();
+    });
+
+    document.getElementById('failed_emails').addEventListener('click', e => {
+        const button = e.target.closest('[data-retry]');
+
+        if (!button) return;
+
+        post(`/api/emails/${button.dataset.retry}/retry`, { validate_status: status => status === 200 })
+            .then(load_failed_emails)
+            .catch(swal_error);
+    });
+
+    const auto_reply_form = document.querySelector('form[name="auto_reply"]');
+
+    auto_reply_form.addEventListener('submit', e => {
+        e.preventDefault();
+
+        const button = auto_reply_form.querySelector('button[type="submit"]');
+        button.disabled = true;
+
+        put('/api/messages/auto-reply', {
+            headers: { 'Content-Type': 'application/json' },
+            body: {
+                enabled: auto_reply_form.enabled.checked,
+                subject: auto_reply_form.subject.value,
+                body: auto_reply_form.body.value
+            }
+        })
+            .catch(swal_error)
+            .finally(() => {
+                button.disabled = false;
+            });
+    });
+
+    load();
+    load_failed_emails();
+    load_auto_reply(auto_reply_form);
+});
@@ -17,6 +17,9 @@ let project_fv = null;
 let editor = null;
 let add_project_modal = null;
 let project_to_modify = null;
+// Vimeo / YouTube videos of the project, `id` is only set for the saved ones
+let embeds = [];
+let embeds_to_delete = [];
 const { router } = window;
 
 const delete_project = (el, id) => Swal.fire({
@@ -49,13 +52,6 @@ router.on('mount', () => {
     let projects_container = null;
     let sortable_categories = null;
 
-    const swal_error = () => Swal.fire({
-        title: 'Une erreur est survenue',
-        text: 'Si le problème persiste veuillez contacter la personne en charge de la maintenance de votre site-web.',
-        icon: 'error',
-        footer: "<a href=\"https://greenassembly.fr/contact\" target=\"_blank\">Contacter l'agence GreenAssembly</a>"
-    });
-
     const init_category_events = (el, span = null, input = null, button = null) => {
         const id = el.dataset.id;
 
@@ -376,7 +372,7 @@ router.on('mount', () => {
 
                 // return
 
-                for (const asset of project_to_modify.assets) {
+                for (const asset of project_to_modify.assets.filter(asset => asset.kind !== 'embed')) {
                     const path = `/uploads/${asset.path}`;
                     let asset_modified = false;
                     const asset_to_modify = { id: asset.id };
@@ -397,6 +393,16 @@ router.on('mount', () => {
                     }
                 }
 
+                embeds_to_delete.forEach(id => assets_modified.push({ id, to_delete: true }));
+
+                for (const video of videos_input.files) {
+                    body.append('files[]', video);
+                }
+
+                embeds
+                    .filter(embed => !embed.id)
+                    .forEach(embed => body.append('embeds[]', embed.url));
+
                 console.log('Need to update assets ?', assets_modified);
 
                 if (assets_modified.length > 0) {
@@ -423,6 +429,12 @@ router.on('mount', () => {
                 }
             } else {
                 assets_grid.value.forEach(img => body.append('files[]', img));
+
+                for (const video of videos_input.files) {
+                    body.append('files[]', video);
+                }
+
+                embeds.forEach(embed => body.append('embeds[]', embed.url));
             }
 
             try {
@@ -470,7 +482,7 @@ router.on('mount', () => {
             } catch (e) {
                 console.log(e)
                 reset_submit_btn(submit_value);
-                swal_error();
+                swal_error(e);
             }
         });
     
@@ -495,6 +507,59 @@ router.on('mount', () => {
         content.dispatchEvent(new Event('input'));
     })
     
+    // Videos
+    const videos_input = document.querySelector('#videos');
+    const embeds_container = document.querySelector('.embeds');
+    const new_embed_input = document.querySelector('#new_embed');
+    const render_embeds = () => {
+        embeds_container.innerHTML = '';
+
+        embeds.forEach(embed => {
+            const item = document.createElement('li');
+            const link = document.createElement('span');
+            link.innerText = embed.url;
+
+            const delete_btn = document.createElement('button');
+            delete_btn.type = 'button';
+            delete_btn.classList.add('text_error');
+            delete_btn.innerHTML = `<svg class="icon icon--sm">
+                <use xlink:href="/dashboard_icons.svg#delete"></use>
+            </svg>`;
+            delete_btn.addEventListener('click', () => {
+                if (embed.id) {
+                    embeds_to_delete.push(embed.id);
+                }
+
+                embeds = embeds.filter(item => item !== embed);
+                render_embeds();
+            });
+
+            item.appendChild(link);
+            item.appendChild(delete_btn);
+            embeds_container.appendChild(item);
+        });
+    }
+
+    new_embed_input
+        .nextElementSibling
+        .addEventListener('click', () => {
+            const url = new_embed_input.value.trim();
+
+            if (!/^https:\/\/((www\.|player\.)?vimeo\.com|(www\.|m\.)?youtube\.com|youtu\.be)\//.test(url)) {
+                Swal.fire({
+                    title: 'Lien invalide',
+                    text: 'Seuls les liens Vimeo et YouTube sont acceptés.',
+                    icon: 'error'
+                });
+
+                return;
+            }
+
+            embeds.push({ url });
+            new_embed_input.value = '';
+            render_embeds();
+        });
+
     const cropper_el = document.querySelector('#cropper');
     let cropper = null;
     const assets_grid = new AssetsGrid(document.querySelector('.assets'));
@@ -580,7 +645,15 @@ router.on('mount', () => {
                 }
 
                 editor.root.innerHTML = project_to_modify.content;
-                assets_grid.setImages(project_to_modify.assets.map(asset => `/uploads/${asset.path}`));
+                assets_grid.setImages(
+                    project_to_modify.assets
+                        .filter(asset => asset.kind !== 'embed')
+                        .map(asset => `/uploads/${asset.path}`)
+                );
+                embeds = project_to_modify.assets
+                    .filter(asset => asset.kind === 'embed')
+                    .map(asset => ({ id: asset.id, url: asset.embed_url }));
+                render_embeds();
 
                 modal.modal.querySelector('.modal__dialog__footer > :first-child').classList.remove('hidden');
                 modal.modal.querySelector('.modal__dialog__footer > :last-child').innerText = project_to_modify
@@ -605,6 +678,10 @@ router.on('mount', () => {
             }
 
             project_to_modify = null;
+            embeds = [];
+            embeds_to_delete = [];
+            videos_input.value = '';
+            render_embeds();
             assets_grid.clear();
             editor.setContents([]);
             project_fv.clear();
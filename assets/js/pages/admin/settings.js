@@ -16,6 +16,7 @@ router.on('mount', () => {
     let background_color = document.querySelector('[name="background_color"]').value;
     let title_color = document.querySelector('[name="title_color"]').value;
     let text_color = document.querySelector('[name="text_color"]').value;
+    let comments_enabled = document.querySelector('[name="comments_enabled"]').checked;
     let logo = logo_input.querySelector('img').getAttribute('src');
     let favicon = favicon_input.querySelector('img').getAttribute('src');
 
@@ -32,7 +33,8 @@ router.on('mount', () => {
             favicon: {},
             background_color: {},
             title_color: {},
-            text_color: {}
+            text_color: {},
+            comments_enabled: {}
         }
     })
     .on('send', e => {
@@ -50,6 +52,9 @@ router.on('mount', () => {
         if (e.detail.text_color !== text_color) {
             body.append('text_color', e.detail.text_color);
         }
+        if (e.detail.comments_enabled !== comments_enabled) {
+            body.append('comments_enabled', e.detail.comments_enabled);
+        }
 
         if (e.detail.logo[0]) {
             body.append('logo', e.detail.logo[0]);
@@ -81,6 +86,9 @@ router.on('mount', () => {
                 if (e.detail.text_color !== text_color) {
                     text_color = e.detail.text_color;
                 }
+                if (e.detail.comments_enabled !== comments_enabled) {
+                    comments_enabled = e.detail.comments_enabled;
+                }
             })
             .catch(swal_error)
         }
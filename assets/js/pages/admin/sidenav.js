@@ -0,0 +1,24 @@
+import { get } from '@js/utils/http';
+import 'router';
+
+const { router } = window;
+
+/**
+ * Number of new contact messages, next to the link of the inbox
+ */
+const update_unread = () => {
+    get('/api/messages/unread')
+        .then(response => response.json())
+        .then(({ count }) => {
+            const badge = document.getElementById('unread_messages');
+
+            if (!badge) return;
+
+            badge.textContent = count > 99 ? '99+' : count;
+            badge.hidden = count === 0;
+        })
+        .catch(() => {});
+};
+
+window.addEventListener('messages:read', update_unread);
+router.on('mount', update_unread);
@@ -0,0 +1,305 @@
+import { get } from '@js/utils/http';
+import swal_error from '@js/utils/swal_error';
+import 'router';
+
+const { router } = window;
+
+const KINDS = {
+    page: 'Page',
+    project: 'Projet',
+    article: 'Article',
+    category: 'Catégorie'
+};
+
+const SOURCES = {
+    direct: 'Accès direct',
+    internal: 'Navigation interne',
+    search: 'Moteurs de recherche',
+    social: 'Réseaux sociaux',
+    website: 'Autres sites'
+};
+
+/**
+ * @param {number|null} seconds
+ * @returns {string}
+ */
+function format_duration(seconds) {
+    if (seconds === null || seconds === undefined) {
+        return '-';
+    }
+
+    const minutes = Math.floor(seconds / 60);
+
+    return minutes > 0 ? `${minutes} min ${Math.round(seconds % 60)} s` : `${Math.round(seconds)} s`;
+}
+
+/**
+ * @param {string} key
+ * @param {number|null} value
+ * @returns {string}
+ */
+function format_value(key, value) {
+    if (value === null || value === undefined) {
+        return '-';
+    }
+
+    switch (key) {
+        case 'average_duration':
+            return format_duration(value);
+        case 'bounce_rate':
+            return `${Math.round(value * 100)} %`;
+        case 'pages_per_session':
+            return value.toLocaleString('fr-FR', { maximumFractionDigits: 1 });
+        default:
+            return value.toLocaleString('fr-FR');
+    }
+}
+
+/**
+ * Evolution between the previous and the current value, in percent
+ * @param {number|null} current
+ * @param {number|null} previous
+ * @returns {string}
+ */
+function delta(current, previous) {
+    if (!current || !previous) {
+        return '';
+    }
+
+    const percent = Math.round((current - previous) / previous * 100);
+
+    return `<small class="delta delta--${percent >= 0 ? 'up' : 'down'}">${percent >= 0 ? '+' : ''}${percent} %</small>`;
+}
+
+/**
+ * @param {string} text
+ * @returns {string}
+ */
+function escape(text) {
+    const element = document.createElement('span');
+    element.textContent = text;
+
+    return element.innerHTML;
+}
+
+/**
+ * Bars of the views of every hour or day of the period
+ * @param {{date: string, views: number, visitors: number}[]} timeline
+ */
+function render_timeline(timeline) {
+    const max = Math.max(1, ...timeline.map(point => point.views));
+    const by_hour = timeline.length > 1
+        && new Date(timeline[1].date) - new Date(timeline[0].date) < 24 * 60 * 60 * 1000;
+
+    document.getElementById('timeline').innerHTML = timeline
+        .map(point => {
+            const date = new Date(point.date);
+            const label = by_hour
+                ? date.toLocaleString('fr-FR', { day: 'numeric', month: 'short', hour: '2-digit' })
+                : date.toLocaleDateString('fr-FR', { day: 'numeric', month: 'short' });
+
+            return `<div class="timeline__bar"
+                style="height: ${point.views / max * 100}%"
+                title="${label} : ${point.views} vues, ${point.visitors} visiteurs"></div>`;
+        })
+        .join('');
+}
+
+function render(statistics) {
+    render_timeline(statistics.timeline);
+
+    document.querySelectorAll('#overview .kpi').forEach(kpi => {
+        const key = kpi.dataset.key;
+        const current = statistics.overview[key];
+        const previous = statistics.previous_overview[key];
+
+        kpi.innerHTML = `<span>${kpi.dataset.label}</span>
+            <strong>${format_value(key, current)}</strong>
+            ${delta(current, previous)}`;
+    });
+
+    document.querySelector('#views tbody').innerHTML = statistics.views
+        .map(view => `<tr>
+            <td><small>${KINDS[view.kind]}</small> ${escape(view.label)}</td>
+            <td>${view.views} ${delta(view.views, view.previous_views)}</td>
+            <td>${view.daily_visitors}</td>
+            <td>${format_duration(view.average_duration)}</td>
+        </tr>`)
+        .join('');
+
+    ['entries', 'exits'].forEach(boundary => {
+        document.querySelector(`#${boundary} tbody`).innerHTML = statistics[boundary]
+            .map(entry => `<tr>
+                <td><small>${KINDS[entry.kind]}</small> ${escape(entry.label)}</td>
+                <td>${entry.sessions} ${delta(entry.sessions, entry.previous_sessions)}</td>
+            </tr>`)
+            .join('');
+    });
+
+    document.querySelector('#referrers tbody').innerHTML = statistics.referrers
+        .map(referrer => `<tr>
+            <td>${escape(referrer.label || 'Inconnu')}</td>
+            <td>${referrer.views} ${delta(referrer.views, referrer.previous_views)}</td>
+        </tr>`)
+        .join('');
+
+    document.querySelector('#campaigns tbody').innerHTML = statistics.campaigns
+        .map(campaign => `<tr>
+            <td>${escape(campaign.source)}</td>
+            <td>${escape(campaign.medium || '-')}</td>
+            <td>${escape(campaign.campaign || '-')}</td>
+            <td>${campaign.landings}</td>
+            <td>${campaign.daily_visitors}</td>
+            <td>${campaign.views} ${delta(campaign.views, campaign.previous_views)}</td>
+            <td>${campaign.project_views}</td>
+        </tr>`)
+        .join('');
+
+    document.querySelector('#events tbody').innerHTML = statistics.events
+        .map(event => `<tr>
+            <td>${escape(event.name)}</td>
+            <td>${event.events} ${delta(event.events, event.previous_events)}</td>
+            <td>${event.daily_visitors}</td>
+        </tr>`)
+        .join('');
+
+    statistics.sources = statistics.sources.map(source => ({ ...source, label: SOURCES[source.label] }));
+
+    ['sources', 'browsers', 'os', 'devices'].forEach(dimension => {
+        const shares = statistics[dimension];
+        const total = shares.reduce((sum, share) => sum + share.views, 0);
+
+        document.getElementById(dimension).innerHTML = shares
+            .map(share => {
+                const percent = total > 0 ? Math.round(share.views / total * 100) : 0;
+
+                return `<li>
+                    <span>${escape(share.label || 'Inconnu')}</span>
+                    <span>${percent} %</span>
+                    <div class="bar" style="width: ${percent}%"></div>
+                </li>`;
+            })
+            .join('');
+    });
+}
+
+/**
+ * Visitors having reached every step, in percent of the first one
+ * @param {{name: string, visitors: number}[]} funnel
+ */
+function render_funnel(funnel) {
+    const first = funnel[0]?.visitors ?? 0;
+
+    document.getElementById('funnel').innerHTML = funnel
+        .map(step => {
+            const percent = first > 0 ? Math.round(step.visitors / first * 100) : 0;
+
+            return `<li>
+                <span>${escape(step.name)}</span>
+                <span>${step.visitors} (${percent} %)</span>
+                <div class="bar" style="width: ${percent}%"></div>
+            </li>`;
+        })
+        .join('');
+}
+
+/** @type {EventSource|null} */
+let live_source = null;
+
+/**
+ * Visitors on the site and contents they are viewing, pushed by the server
+ */
+function listen_live() {
+    live_source?.close();
+    live_source = new EventSource('/api/metrics/live');
+
+    live_source.addEventListener('message', e => {
+        const container = document.getElementById('live');
+
+        // The page has been left
+        if (!container) {
+            live_source.close();
+            return;
+        }
+
+        const live = JSON.parse(e.data);
+
+        container.querySelector('[data-live="visitors"]').textContent = live.visitors;
+        document.getElementById('live_pages').innerHTML = live.pages
+            .map(page => `<li>
+                <span><small>${KINDS[page.kind]}</small> ${escape(page.label || 'Inconnu')}</span>
+                <span>${page.views}</span>
+            </li>`)
+            .join('');
+    });
+}
+
+router.on('mount', () => {
+    const form = document.querySelector('form[name="period"]');
+    const start = form.querySelector('[name="start"]');
+    const end = form.querySelector('[name="end"]');
+    const bots = form.querySelector('[name="bots"]');
+    const funnel_form = document.querySelector('form[name="funnel"]');
+    const steps = funnel_form.querySelector('[name="steps"]');
+
+    const get_params = () => {
+        const params = new URLSearchParams();
+
+        if (start.value) {
+            params.set('start', start.value);
+        }
+        if (end.value) {
+            params.set('end', end.value);
+        }
+        if (bots.checked) {
+            params.set('bots', 'true');
+        }
+
+        return params;
+    };
+
+    const load_funnel = () => {
+        const params = get_params();
+        params.set('steps', steps.value.replace(/\s/g, ''));
+
+        get(`/api/metrics/funnel?${params.toString()}`)
+            .then(response => response.json())
+            .then(render_funnel)
+            .catch(swal_error);
+    };
+
+    const load = () => {
+        get(`/api/metrics?${get_params().toString()}`)
+            .then(response => response.json())
+            .then(statistics => {
+                // The default period is chosen by the server
+                start.value = statistics.period.start.slice(0, 10);
+                end.value = new Date(new Date(statistics.period.end) - 1).toISOString().slice(0, 10);
+
+                render(statistics);
+                load_funnel();
+            })
+            .catch(swal_error);
+    };
+
+    start.addEventListener('change', load);
+    end.addEventListener('change', load);
+    bots.addEventListener('change', load);
+    document.querySelector('form[name="export"]').addEventListener('submit', e => {
+        e.preventDefault();
+
+        const params = get_params();
+        params.delete('bots');
+        params.set('format', e.target.format.value);
+        params.set('anonymize', e.target.anonymize.checked);
+
+        window.location.href = `/api/metrics/export/${e.target.table.value}?${params.toString()}`;
+    });
+    funnel_form.addEventListener('submit', e => {
+        e.preventDefault();
+        load_funnel();
+    });
+
+    load();
+    listen_live();
+});
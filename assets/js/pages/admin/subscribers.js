@@ -0,0 +1,80 @@
+import { get, del } from '@js/utils/http';
+import swal_error from '@js/utils/swal_error';
+import Swal from 'sweetalert2';
+import 'router';
+
+const { router } = window;
+
+const STATUSES = {
+    pending: 'En attente de confirmation',
+    confirmed: 'Confirmé',
+    unsubscribed: 'Désinscrit'
+};
+
+/**
+ * @param {string} text
+ * @returns {string}
+ */
+function escape(text) {
+    const element = document.createElement('span');
+    element.textContent = text;
+
+    return element.innerHTML;
+}
+
+/**
+ * @param {string} date
+ * @returns {string}
+ */
+const format_date = date => new Date(date).toLocaleString('fr-FR', {
+    dateStyle: 'short',
+    timeStyle: 'short'
+});
+
+const load = () => {
+    get('/api/subscribers')
+        .then(response => response.json())
+        .then(subscribers => {
+            const confirmed = subscribers.filter(subscriber => subscriber.status === 'confirmed').length;
+
+            document.getElementById('counter').textContent = `${confirmed} abonné${confirmed > 1 ? 's' : ''} confirmé${confirmed > 1 ? 's' : ''}`;
+            document.getElementById('subscribers').innerHTML = subscribers
+                .map(subscriber => `<tr>
+                    <td>${escape(subscriber.email)}</td>
+                    <td>${STATUSES[subscriber.status]}</td>
+                    <td>${format_date(subscriber.date)}</td>
+                    <td><button type="button" data-delete="${subscriber.id}">Supprimer</button></td>
+                </tr>`)
+                .join('') || '<tr><td colspan="4">Aucun abonné</td></tr>';
+        })
+        .catch(swal_error);
+};
+
+router.on('mount', () => {
+    document.getElementById('subscribers').addEventListener('click', e => {
+        const button = e.target.closest('[data-delete]');
+
+        if (!button) return;
+
+        Swal.fire({
+            title: 'Suppression',
+            text: 'Êtes-vous certain.e de vouloir supprimer cet abonné ?',
+            icon: 'warning',
+            showCancelButton: true,
+            confirmButtonColor: '#3085d6',
+            cancelButtonColor: '#d33',
+            confirmButtonText: 'Oui, supprimer',
+            cancelButtonText: 'Annuler',
+            reverseButtons: true
+        })
+            .then(res => {
+                if (!res.isConfirmed) return;
+
+                del(`/api/subscribers/${button.dataset.delete}`)
+                    .then(load)
+                    .catch(swal_error);
+            });
+    });
+
+    load();
+});
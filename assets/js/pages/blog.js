@@ -1,5 +1,7 @@
 import 'router';
 import lightbox from '@js/components/lightbox';
+import { post } from '@js/utils/http';
+import proof_of_work from '@js/utils/proof_of_work';
 
 const { router } = window;
 
@@ -10,6 +12,112 @@ router.on('mount', () => {
     categories = document.querySelectorAll('main > nav > a');
     const global_category = categories[0];
 
+    const newsletter = document.querySelector('form[name="newsletter"]');
+    const message = newsletter.querySelector('.newsletter__message');
+
+    newsletter.addEventListener('submit', e => {
+        e.preventDefault();
+
+        const body = { email: newsletter.email.value };
+
+        if (newsletter.website.value) {
+            body.website = newsletter.website.value;
+        }
+
+        post('/newsletter', {
+            headers: {
+                'Content-Type': 'application/x-www-form-urlencoded'
+            },
+            validate_status: status => status === 200,
+            body: new URLSearchParams(body)
+        })
+            .then(() => {
+                newsletter.reset();
+                message.textContent = 'Merci ! Un email vous a été envoyé pour confirmer votre inscription.';
+            })
+            .catch(() => {
+                message.textContent = 'Oups, une erreur est survenue, veuillez réessayer plus tard.';
+            })
+            .finally(() => {
+                message.hidden = false;
+            });
+    });
+
+    const comments = document.querySelector('#comments');
+    const comment_form = document.querySelector('form[name="comment"]');
+
+    if (comment_form) {
+        const comment_message = comment_form.querySelector('.comment__message');
+        const replying = comment_form.querySelector('.comment__replying');
+
+        const reply_to = (parent_id = '') => {
+            comment_form.parent_id.value = parent_id;
+
+            if (parent_id) {
+                replying.querySelector('span').textContent = comments
+                    .querySelector(`.comment[data-id="${parent_id}"] .comment__author`)
+                    .textContent;
+                replying.classList.remove('hidden');
+                comment_form.content.focus();
+            } else {
+                replying.classList.add('hidden');
+            }
+        };
+
+        comments
+            .querySelectorAll('.comment__reply')
+            .forEach(btn => btn.addEventListener('click', () => reply_to(btn.dataset.parent)));
+        replying
+            .querySelector('.comment__cancel')
+            .addEventListener('click', () => reply_to());
+
+        comment_form.addEventListener('submit', async e => {
+            e.preventDefault();
+
+            const submit_btn = comment_form.querySelector('[type="submit"]');
+            const body = {
+                author: comment_form.author.value,
+                content: comment_form.content.value,
+                token: comment_form.token.value
+            };
+
+            for (const key of ['email', 'parent_id', 'website']) {
+                if (comment_form[key].value) {
+                    body[key] = comment_form[key].value;
+                }
+            }
+
+            submit_btn.setAttribute('disabled', true);
+
+            // Spam protection, see `utils::spam`
+            const difficulty = parseInt(comment_form.token.dataset.difficulty, 10);
+
+            if (difficulty > 0) {
+                body.proof = await proof_of_work(body.token, difficulty);
+            }
+
+            post(`/blog/articles/${comments.dataset.article}/comments`, {
+                headers: {
+                    'Content-Type': 'application/x-www-form-urlencoded'
+                },
+                validate_status: status => status === 200,
+                body: new URLSearchParams(body)
+            })
+                .then(() => {
+                    comment_form.reset();
+                    reply_to();
+                    comment_message.textContent = 'Merci ! Votre commentaire sera publié après modération.';
+                })
+                .catch(() => {
+                    comment_message.textContent = 'Oups, une erreur est survenue, veuillez réessayer plus tard.';
+                })
+                .finally(() => {
+                    comment_message.hidden = false;
+                    submit_btn.removeAttribute('disabled');
+                });
+        });
+    }
+
     if (location.pathname.includes('/articles')) {
         global_category.classList.add('hidden');
     } else {
@@ -1,6 +1,8 @@
 import 'router';
 import Form, { Required, Regex, StringLength } from 'formvalidation';
 import { post } from '../utils/http';
+import { track_event } from '../utils/metrics';
+import proof_of_work from '../utils/proof_of_work';
 
 window.router.on('mount', () => {
     const required_validator = new Required();
@@ -38,7 +40,7 @@ window.router.on('mount', () => {
             }
         }
     })
-        .on('send', e => {
+        .on('send', async e => {
             const body = {};
 
             for (const [key, value] of Object.entries(e.detail)) {
@@ -47,6 +49,21 @@ window.router.on('mount', () => {
                 }
             }
 
+            // Spam protection, see `utils::spam`
+            const token = e.target.querySelector('[name="token"]');
+            const honeypot = e.target.querySelector('[name="website"]');
+            const difficulty = parseInt(token.dataset.difficulty, 10);
+
+            body.token = token.value;
+
+            if (honeypot.value) {
+                body.website = honeypot.value;
+            }
+
+            if (difficulty > 0) {
+                body.proof = await proof_of_work(token.value, difficulty);
+            }
+
             post('/contact', {
                 headers: {
                     'Content-Type': 'application/x-www-form-urlencoded'
@@ -55,6 +72,7 @@ window.router.on('mount', () => {
                 body: new URLSearchParams(body)
             })
                 .then(() => {
+                    track_event('contact_form_sent');
                     e.target.classList.add('hidden');
                     document.querySelector('#success').classList.remove('hidden');
                 })
@@ -1,13 +1,32 @@
 import 'router';
 import LazyLoader from '@js/components/lazy_loader';
 import { get } from '@js/utils/http';
+import { do_not_track, read_cookie, track_clicks, track_videos } from '@js/utils/metrics';
 
 const { router } = window;
 
-const read_cookie = (cookie_name) => {
-    return document.cookie.split('; ').find(row => row.startsWith(cookie_name))?.split('=')?.[1]
+/** Duration of the consent choice: 6 months */
+const CONSENT_MAX_AGE = 60 * 60 * 24 * 182;
+
+const show_consent = () => document.getElementById('consent')?.removeAttribute('hidden');
+
+/**
+ * Save the choice of the visitor, the session cookie is removed when it is refused
+ * @param {'granted'|'denied'} consent
+ */
+const set_consent = consent => {
+    document.cookie = `consent=${consent}; max-age=${CONSENT_MAX_AGE}; path=/; SameSite=Strict; Secure`;
+
+    if (consent === 'denied') {
+        document.cookie = 'sid=; max-age=0; path=/; SameSite=Strict; Secure';
+    }
+
+    document.getElementById('consent')?.setAttribute('hidden', '');
 }
 
+/** The first view of the page load is a landing view, the next ones are navigations inside the site */
+let is_landing = true;
+
 const send_metrics = () => {
     if (!navigator.sendBeacon) return;
     
@@ -27,6 +46,20 @@ const send_metrics = () => {
 document.addEventListener('readystatechange', e => {
     if (e.target.readyState === 'complete') {
         LazyLoader();
+        track_clicks();
+
+        document.querySelectorAll('#consent [data-consent]')
+            .forEach(button => button.addEventListener('click', () => set_consent(button.dataset.consent)));
+        document.getElementById('consent_settings')
+            ?.addEventListener('click', e => {
+                e.preventDefault();
+                show_consent();
+            });
+
+        if (!read_cookie('consent') && !do_not_track()) {
+            show_consent();
+        }
+
         const navbar = document.querySelector('#topbar nav');
         const open_menu_btn = document.querySelector('#open_mobile_menu');
         const close_mobile_menu = () => {
@@ -60,23 +93,46 @@ document.addEventListener('readystatechange', e => {
 router.on('change', async () => {
     LazyLoader();
     send_metrics();
+    track_videos();
 
+    if (do_not_track()) return;
+
+    // The session is only created with the consent of the visitor, views are
+    // still counted anonymously without it
     let sid = read_cookie('sid');
-    if(!sid) {
-        const res = await get('/metrics/session');
-        const data = await res.json();
-        sid = data.sid;
-        document.cookie = 'sid=' + sid + '; expires=' + new Date(data.vud).toUTCString() + '; SameSite=Strict; Secure';
+    if (!sid && read_cookie('consent') === 'granted') {
+        try {
+            const res = await get('/metrics/session');
+            const data = await res.json();
+            sid = data.sid;
+            // The server pushes back its expiration on every view
+            document.cookie = 'sid=' + sid + '; expires=' + new Date(data.vud).toUTCString() + '; path=/; SameSite=Strict; Secure';
+        } catch (_) {
+            sid = null;
+        }
     }
 
     const { pathname } = location;
-    const belongs_to = pathname.includes('/articles/')
-        ? 'BlogPost'
-        : pathname.includes('/portfolio/')
-            ? 'Project'
-            : 'Page';
-    const res = await get(`/metrics/token?path=${location.pathname}&sid=${sid}&belongs_to=${belongs_to}`);
-    localStorage.setItem('VID', await res.text());
+    const belongs_to = pathname.startsWith('/blog/articles/')
+        ? 'BlogArticle'
+        : pathname.startsWith('/blog/categories/')
+            ? 'BlogCategory'
+            : pathname.startsWith('/portfolio/')
+                ? 'Project'
+                : 'Page';
+    const params = new URLSearchParams({ path: location.pathname, belongs_to, search: location.search });
+    if (sid) {
+        params.set('sid', sid);
+    }
+    if (is_landing) {
+        params.set('referrer', document.referrer);
+        is_landing = false;
+    }
+
+    const res = await get(`/metrics/token?${params.toString()}`);
+    if (res.status === 200) {
+        localStorage.setItem('VID', await res.text());
+    }
 });
 
 window.addEventListener('unload', send_metrics, false);
@@ -0,0 +1,158 @@
+// Conversion between the content of the Quill editor and the blocks of an
+// article, see `services::blog::articles::blocks`
+
+/** Maximum number of images of a gallery */
+const MAX_GALLERY_IMAGES = 20;
+
+const escape = text => text
+    .replace(/&/g, '&amp;')
+    .replace(/</g, '&lt;')
+    .replace(/>/g, '&gt;')
+    .replace(/"/g, '&quot;');
+
+const inline = (text, attributes = {}) => {
+    let html = escape(text);
+
+    if (attributes.bold) {
+        html = `<b>${html}</b>`;
+    }
+    if (attributes.italic) {
+        html = `<i>${html}</i>`;
+    }
+    if (attributes.link) {
+        html = `<a href="${escape(attributes.link)}">${html}</a>`;
+    }
+
+    return html;
+};
+
+/**
+ * Blocks of the content of the editor. The images already saved are found
+ * with their path in `images`, the new ones are returned in `pictures` to be
+ * sent with the blocks, referenced by their index
+ */
+export const delta_to_blocks = (delta, images = []) => {
+    const blocks = [];
+    const pictures = [];
+    let html = '';
+    let text = '';
+
+    const push = block => {
+        const last = blocks[blocks.length - 1];
+
+        // The following images make a gallery
+        if (block.type === 'image' && last && last.type === 'image') {
+            const { type, ...image } = last;
+            blocks[blocks.length - 1] = { type: 'gallery', images: [image, block.image] };
+            return;
+        }
+        if (
+            block.type === 'image'
+            && last
+            && last.type === 'gallery'
+            && last.images.length < MAX_GALLERY_IMAGES
+        ) {
+            last.images.push(block.image);
+            return;
+        }
+        // The editor has a line by item
+        if (block.type === 'list' && last && last.type === 'list' && last.ordered === block.ordered) {
+            last.items.push(...block.items);
+            return;
+        }
+
+        blocks.push(block.type === 'image' ? { type: 'image', ...block.image } : block);
+    };
+
+    const end_line = (attributes = {}) => {
+        if (text.trim()) {
+            if (attributes.header) {
+                push({ type: 'heading', level: attributes.header + 1, text });
+            } else if (attributes.list) {
+                push({ type: 'list', ordered: attributes.list === 'ordered', items: [html] });
+            } else if (attributes.blockquote) {
+                push({ type: 'quote', text });
+            } else {
+                push({ type: 'paragraph', text: html });
+            }
+        }
+
+        html = '';
+        text = '';
+    };
+
+    for (const op of delta.ops) {
+        if (typeof op.insert === 'string') {
+            op.insert.split('\n').forEach((part, i) => {
+                if (i > 0) {
+                    end_line(op.attributes);
+                }
+
+                html += inline(part, op.attributes);
+                text += part;
+            });
+        } else if (op.insert.image) {
+            end_line();
+
+            const src = op.insert.image;
+            const image = { alt: (op.attributes && op.attributes.alt) || '' };
+
+            if (src.startsWith('data:')) {
+                pictures.push(src);
+                image.source = { upload: pictures.length - 1 };
+            } else {
+                const saved = images.find(image => `/uploads/${image.path}` === src);
+
+                if (!saved) {
+                    continue;
+                }
+
+                image.source = { id: saved.id };
+            }
+
+            push({ type: 'image', image });
+        } else if (op.insert.video) {
+            end_line();
+            push({ type: 'embed', url: op.insert.video });
+        }
+    }
+
+    end_line();
+
+    return { blocks, pictures };
+};
+
+/** HTML of the blocks of an article, to be edited */
+export const blocks_to_html = (blocks, images = []) => {
+    const image_html = image => {
+        const saved = images.find(saved => saved.id === image.source.id);
+
+        return saved ? `<p><img src="/uploads/${saved.path}" alt="${escape(image.alt)}"></p>` : '';
+    };
+
+    return blocks
+        .map(block => {
+            switch (block.type) {
+                case 'paragraph':
+                    return `<p>${block.text}</p>`;
+                case 'heading':
+                    return `<h${block.level - 1}>${escape(block.text)}</h${block.level - 1}>`;
+                case 'list': {
+                    const tag = block.ordered ? 'ol' : 'ul';
+
+                    return `<${tag}>${block.items.map(item => `<li>${item}</li>`).join('')}</${tag}>`;
+                }
+                case 'image':
+                    return image_html(block);
+                case 'quote':
+                    return `<blockquote>${escape(block.text)}</blockquote>`;
+                case 'gallery':
+                    return block.images.map(image_html).join('');
+                case 'embed':
+                    return `<iframe class="ql-video" src="${escape(block.url)}"></iframe>`;
+                default:
+                    return '';
+            }
+        })
+        .join('');
+};
@@ -0,0 +1,135 @@
+export const read_cookie = (cookie_name) => {
+    return document.cookie.split('; ').find(row => row.startsWith(cookie_name))?.split('=')?.[1]
+}
+
+/**
+ * Visitors asking not to be tracked are not asked for their consent
+ * @returns {boolean}
+ */
+export const do_not_track = () => navigator.doNotTrack === '1' || navigator.globalPrivacyControl === true;
+
+/**
+ * Record an interaction of the visitor with the current view. The request
+ * outlives the page, so outbound links can be tracked too
+ * @param {string} name Snake case name, e.g. `contact_form_sent`
+ * @param {Object} [properties]
+ */
+export const track_event = (name, properties) => {
+    if (do_not_track()) return;
+
+    const body = { name };
+    const token = localStorage.getItem('VID');
+    const sid = read_cookie('sid');
+
+    if (properties && Object.keys(properties).length > 0) {
+        body.properties = properties;
+    }
+    if (token) {
+        body.token = token;
+    }
+    if (sid && read_cookie('consent') === 'granted') {
+        body.sid = sid;
+    }
+
+    fetch('/metrics/event', {
+        method: 'POST',
+        keepalive: true,
+        headers: {
+            'Content-Type': 'application/json'
+        },
+        body: JSON.stringify(body)
+    })
+        .catch(() => {});
+}
+
+/**
+ * Properties of an element with a `data-track` attribute: its `data-track-*`
+ * attributes, e.g. `data-track-id="1"` gives `{ id: '1' }`
+ * @param {HTMLElement} element
+ * @returns {Object}
+ */
+const get_properties = element => {
+    const properties = {};
+
+    for (const [key, value] of Object.entries(element.dataset)) {
+        if (key.startsWith('track') && key.length > 5) {
+            properties[key[5].toLowerCase() + key.slice(6)] = value;
+        }
+    }
+
+    return properties;
+}
+
+/**
+ * Track the clicks on the elements with a `data-track` attribute and on the
+ * links to other websites, as `outbound_link_clicked`
+ */
+export const track_clicks = () => {
+    document.addEventListener('click', e => {
+        const element = e.target.closest?.('[data-track], a[href]');
+
+        if (!element) return;
+
+        const properties = get_properties(element);
+        const is_outbound = element instanceof HTMLAnchorElement
+            && element.host !== location.host
+            && element.protocol.startsWith('http');
+
+        if (is_outbound) {
+            properties.url = element.href;
+        }
+
+        if (element.dataset.track) {
+            track_event(element.dataset.track, properties);
+        } else if (is_outbound) {
+            track_event('outbound_link_clicked', properties);
+        }
+    }, true);
+
+    // Vimeo players post their events once asked for them
+    window.addEventListener('message', e => {
+        if (e.origin !== 'https://player.vimeo.com') return;
+
+        let data = e.data;
+        if (typeof data === 'string') {
+            try {
+                data = JSON.parse(data);
+            } catch (_) {
+                return;
+            }
+        }
+
+        if (data?.event !== 'play') return;
+
+        const iframe = [...document.querySelectorAll('iframe')]
+            .find(iframe => iframe.contentWindow === e.source);
+
+        if (iframe && !iframe.dataset.played) {
+            iframe.dataset.played = 'true';
+            track_event('video_played', { url: iframe.src.split('?')[0] });
+        }
+    });
+}
+
+/**
+ * Track the first play of the videos and the Vimeo players of the page
+ */
+export const track_videos = () => {
+    document
+        .querySelectorAll('video')
+        .forEach(video => video.addEventListener('play', () => {
+            track_event('video_played', { url: video.currentSrc });
+        }, { once: true }));
+
+    document
+        .querySelectorAll('iframe[src*="player.vimeo.com"]')
+        .forEach(iframe => {
+            const listen = () => iframe.contentWindow?.postMessage(
+                JSON.stringify({ method: 'addEventListener', value: 'play' }),
+                'https://player.vimeo.com'
+            );
+
+            listen();
+            iframe.addEventListener('load', listen);
+        });
+}
@@ -0,0 +1,29 @@
+const encoder = new TextEncoder();
+
+const leading_zero_bits = bytes => {
+    let bits = 0;
+
+    for (const byte of bytes) {
+        if (byte !== 0) {
+            return bits + Math.clz32(byte) - 24;
+        }
+
+        bits += 8;
+    }
+
+    return bits;
+}
+
+/**
+ * Find the proof of work of a contact token: the first number whose SHA-256
+ * of `${token}:${number}` starts with `difficulty` zero bits
+ */
+export default async (token, difficulty) => {
+    for (let proof = 0; ; proof++) {
+        const hash = await crypto.subtle.digest('SHA-256', encoder.encode(`${token}:${proof}`));
+
+        if (leading_zero_bits(new Uint8Array(hash)) >= difficulty) {
+            return proof;
+        }
+    }
+}
@@ -1,14 +1,64 @@
 import Swal from "sweetalert2";
 
-export default () => Swal.fire({
+const EXTENSIONS = {
+    'image/png': 'PNG',
+    'image/jpeg': 'JPEG',
+    'image/webp': 'WebP',
+    'image/svg+xml': 'SVG',
+    'video/mp4': 'MP4',
+    'video/webm': 'WebM'
+};
+
+const format_size = bytes => bytes >= 1000000
+    ? `${Math.round(bytes / 100000) / 10} Mo`
+    : `${Math.round(bytes / 1000)} Ko`;
+
+const generic_error = () => Swal.fire({
     title: 'Une erreur est survenue',
     text: 'Si le problème persiste veuillez contacter la personne en charge de la maintenance de votre site-web.',
     icon: 'error',
     footer: "<a href=\"https://greenassembly.fr/contact\" target=\"_blank\">Contacter l'agence GreenAssembly</a>"
 });
 
+/**
+ * Display the reason of a rejected upload (oversized file, unsupported format,
+ * invalid SVG), or a generic error for any other failure
+ */
+export default (response = null) => {
+    if (!(response instanceof Response) || ![400, 413, 415].includes(response.status)) {
+        return generic_error();
+    }
+
+    return response.json()
+        .then(({ error, limit, accepted }) => {
+            switch (error) {
+                case 'payload_too_large':
+                    return Swal.fire({
+                        title: 'Fichier trop volumineux',
+                        text: `La taille maximale autorisée est de ${format_size(limit)}.`,
+                        icon: 'warning'
+                    });
+                case 'unsupported_media_type':
+                    return Swal.fire({
+                        title: 'Format de fichier non supporté',
+                        text: `Formats acceptés : ${accepted.map(type => EXTENSIONS[type] || type).join(', ')}.`,
+                        icon: 'warning'
+                    });
+                case 'invalid_svg':
+                    return Swal.fire({
+                        title: 'Fichier SVG invalide',
+                        text: "Le fichier n'a pas pu être lu, veuillez l'exporter à nouveau depuis votre logiciel.",
+                        icon: 'warning'
+                    });
+                default:
+                    return generic_error();
+            }
+        })
+        .catch(generic_error);
+}
+
 export const data_removed = (name) => Swal.fire({
     title: "L'élément n'existe plus",
     text: `${name} a déjà été supprimé`,
     icon: 'warning'
-})
\ No newline at end of file
+})
@@ -32,4 +32,11 @@ INSERT INTO blog_articles (category_id, cover_id, title, uri, is_published, is_s
 (2, 1, 'Les aventures de lulu partie 3', 'les-aventures-de-lulu-partie-4', true, false);
 
 INSERT INTO settings (background_color, title_color, text_color) VALUES
-('#ffffff', '#1C04FC', '#040404');
\ No newline at end of file
+('#ffffff', '#1C04FC', '#040404');
+
+INSERT INTO contact_auto_reply (enabled, subject, body) VALUES
+(true, 'Votre message a bien été reçu - Ludivine Farat', 'Bonjour {prénom},
+
+Merci pour votre message, il a bien été reçu. Je vous répondrai dans les plus brefs délais.
+
+Ludivine Farat');
\ No newline at end of file
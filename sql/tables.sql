@@ -31,7 +31,10 @@ DROP TABLE IF EXISTS files CASCADE;
 CREATE TABLE files (
     id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
     name VARCHAR(120),
-    path VARCHAR(255) NOT NULL
+    path VARCHAR(255) NOT NULL,
+    width INT,
+    height INT,
+    placeholder TEXT -- base64 WebP data URI used as blurred preview
 );
 
 -- TODO : implement a trigger when delete to recalculate order
@@ -41,9 +44,19 @@ CREATE TABLE project_assets (
     project_id SMALLINT NOT NULL
         REFERENCES projects (id)
         ON DELETE CASCADE,
-    file_id INT NOT NULL
+    kind VARCHAR(5) NOT NULL DEFAULT 'image'
+        CHECK (kind IN ('image', 'video', 'embed')),
+    -- NULL for embedded videos
+    file_id INT
         REFERENCES files (id),
-    "order" SMALLINT NOT NULL
+    -- Image displayed before a video is played
+    poster_id INT
+        REFERENCES files (id),
+    embed_provider VARCHAR(10)
+        CHECK (embed_provider IN ('vimeo', 'youtube')),
+    embed_id VARCHAR(60),
+    "order" SMALLINT NOT NULL,
+    CHECK ((kind = 'embed') = (file_id IS NULL AND embed_id IS NOT NULL))
     -- UNIQUE (project_id, "order")
 );
 
@@ -59,29 +72,23 @@ DROP TABLE IF EXISTS metric_sessions CASCADE;
 CREATE TABLE metric_sessions (
     id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
     ip VARCHAR(120),
-    expiration_date TIMESTAMP WITH TIME ZONE DEFAULT NOW() + interval '30 minutes' NOT NULL
+    start_date TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    -- Pushed back on every activity of the visitor
+    expiration_date TIMESTAMP WITH TIME ZONE DEFAULT NOW() + interval '30 minutes' NOT NULL,
+    -- UTM parameters of the first view of the session having some
+    utm_source VARCHAR(100),
+    utm_medium VARCHAR(100),
+    utm_campaign VARCHAR(100),
+    utm_term VARCHAR(100),
+    utm_content VARCHAR(100)
 );
 
-DROP TABLE IF EXISTS metrics CASCADE;
-CREATE TABLE metrics (
-    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
-    session_id uuid REFERENCES metric_sessions (id) ON DELETE SET NULL,
-    page_id SMALLINT
-        REFERENCES pages (id)
-        ON DELETE CASCADE,
-    project_id SMALLINT
-        REFERENCES projects (id)
-        ON DELETE SET NULL,
-    article_id SMALLINT
-        REFERENCES blog_articles (id)
-        ON DELETE SET NULL,
-    ip VARCHAR(120),
-    browser VARCHAR(20),
-    os VARCHAR(20),
-    device_type VARCHAR(20),
-    referer VARCHAR(255),
-    "date" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
-    end_date TIMESTAMP WITH TIME ZONE
+-- Secret of the day used to hash the IP addresses, older salts are deleted so
+-- the hashes of the previous days can not be linked to an IP anymore
+DROP TABLE IF EXISTS metric_salts CASCADE;
+CREATE TABLE metric_salts (
+    "day" DATE PRIMARY KEY DEFAULT CURRENT_DATE,
+    salt BYTEA NOT NULL
 );
 
 DROP TABLE IF EXISTS page_chunks CASCADE;
@@ -115,13 +122,21 @@ CREATE TABLE "user" (
 
 DROP TABLE IF EXISTS attempts CASCADE;
 CREATE TABLE attempts (
-    id SMALLINT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
     email VARCHAR(250) NOT NULL,
     ip VARCHAR(60) NOT NULL,
-    is_login BOOLEAN NOT NULL,
+    kind VARCHAR(20) NOT NULL CHECK (kind IN ('login', 'lost_password', 'contact', 'subscribe', 'comment')),
     "date" tIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
 );
 
+-- Nonces of the tokens of the spam checks already used, kept until the tokens
+-- expire so a form can not be sent again with the same token
+DROP TABLE IF EXISTS spam_tokens CASCADE;
+CREATE TABLE spam_tokens (
+    nonce VARCHAR(20) PRIMARY KEY,
+    expiration_date TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
 DROP TABLE IF EXISTS blog_categories CASCADE;
 CREATE TABLE blog_categories (
     id SMALLINT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
@@ -144,12 +159,23 @@ CREATE TABLE blog_articles (
         ON DELETE SET NULL,
     title VARCHAR(255) NOT NULL,
     description VARCHAR(320),
-    content TEXT NOT NULL,
+    -- Source displayed, see `services::blog::articles::Format`
+    format VARCHAR(10) NOT NULL DEFAULT 'blocks'
+        CHECK (format IN ('blocks', 'markdown')),
+    -- See `services::blog::articles::blocks`
+    blocks JSONB NOT NULL DEFAULT '[]',
+    markdown TEXT,
+    -- HTML of the articles written before the blocks, converted by the
+    -- `migrate-articles` command
+    content TEXT,
     uri VARCHAR(260),
     "date" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
     modified_date TIMESTAMP WITH TIME ZONE,
     is_published BOOLEAN DEFAULT FALSE,
-    is_seo BOOLEAN DEFAULT FALSE
+    is_seo BOOLEAN DEFAULT FALSE,
+    -- Set when the subscribers are notified of the publication, only once
+    notified_date TIMESTAMP WITH TIME ZONE,
+    comments_closed BOOLEAN NOT NULL DEFAULT FALSE
 );
 
 DROP TABLE IF EXISTS blog_article_images CASCADE;
@@ -162,9 +188,270 @@ CREATE TABLE blog_article_images (
         REFERENCES files (id)
 );
 
+-- Comments of the articles, only displayed once approved. A reply answers a
+-- top level comment, the threads are one level deep
+DROP TABLE IF EXISTS blog_comments CASCADE;
+CREATE TABLE blog_comments (
+    id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    article_id SMALLINT NOT NULL
+        REFERENCES blog_articles (id)
+        ON DELETE CASCADE,
+    parent_id INT
+        REFERENCES blog_comments (id)
+        ON DELETE CASCADE,
+    author VARCHAR(120) NOT NULL,
+    email VARCHAR(320),
+    content TEXT NOT NULL,
+    status VARCHAR(10) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'approved', 'rejected', 'spam')),
+    -- Written from the back office, as the author of the blog
+    is_author BOOLEAN NOT NULL DEFAULT FALSE,
+    "date" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+DROP TABLE IF EXISTS metrics CASCADE;
+CREATE TABLE metrics (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    session_id uuid REFERENCES metric_sessions (id) ON DELETE SET NULL,
+    page_id SMALLINT
+        REFERENCES pages (id)
+        ON DELETE CASCADE,
+    project_id SMALLINT
+        REFERENCES projects (id)
+        ON DELETE SET NULL,
+    article_id SMALLINT
+        REFERENCES blog_articles (id)
+        ON DELETE SET NULL,
+    category_id SMALLINT
+        REFERENCES blog_categories (id)
+        ON DELETE SET NULL,
+    ip VARCHAR(120),
+    browser VARCHAR(20),
+    os VARCHAR(20),
+    device_type VARCHAR(20),
+    -- Host of the referrer and its kind
+    referrer_domain VARCHAR(255),
+    referrer_kind VARCHAR(8) NOT NULL DEFAULT 'direct'
+        CHECK (referrer_kind IN ('direct', 'internal', 'search', 'social', 'website')),
+    -- UTM parameters of the URL of a landing view
+    utm_source VARCHAR(100),
+    utm_medium VARCHAR(100),
+    utm_campaign VARCHAR(100),
+    utm_term VARCHAR(100),
+    utm_content VARCHAR(100),
+    -- Crawler according to the user agent or to the behavior of the visitor
+    is_bot BOOLEAN NOT NULL DEFAULT false,
+    "date" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    end_date TIMESTAMP WITH TIME ZONE,
+    CHECK (num_nonnulls(page_id, project_id, article_id, category_id) <= 1)
+);
+
+-- Interactions of the visitors (contact form sent, video played, outbound link
+-- clicked...), linked to the view they happened on
+DROP TABLE IF EXISTS metric_events CASCADE;
+CREATE TABLE metric_events (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    metric_id uuid REFERENCES metrics (id) ON DELETE SET NULL,
+    session_id uuid REFERENCES metric_sessions (id) ON DELETE SET NULL,
+    ip VARCHAR(120),
+    "name" VARCHAR(60) NOT NULL,
+    properties JSONB,
+    is_bot BOOLEAN NOT NULL DEFAULT false,
+    "date" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+CREATE INDEX metric_events_name_date ON metric_events ("name", "date");
+
+-- Rollups of the metrics by hour and by day, recomputed from the raw metrics by
+-- a background job as long as they exist, then kept once they are purged
+DROP TABLE IF EXISTS metric_rollups CASCADE;
+CREATE TABLE metric_rollups (
+    granularity VARCHAR(4) NOT NULL CHECK (granularity IN ('hour', 'day')),
+    period_start TIMESTAMP WITH TIME ZONE NOT NULL,
+    is_bot BOOLEAN NOT NULL,
+    views INTEGER NOT NULL,
+    visitors INTEGER NOT NULL,
+    sessions INTEGER NOT NULL,
+    -- Views belonging to a session
+    session_views INTEGER NOT NULL,
+    -- Sessions with a single view
+    bounces INTEGER NOT NULL,
+    -- Sum (in seconds) and number of the durations of the closed views
+    duration_sum DOUBLE PRECISION NOT NULL,
+    durations INTEGER NOT NULL,
+    PRIMARY KEY (granularity, period_start, is_bot)
+);
+
+DROP TABLE IF EXISTS metric_target_rollups CASCADE;
+CREATE TABLE metric_target_rollups (
+    granularity VARCHAR(4) NOT NULL CHECK (granularity IN ('hour', 'day')),
+    period_start TIMESTAMP WITH TIME ZONE NOT NULL,
+    is_bot BOOLEAN NOT NULL,
+    page_id SMALLINT
+        REFERENCES pages (id)
+        ON DELETE CASCADE,
+    project_id SMALLINT
+        REFERENCES projects (id)
+        ON DELETE CASCADE,
+    article_id SMALLINT
+        REFERENCES blog_articles (id)
+        ON DELETE CASCADE,
+    category_id SMALLINT
+        REFERENCES blog_categories (id)
+        ON DELETE CASCADE,
+    views INTEGER NOT NULL,
+    visitors INTEGER NOT NULL,
+    duration_sum DOUBLE PRECISION NOT NULL,
+    durations INTEGER NOT NULL
+);
+CREATE INDEX metric_target_rollups_period ON metric_target_rollups (granularity, period_start);
+
+-- Contents the sessions started or ended on
+DROP TABLE IF EXISTS metric_entry_rollups CASCADE;
+CREATE TABLE metric_entry_rollups (
+    period_start TIMESTAMP WITH TIME ZONE NOT NULL,
+    is_bot BOOLEAN NOT NULL,
+    is_exit BOOLEAN NOT NULL,
+    page_id SMALLINT
+        REFERENCES pages (id)
+        ON DELETE CASCADE,
+    project_id SMALLINT
+        REFERENCES projects (id)
+        ON DELETE CASCADE,
+    article_id SMALLINT
+        REFERENCES blog_articles (id)
+        ON DELETE CASCADE,
+    category_id SMALLINT
+        REFERENCES blog_categories (id)
+        ON DELETE CASCADE,
+    sessions INTEGER NOT NULL
+);
+CREATE INDEX metric_entry_rollups_period ON metric_entry_rollups (period_start);
+
+DROP TABLE IF EXISTS metric_referrer_rollups CASCADE;
+CREATE TABLE metric_referrer_rollups (
+    period_start TIMESTAMP WITH TIME ZONE NOT NULL,
+    is_bot BOOLEAN NOT NULL,
+    kind VARCHAR(8) NOT NULL,
+    "domain" VARCHAR(255),
+    views INTEGER NOT NULL
+);
+CREATE INDEX metric_referrer_rollups_period ON metric_referrer_rollups (period_start);
+
+-- Views attributed to a campaign by their own UTM parameters or by the ones
+-- of their session
+DROP TABLE IF EXISTS metric_campaign_rollups CASCADE;
+CREATE TABLE metric_campaign_rollups (
+    period_start TIMESTAMP WITH TIME ZONE NOT NULL,
+    is_bot BOOLEAN NOT NULL,
+    utm_source VARCHAR(100),
+    utm_medium VARCHAR(100),
+    utm_campaign VARCHAR(100),
+    -- Views of the landing URL with the UTM parameters
+    landings INTEGER NOT NULL,
+    views INTEGER NOT NULL,
+    visitors INTEGER NOT NULL,
+    sessions INTEGER NOT NULL,
+    project_views INTEGER NOT NULL
+);
+CREATE INDEX metric_campaign_rollups_period ON metric_campaign_rollups (period_start);
+
+DROP TABLE IF EXISTS metric_device_rollups CASCADE;
+CREATE TABLE metric_device_rollups (
+    period_start TIMESTAMP WITH TIME ZONE NOT NULL,
+    is_bot BOOLEAN NOT NULL,
+    browser VARCHAR(20),
+    os VARCHAR(20),
+    device_type VARCHAR(20),
+    views INTEGER NOT NULL
+);
+CREATE INDEX metric_device_rollups_period ON metric_device_rollups (period_start);
+
+DROP TABLE IF EXISTS metric_event_rollups CASCADE;
+CREATE TABLE metric_event_rollups (
+    period_start TIMESTAMP WITH TIME ZONE NOT NULL,
+    is_bot BOOLEAN NOT NULL,
+    "name" VARCHAR(60) NOT NULL,
+    events INTEGER NOT NULL,
+    visitors INTEGER NOT NULL
+);
+CREATE INDEX metric_event_rollups_period ON metric_event_rollups (period_start);
+
 DROP TABLE IF EXISTS settings CASCADE;
 CREATE TABLE settings (
     background_color CHAR(7) NOT NULL,
     title_color CHAR(7) NOT NULL,
-    text_color CHAR(7) NOT NULL
-);
\ No newline at end of file
+    text_color CHAR(7) NOT NULL,
+    favicon_format VARCHAR(3) NOT NULL DEFAULT 'svg' CHECK (favicon_format IN ('svg', 'png')),
+    -- Closes the comments of every article when false
+    comments_enabled BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+-- Messages sent with the contact form, kept even when the notification email
+-- could not be sent
+DROP TABLE IF EXISTS contact_messages CASCADE;
+CREATE TABLE contact_messages (
+    id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    firstname VARCHAR(120) NOT NULL,
+    lastname VARCHAR(120) NOT NULL,
+    phone_number VARCHAR(20),
+    email VARCHAR(320) NOT NULL,
+    content TEXT NOT NULL,
+    status VARCHAR(8) NOT NULL DEFAULT 'new'
+        CHECK (status IN ('new', 'read', 'replied', 'archived', 'spam')),
+    "date" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+CREATE INDEX contact_messages_status ON contact_messages (status, "date");
+
+-- Answers sent from the back office
+DROP TABLE IF EXISTS contact_replies CASCADE;
+CREATE TABLE contact_replies (
+    id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    message_id INT NOT NULL
+        REFERENCES contact_messages (id)
+        ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    "date" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Acknowledgement sent to the visitors who use the contact form, editable
+-- from the back office
+DROP TABLE IF EXISTS contact_auto_reply CASCADE;
+CREATE TABLE contact_auto_reply (
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    subject VARCHAR(255) NOT NULL,
+    body TEXT NOT NULL
+);
+
+-- Visitors subscribed to the new articles of the blog, confirmed with the link
+-- of an email (double opt-in). The emails are saved in lower case
+DROP TABLE IF EXISTS subscribers CASCADE;
+CREATE TABLE subscribers (
+    id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    email VARCHAR(320) NOT NULL UNIQUE,
+    status VARCHAR(12) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'confirmed', 'unsubscribed')),
+    confirmed_date TIMESTAMP WITH TIME ZONE,
+    unsubscribed_date TIMESTAMP WITH TIME ZONE,
+    "date" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- Emails waiting to be sent by the background worker, retried with an
+-- exponential backoff until sent or given up
+DROP TABLE IF EXISTS email_outbox CASCADE;
+CREATE TABLE email_outbox (
+    id INT PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    recipient VARCHAR(320) NOT NULL,
+    sender VARCHAR(320) NOT NULL,
+    reply_to VARCHAR(320),
+    subject VARCHAR(255) NOT NULL,
+    text_body TEXT,
+    html_body TEXT,
+    status VARCHAR(7) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'sent', 'failed')),
+    attempts SMALLINT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_date TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    sent_date TIMESTAMP WITH TIME ZONE,
+    "date" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+CREATE INDEX email_outbox_pending ON email_outbox (status, next_attempt_date);
@@ -216,6 +216,58 @@ async fn blog(session: Identity, pool: web::Data<PgPool>) -> Result<HttpResponse
     .into_response()
 }
 
+#[get("/statistiques")]
+pub async fn statistics(session: Identity) -> Result<HttpResponse, Error> {
+    if session.identity().is_none() {
+        return Ok(HttpResponse::Found().header("location", "/admin").finish());
+    }
+
+    #[derive(Template)]
+    #[template(path = "pages/admin/statistics.html")]
+    struct Statistics;
+
+    Statistics.into_response()
+}
+
+#[get("/messages")]
+pub async fn messages(session: Identity) -> Result<HttpResponse, Error> {
+    if session.identity().is_none() {
+        return Ok(HttpResponse::Found().header("location", "/admin").finish());
+    }
+
+    #[derive(Template)]
+    #[template(path = "pages/admin/messages.html")]
+    struct Messages;
+
+    Messages.into_response()
+}
+
+#[get("/abonnes")]
+pub async fn subscribers(session: Identity) -> Result<HttpResponse, Error> {
+    if session.identity().is_none() {
+        return Ok(HttpResponse::Found().header("location", "/admin").finish());
+    }
+
+    #[derive(Template)]
+    #[template(path = "pages/admin/subscribers.html")]
+    struct Subscribers;
+
+    Subscribers.into_response()
+}
+
+#[get("/commentaires")]
+pub async fn comments(session: Identity) -> Result<HttpResponse, Error> {
+    if session.identity().is_none() {
+        return Ok(HttpResponse::Found().header("location", "/admin").finish());
+    }
+
+    #[derive(Template)]
+    #[template(path = "pages/admin/comments.html")]
+    struct Comments;
+
+    Comments.into_response()
+}
+
 #[get("/parametres")]
 pub async fn settings(session: Identity, pool: web::Data<PgPool>) -> Result<HttpResponse, Error> {
     if session.identity().is_none() {
@@ -229,7 +281,9 @@ pub async fn settings(session: Identity, pool: web::Data<PgPool>) -> Result<Http
             struct Setting {
                 background_color: String,
                 title_color: String,
-                text_color: String
+                text_color: String,
+                favicon_format: String,
+                comments_enabled: bool
                 // categories: Vec<services::projects::Category>,
                 // projects: Vec<services::projects::Project>,
             }
@@ -237,7 +291,9 @@ pub async fn settings(session: Identity, pool: web::Data<PgPool>) -> Result<Http
             return Setting {
                 background_color: settings.background_color,
                 title_color: settings.title_color,
-                text_color: settings.text_color
+                text_color: settings.text_color,
+                favicon_format: settings.favicon_format,
+                comments_enabled: settings.comments_enabled
             }
             .into_response();
         }
@@ -1,13 +1,34 @@
-use crate::{services, utils::image::Uploader, utils::patch::Patch};
+use crate::{
+    services::{
+        self,
+        blog::articles::{
+            blocks::{self, Block},
+            markdown, Format,
+        },
+    },
+    utils::image::Uploader,
+    utils::patch::Patch,
+    utils::storage::Storage,
+    utils::upload::{self, File, Multipart},
+};
 use actix_identity::Identity;
-use actix_web::{delete, get, patch, post, web, HttpResponse};
+use actix_web::{delete, get, patch, post, web, HttpResponse, ResponseError};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use slugmin::slugify;
-use sqlx::{FromRow, PgPool};
+use sqlx::{
+    types::{Json, Uuid},
+    FromRow, PgPool, Postgres, Transaction,
+};
 use std::collections::HashMap;
 use std::ops::DerefMut;
 
+/// Maximum size (in bytes) of a picture inserted in the content of an article
+const PICTURE_LIMIT: usize = 2_000_000;
+
+/// Accepted types of the pictures inserted in the content of an article
+const PICTURES: &[&str] = &["image/png", "image/jpeg"];
+
 #[get("/categories/{id}")]
 async fn get_category(
     pool: web::Data<PgPool>,
@@ -213,9 +234,12 @@ async fn get_article(
         cover: String,
         title: String,
         description: Option<String>,
-        content: String,
+        format: String,
+        blocks: Json<Vec<Block>>,
+        markdown: Option<String>,
         is_published: Option<bool>,
         is_seo: Option<bool>,
+        comments_closed: bool,
     }
 
     #[derive(Serialize)]
@@ -227,7 +251,15 @@ async fn get_article(
     let (article, images) = futures::join!(
         services::blog::articles::get::<Article>(
             &pool,
-            r#"f.path AS "cover", title, description, content, is_published, is_seo"#,
+            r#"f.path AS "cover",
+            title,
+            description,
+            format,
+            blocks,
+            markdown,
+            is_published,
+            is_seo,
+            comments_closed"#,
             id
         ),
         services::blog::articles::images::get_all(&pool, id)
@@ -239,9 +271,12 @@ async fn get_article(
             "cover": article.cover,
             "title": article.title,
             "description": article.description,
-            "content": article.content,
+            "format": article.format,
+            "blocks": article.blocks.0,
+            "markdown": article.markdown,
             "is_published": article.is_published,
             "is_seo": article.is_seo,
+            "comments_closed": article.comments_closed,
             "images": serde_json::json!(
                 images
                     .iter()
@@ -259,28 +294,113 @@ async fn get_article(
 
 #[derive(Deserialize)]
 pub struct NewArticleForm {
-    cover: actix_extract_multipart::File,
+    cover: File,
     category_id: Option<i16>,
     title: String,
     description: Option<String>,
     is_published: Option<bool>,
     is_seo: Option<bool>,
-    content: String,
-    pictures: Option<Vec<actix_extract_multipart::File>>,
+    /// The blocks by default
+    format: Option<Format>,
+    /// JSON of the blocks, the pictures being referenced by their index
+    blocks: Option<String>,
+    /// Source in Markdown, the pictures being referenced as `upload:{index}`
+    markdown: Option<String>,
+    pictures: Option<Vec<File>>,
+}
+
+/// Blocks sent as JSON, validated
+fn parse_blocks(blocks: &str) -> Option<Vec<Block>> {
+    serde_json::from_str(blocks)
+        .ok()
+        .and_then(|blocks| blocks::validate(blocks).ok())
+}
+
+/// Markdown source trimmed, `None` when empty or too long
+fn parse_markdown(source: &str) -> Option<String> {
+    let source = source.trim();
+
+    if source.is_empty() || source.len() > markdown::MAX_LENGTH {
+        return None;
+    }
+
+    Some(source.to_string())
+}
+
+/// Save the pictures inserted in the content of an article, returning the ids
+/// of the images in the same order
+async fn upload_pictures(
+    transaction: &mut Transaction<'_, Postgres>,
+    uploader: &mut Uploader,
+    id: i16,
+    pictures: &[File],
+) -> Result<Vec<Uuid>, HttpResponse> {
+    let mut uploads = vec![];
+
+    for (i, image) in pictures.iter().enumerate() {
+        if let Err(e) = image
+            .accept(PICTURES)
+            .and_then(|_| image.limit(PICTURE_LIMIT))
+        {
+            return Err(e.error_response());
+        }
+
+        let image = match image.image() {
+            Ok(image) => image,
+            Err(_) => return Err(HttpResponse::InternalServerError().finish()),
+        };
+        let name = format!("{}_{}_{}", id, i, chrono::Utc::now().timestamp());
+
+        let metadata = match uploader
+            .handle(&image, &name, Some((500, 500)), Some((700, 700)), true)
+            .await
+        {
+            Ok(metadata) => metadata,
+            Err(_) => return Err(HttpResponse::InternalServerError().finish()),
+        };
+
+        let file_id = match services::files::insert(
+            transaction.deref_mut(),
+            None,
+            &format!(
+                "{}.{}",
+                name,
+                if image.color().has_alpha() {
+                    "png"
+                } else {
+                    "jpg"
+                }
+            ),
+            Some(&metadata),
+        )
+        .await
+        {
+            Ok(file_id) => file_id,
+            Err(_) => return Err(HttpResponse::InternalServerError().finish()),
+        };
+
+        match services::blog::articles::images::insert(transaction.deref_mut(), id, file_id).await {
+            Ok(image_id) => uploads.push(image_id),
+            Err(_) => return Err(HttpResponse::InternalServerError().finish()),
+        }
+    }
+
+    Ok(uploads)
 }
 
 #[post("/articles")]
 async fn insert_article(
     pool: web::Data<PgPool>,
+    storage: web::Data<dyn Storage>,
     session: Identity,
-    mut form: actix_extract_multipart::Multipart<NewArticleForm>,
+    mut form: Multipart<NewArticleForm>,
 ) -> HttpResponse {
     if session.identity().is_none() {
         return HttpResponse::Unauthorized().finish();
     }
 
     form.title = form.title.trim().to_string();
-    let mut uploader = Uploader::new();
+    let mut uploader = Uploader::new(storage.clone().into_inner());
 
     if form.title.is_empty() || form.title.len() > 255 {
         return HttpResponse::BadRequest().finish();
@@ -300,17 +420,35 @@ async fn insert_article(
         }
     }
 
+    let format = form.format.unwrap_or(Format::Blocks);
+    // Only the content of the format is saved
+    let (mut blocks, source) = match format {
+        Format::Blocks => match form.blocks.as_deref().and_then(parse_blocks) {
+            Some(blocks) => (blocks, None),
+            None => return HttpResponse::BadRequest().finish(),
+        },
+        Format::Markdown => match form.markdown.as_deref().and_then(parse_markdown) {
+            Some(source) => (vec![], Some(source)),
+            None => return HttpResponse::BadRequest().finish(),
+        },
+    };
+
+    if let Err(e) = form.cover.accept(upload::IMAGES) {
+        return e.error_response();
+    }
+
     let mut transaction = pool.begin().await.unwrap();
-    let cover_id = match image::load_from_memory(form.cover.data()) {
+    let cover_id = match form.cover.image() {
         Ok(image) => {
             let name = format!("cover_{}", chrono::Utc::now().timestamp());
 
-            if uploader
+            let metadata = match uploader
                 .handle(&image, &name, Some((500, 250)), Some((700, 350)), true)
-                .is_err()
+                .await
             {
-                return HttpResponse::InternalServerError().finish();
-            }
+                Ok(metadata) => metadata,
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            };
 
             let file_id = match services::files::insert(
                 transaction.deref_mut(),
@@ -324,6 +462,7 @@ async fn insert_article(
                         "jpg"
                     }
                 ),
+                Some(&metadata),
             )
             .await
             {
@@ -336,91 +475,29 @@ async fn insert_article(
         Err(_) => return HttpResponse::InternalServerError().finish(),
     };
 
-    let mut allowed_tags = std::collections::HashSet::<&str>::new();
-    allowed_tags.insert("b");
-    allowed_tags.insert("ul");
-    allowed_tags.insert("ol");
-    allowed_tags.insert("li");
-    allowed_tags.insert("a");
-    allowed_tags.insert("p");
-    allowed_tags.insert("br");
-
-    form.content = ammonia::Builder::default()
-        .tags(allowed_tags)
-        .clean(form.content.trim())
-        .to_string();
-
     if let Ok(id) = services::blog::articles::insert(
         transaction.deref_mut(),
         form.category_id,
         cover_id,
         &form.title,
         form.description.as_deref(),
-        &form.content,
         form.is_published,
         form.is_seo,
     )
     .await
     {
-        let mut content = form.content.clone();
-
-        if let Some(pictures) = &form.pictures {
-            for (i, image) in pictures.iter().enumerate() {
-                if !&["image/png", "image/jpeg"].contains(&image.file_type().as_str())
-                    || image.len() > 2000000
-                {
-                    return HttpResponse::BadRequest().finish();
+        let uploads = match &form.pictures {
+            Some(pictures) => {
+                match upload_pictures(&mut transaction, &mut uploader, id, pictures).await {
+                    Ok(uploads) => uploads,
+                    Err(res) => return res,
                 }
-
-                match image::load_from_memory(image.data()) {
-                    Ok(image) => {
-                        let name = format!("{}_{}_{}", id, i, chrono::Utc::now().timestamp());
-
-                        if uploader
-                            .handle(&image, &name, Some((500, 500)), Some((700, 700)), true)
-                            .is_err()
-                        {
-                            return HttpResponse::InternalServerError().finish();
-                        }
-
-                        if let Ok(file_id) = services::files::insert(
-                            transaction.deref_mut(),
-                            None,
-                            &format!(
-                                "{}.{}",
-                                name,
-                                if image.color().has_alpha() {
-                                    "png"
-                                } else {
-                                    "jpg"
-                                }
-                            ),
-                        )
-                        .await
-                        {
-                            match services::blog::articles::images::insert(
-                                transaction.deref_mut(),
-                                id,
-                                file_id,
-                            )
-                            .await
-                            {
-                                Ok(id) => {
-                                    content = content.replacen(
-                                        &format!("[[{}]]", i),
-                                        &format!("[[{}]]", id),
-                                        1,
-                                    );
-                                }
-                                Err(_) => return HttpResponse::InternalServerError().finish(),
-                            }
-                        } else {
-                            return HttpResponse::InternalServerError().finish();
-                        };
-                    }
-                    Err(_) => return HttpResponse::InternalServerError().finish(),
-                };
             }
+            None => vec![],
+        };
+
+        if blocks::resolve(&mut blocks, &uploads, &[]).is_err() {
+            return HttpResponse::BadRequest().finish();
         }
 
         let mut fields_to_update = HashMap::new();
@@ -428,11 +505,26 @@ async fn insert_article(
             String::from("uri"),
             Value::String(slugify(&format!("{}-{}", form.title, id))),
         );
-        fields_to_update.insert(String::from("content"), Value::String(content));
+        fields_to_update.insert(
+            String::from("format"),
+            Value::String(format.as_str().to_string()),
+        );
+
+        if let Some(source) = source {
+            match markdown::resolve(&source, &uploads) {
+                Some(source) => {
+                    fields_to_update.insert(String::from("markdown"), Value::String(source));
+                }
+                None => return HttpResponse::BadRequest().finish(),
+            }
+        }
 
         if services::blog::articles::partial_update(transaction.deref_mut(), id, fields_to_update)
             .await
             .is_err()
+            || services::blog::articles::set_blocks(transaction.deref_mut(), id, &blocks)
+                .await
+                .is_err()
         {
             return HttpResponse::InternalServerError().finish();
         }
@@ -441,6 +533,10 @@ async fn insert_article(
 
         uploader.clear();
 
+        if form.is_published == Some(true) {
+            crate::controllers::newsletter::notify_subscribers(&pool, id).await;
+        }
+
         return HttpResponse::Created().json(id);
     }
 
@@ -450,7 +546,7 @@ async fn insert_article(
 #[derive(Deserialize, Serialize)]
 pub struct UpdateArticleForm {
     #[serde(default, skip_serializing)]
-    cover: Patch<actix_extract_multipart::File>,
+    cover: Patch<File>,
     #[serde(default)]
     category_id: Patch<Option<i16>>,
     #[serde(default)]
@@ -458,20 +554,30 @@ pub struct UpdateArticleForm {
     #[serde(default)]
     description: Patch<Option<String>>,
     #[serde(default)]
-    content: Patch<String>,
+    format: Patch<Format>,
+    /// JSON of the blocks, the new pictures being referenced by their index
+    #[serde(default)]
+    blocks: Patch<String>,
+    /// Source in Markdown, the new pictures being referenced as
+    /// `upload:{index}`
+    #[serde(default)]
+    markdown: Patch<String>,
     #[serde(default)]
     is_published: Patch<bool>,
     #[serde(default)]
     is_seo: Patch<bool>,
+    #[serde(default)]
+    comments_closed: Patch<bool>,
     #[serde(default, skip_serializing)]
-    pictures: Patch<Option<Vec<actix_extract_multipart::File>>>,
+    pictures: Patch<Option<Vec<File>>>,
 }
 
 #[patch("/articles/{id}")]
 async fn update_article(
     pool: web::Data<PgPool>,
+    storage: web::Data<dyn Storage>,
     session: Identity,
-    mut form: actix_extract_multipart::Multipart<UpdateArticleForm>,
+    mut form: Multipart<UpdateArticleForm>,
     web::Path(id): web::Path<i16>,
 ) -> HttpResponse {
     if session.identity().is_none() {
@@ -485,12 +591,14 @@ async fn update_article(
     #[derive(FromRow)]
     struct Article {
         cover_id: i32,
+        blocks: Json<Vec<Block>>,
+        markdown: Option<String>,
     }
 
-    let mut uploader = Uploader::new();
+    let mut uploader = Uploader::new(storage.clone().into_inner());
 
-    let Article { cover_id } = if let Ok(article) =
-        services::blog::articles::get::<Article>(&pool, "cover_id", id).await
+    let article = if let Ok(article) =
+        services::blog::articles::get::<Article>(&pool, "cover_id, blocks, markdown", id).await
     {
         article
     } else {
@@ -536,126 +644,114 @@ async fn update_article(
         }
     }
 
-    if let Patch::Value(content) = &form.content {
-        let mut allowed_tags = std::collections::HashSet::<&str>::new();
-        allowed_tags.insert("b");
-        allowed_tags.insert("ul");
-        allowed_tags.insert("ol");
-        allowed_tags.insert("li");
-        allowed_tags.insert("a");
-        allowed_tags.insert("p");
-        allowed_tags.insert("br");
-
-        let images = services::blog::articles::images::get_all(pool.as_ref(), id).await;
-
-        for image in &images {
-            // If content doesnt has id anymore so the image has been deleted
-            if !content.contains(&image.id.to_string()) {
-                services::blog::articles::images::delete(transaction.deref_mut(), image.id).await;
-                let filename = image.path.split('.').collect::<Vec<_>>();
-                let filename = filename.get(0).unwrap();
-
-                files_to_remove.append(
-                    &mut [
-                        format!("./uploads/mobile/{}", image.path),
-                        format!("./uploads/mobile/{}.webp", filename),
-                        format!("./uploads/{}", image.path),
-                        format!("./uploads/{}.webp", filename),
-                    ]
-                    .to_vec(),
-                );
-            }
-        }
-
-        form.content = Patch::Value(
-            ammonia::Builder::default()
-                .tags(allowed_tags)
-                .clean(content.trim())
-                .to_string(),
-        );
+    if let Patch::Null = form.format {
+        return HttpResponse::BadRequest().finish();
     }
 
-    if let Patch::Value(Some(pictures)) = &form.pictures {
-        let mut content = if let Patch::Value(content) = &form.content {
-            content.clone()
-        } else {
-            return HttpResponse::BadRequest().finish();
-        };
+    // The content is either the blocks or the Markdown source
+    let has_content = match (&form.blocks, &form.markdown) {
+        (Patch::Undefined, Patch::Undefined) => false,
+        (Patch::Value(_), Patch::Undefined) | (Patch::Undefined, Patch::Value(_)) => true,
+        _ => return HttpResponse::BadRequest().finish(),
+    };
 
-        for (i, image) in pictures.iter().enumerate() {
-            if !&["image/png", "image/jpeg"].contains(&image.file_type().as_str())
-                || image.len() > 2000000
-            {
-                return HttpResponse::BadRequest().finish();
+    if has_content {
+        let blocks = match &form.blocks {
+            Patch::Value(blocks) => match parse_blocks(blocks) {
+                Some(blocks) => Some(blocks),
+                None => return HttpResponse::BadRequest().finish(),
+            },
+            _ => None,
+        };
+        let source = match &form.markdown {
+            Patch::Value(source) => match parse_markdown(source) {
+                Some(source) => Some(source),
+                None => return HttpResponse::BadRequest().finish(),
+            },
+            _ => None,
+        };
+        let uploads = match &form.pictures {
+            Patch::Value(Some(pictures)) => {
+                match upload_pictures(&mut transaction, &mut uploader, id, pictures).await {
+                    Ok(uploads) => uploads,
+                    Err(res) => return res,
+                }
             }
+            _ => vec![],
+        };
+        let images = services::blog::articles::images::get_all(pool.as_ref(), id).await;
 
-            match image::load_from_memory(image.data()) {
-                Ok(image) => {
-                    let name = format!("{}_{}_{}", id, i, chrono::Utc::now().timestamp());
-
-                    if uploader
-                        .handle(&image, &name, Some((500, 500)), Some((700, 700)), true)
-                        .is_err()
-                    {
-                        return HttpResponse::InternalServerError().finish();
-                    }
+        // Images displayed by the new content, and by the content of the other
+        // format which can still be switched back to
+        let displayed = match (blocks, source) {
+            (Some(mut blocks), _) => {
+                if blocks::resolve(
+                    &mut blocks,
+                    &uploads,
+                    &images.iter().map(|image| image.id).collect::<Vec<_>>(),
+                )
+                .is_err()
+                {
+                    return HttpResponse::BadRequest().finish();
+                }
 
-                    let file_id = if let Ok(id) = services::files::insert(
-                        transaction.deref_mut(),
-                        None,
-                        &format!(
-                            "{}.{}",
-                            name,
-                            if image.color().has_alpha() {
-                                "png"
-                            } else {
-                                "jpg"
-                            }
-                        ),
-                    )
-                    .await
-                    {
-                        id
-                    } else {
-                        return HttpResponse::InternalServerError().finish();
-                    };
-
-                    match services::blog::articles::images::insert(
-                        transaction.deref_mut(),
-                        id,
-                        file_id,
-                    )
+                if services::blog::articles::set_blocks(transaction.deref_mut(), id, &blocks)
                     .await
-                    {
-                        Ok(id) => {
-                            content =
-                                content.replacen(&format!("[[{}]]", i), &format!("[[{}]]", id), 1);
-                        }
-                        Err(_) => return HttpResponse::InternalServerError().finish(),
-                    }
-                }
-                Err(_) => {
+                    .is_err()
+                {
                     return HttpResponse::InternalServerError().finish();
                 }
+
+                let mut displayed = blocks::image_ids(&blocks);
+                displayed.append(&mut markdown::image_ids(
+                    article.markdown.as_deref().unwrap_or_default(),
+                ));
+                displayed
             }
-        }
+            (None, Some(source)) => {
+                let source = match markdown::resolve(&source, &uploads) {
+                    Some(source) => source,
+                    None => return HttpResponse::BadRequest().finish(),
+                };
+
+                let mut displayed = markdown::image_ids(&source);
+                displayed.append(&mut blocks::image_ids(&article.blocks));
+                form.markdown = Patch::Value(source);
+                displayed
+            }
+            (None, None) => vec![],
+        };
 
-        form.content = Patch::Value(content);
+        // The images removed from the content are deleted
+        for image in &images {
+            if !displayed.contains(&image.id) {
+                services::blog::articles::images::delete(transaction.deref_mut(), image.id).await;
+                files_to_remove.append(&mut crate::utils::image::variants(&image.path));
+            }
+        }
+    } else if let Patch::Value(Some(_)) = &form.pictures {
+        // The pictures are only sent with the content displaying them
+        return HttpResponse::BadRequest().finish();
     }
 
     let mut fields_need_update = crate::utils::patch::extract_fields(&*form);
 
     if let Patch::Value(cover) = &form.cover {
-        match image::load_from_memory(cover.data()) {
+        if let Err(e) = cover.accept(upload::IMAGES) {
+            return e.error_response();
+        }
+
+        match cover.image() {
             Ok(image) => {
                 let name = format!("cover_{}", chrono::Utc::now().timestamp());
 
-                if uploader
+                let metadata = match uploader
                     .handle(&image, &name, Some((500, 250)), Some((700, 350)), true)
-                    .is_err()
+                    .await
                 {
-                    return HttpResponse::BadRequest().finish();
-                }
+                    Ok(metadata) => metadata,
+                    Err(_) => return HttpResponse::BadRequest().finish(),
+                };
 
                 let file_id = services::files::insert(
                     transaction.deref_mut(),
@@ -669,6 +765,7 @@ async fn update_article(
                             "jpg"
                         }
                     ),
+                    Some(&metadata),
                 )
                 .await
                 .unwrap();
@@ -680,25 +777,14 @@ async fn update_article(
                 }
 
                 let path = if let Ok(cover) =
-                    services::files::get::<Cover>(&pool, cover_id, "path").await
+                    services::files::get::<Cover>(&pool, article.cover_id, "path").await
                 {
                     cover.path
                 } else {
                     return HttpResponse::InternalServerError().finish();
                 };
 
-                let old_cover_name = path.split('.').collect::<Vec<_>>();
-                let old_cover_name = old_cover_name.get(0).unwrap();
-
-                files_to_remove.append(
-                    &mut [
-                        format!("./uploads/mobile/{}", path),
-                        format!("./uploads/mobile/{}.webp", old_cover_name),
-                        format!("./uploads/{}", path),
-                        format!("./uploads/{}.webp", old_cover_name),
-                    ]
-                    .to_vec(),
-                );
+                files_to_remove.append(&mut crate::utils::image::variants(&path));
 
                 // Delete cover form field of the fields to be update, set
                 // cover_id instead with new file id
@@ -721,21 +807,26 @@ async fn update_article(
 
     // If an cover has been supplied, remove old
     if let Patch::Value(_) = &form.cover {
-        services::files::delete(transaction.deref_mut(), cover_id).await;
+        services::files::delete(transaction.deref_mut(), article.cover_id).await;
     }
 
     transaction.commit().await.unwrap();
 
-    crate::utils::image::remove_files(&files_to_remove);
+    crate::utils::image::remove_files(storage.into_inner(), files_to_remove).await;
 
     uploader.clear();
 
+    if let Patch::Value(true) = form.is_published {
+        crate::controllers::newsletter::notify_subscribers(&pool, id).await;
+    }
+
     HttpResponse::Ok().finish()
 }
 
 #[delete("/articles/{id}")]
 async fn delete_article(
     pool: web::Data<PgPool>,
+    storage: web::Data<dyn Storage>,
     session: Identity,
     web::Path(id): web::Path<i16>,
 ) -> HttpResponse {
@@ -765,37 +856,15 @@ async fn delete_article(
 
     if let Ok(article) = article {
         if let Ok(file) = services::files::get::<File>(&pool, article.cover_id, "path").await {
-            let cover_file_name = file.path.split('.').collect::<Vec<_>>();
-            let cover_file_name = cover_file_name.get(0).unwrap();
-
-            images_to_delete.append(
-                &mut [
-                    format!("./uploads/mobile/{}", file.path),
-                    format!("./uploads/mobile/{}.webp", cover_file_name),
-                    format!("./uploads/{}", file.path),
-                    format!("./uploads/{}.webp", cover_file_name),
-                ]
-                .to_vec(),
-            );
+            images_to_delete.append(&mut crate::utils::image::variants(&file.path));
 
             for image in &images {
-                let file_name = image.path.split('.').collect::<Vec<_>>();
-                let file_name = file_name.get(0).unwrap();
-
-                images_to_delete.append(
-                    &mut [
-                        format!("./uploads/mobile/{}", image.path),
-                        format!("./uploads/mobile/{}.webp", file_name),
-                        format!("./uploads/{}", image.path),
-                        format!("./uploads/{}.webp", file_name),
-                    ]
-                    .to_vec(),
-                );
+                images_to_delete.append(&mut crate::utils::image::variants(&image.path));
             }
 
             services::blog::articles::delete(&pool, id).await;
 
-            crate::utils::image::remove_files(&images_to_delete);
+            crate::utils::image::remove_files(storage.into_inner(), images_to_delete).await;
 
             return HttpResponse::Ok().finish();
         }
@@ -0,0 +1,330 @@
+use crate::{
+    services::blog::comments::{self, Comment, NewComment, Status},
+    utils::html,
+};
+use actix_identity::Identity;
+use actix_web::{delete, get, patch, post, web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+/// Number of comments per page of the moderation queue
+const PAGE_SIZE: i64 = 20;
+
+/// Name the replies of the back office are signed with
+const AUTHOR_NAME: &str = "Ludivine Farat";
+
+#[derive(Deserialize)]
+pub struct QueueQuery {
+    /// The pending comments by default
+    status: Option<Status>,
+    /// Page of the queue, starting from 1
+    page: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct StatusForm {
+    status: Status,
+}
+
+#[derive(Deserialize)]
+pub struct ReplyForm {
+    content: String,
+}
+
+#[get("")]
+pub async fn get_comments(
+    pool: web::Data<PgPool>,
+    session: Identity,
+    query: web::Query<QueueQuery>,
+) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let status = query.status.unwrap_or(Status::Pending);
+    let page = query.page.unwrap_or(1);
+
+    if page < 1 {
+        return HttpResponse::BadRequest().finish();
+    }
+
+    let (comments, total) = futures::join!(
+        comments::get_all(pool.as_ref(), status, PAGE_SIZE, (page - 1) * PAGE_SIZE),
+        comments::count(pool.as_ref(), status)
+    );
+
+    match (comments, total) {
+        (Ok(comments), Ok(total)) => HttpResponse::Ok().json(serde_json::json!({
+            "comments": comments,
+            "total": total,
+            "page_size": PAGE_SIZE,
+        })),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Approve, reject or mark as spam a comment
+#[patch("/{id}")]
+pub async fn update_comment(
+    pool: web::Data<PgPool>,
+    session: Identity,
+    web::Path(id): web::Path<i32>,
+    form: web::Json<StatusForm>,
+) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    match comments::set_status(pool.as_ref(), id, form.status).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Reply to a pending or approved comment as the author of the blog. The reply
+/// is published directly, and approves the comment and its thread when pending
+#[post("/{id}/reply")]
+pub async fn reply(
+    pool: web::Data<PgPool>,
+    session: Identity,
+    web::Path(id): web::Path<i32>,
+    form: web::Json<ReplyForm>,
+) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let content = html::clean(&form.content);
+
+    if content.is_empty() || content.len() > 10_000 {
+        return HttpResponse::BadRequest().finish();
+    }
+
+    let comment = match comments::get(pool.as_ref(), id).await {
+        Ok(comment) => comment,
+        Err(sqlx::Error::RowNotFound) => return HttpResponse::NotFound().finish(),
+        Err(e) => {
+            eprintln!("{}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let parent = match comment.parent_id {
+        Some(parent_id) => match comments::get(pool.as_ref(), parent_id).await {
+            Ok(parent) => Some(parent),
+            Err(e) => {
+                eprintln!("{}", e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        },
+        None => None,
+    };
+    let thread: Vec<&Comment> = std::iter::once(&comment).chain(parent.as_ref()).collect();
+
+    // A reply under a rejected comment or a spam would never be displayed
+    if thread.iter().any(|comment| {
+        comment.status != Status::Pending.as_str() && comment.status != Status::Approved.as_str()
+    }) {
+        return HttpResponse::BadRequest().finish();
+    }
+
+    for comment in thread
+        .iter()
+        .filter(|comment| comment.status == Status::Pending.as_str())
+    {
+        if let Err(e) = comments::set_status(pool.as_ref(), comment.id, Status::Approved).await {
+            eprintln!("{}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    // Threads are one level deep, a reply to a reply answers its comment
+    let reply = NewComment {
+        article_id: comment.article_id,
+        parent_id: Some(comment.parent_id.unwrap_or(comment.id)),
+        author: AUTHOR_NAME,
+        email: None,
+        content: &content,
+        status: Status::Approved,
+        is_author: true,
+    };
+
+    match comments::add(pool.as_ref(), &reply).await {
+        Ok(reply_id) => HttpResponse::Created().json(serde_json::json!({ "id": reply_id })),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Delete a comment and its replies
+#[delete("/{id}")]
+pub async fn delete_comment(
+    pool: web::Data<PgPool>,
+    session: Identity,
+    web::Path(id): web::Path<i32>,
+) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    match comments::delete(pool.as_ref(), id).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::{
+        create_pool,
+        services::blog::comments::{self, NewComment, Status},
+    };
+    use actix_identity::{CookieIdentityPolicy, IdentityService};
+    use actix_web::{cookie::Cookie, test, web, App};
+    use dotenv::dotenv;
+
+    #[actix_rt::test]
+    async fn test_moderation() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let mut app = test::init_service(
+            App::new()
+                .wrap(IdentityService::new(
+                    CookieIdentityPolicy::new(&[0; 32])
+                        .name("auth-cookie")
+                        .secure(true),
+                ))
+                .data(pool.clone())
+                .service(web::scope("/user").service(crate::controllers::user::login))
+                .service(
+                    web::scope("/comments")
+                        .service(super::get_comments)
+                        .service(super::update_comment)
+                        .service(super::reply)
+                        .service(super::delete_comment),
+                ),
+        )
+        .await;
+        let id = comments::add(
+            &pool,
+            &NewComment {
+                article_id: 3,
+                parent_id: None,
+                author: "Jean",
+                email: None,
+                content: "Très bel article",
+                status: Status::Pending,
+                is_author: false,
+            },
+        )
+        .await
+        .unwrap();
+
+        let res = test::TestRequest::get()
+            .uri("/comments")
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), 401);
+
+        let res = test::TestRequest::post()
+            .uri("/user/login")
+            .set_form(&serde_json::json!({
+                "email": "contact@ludivinefarat.fr",
+                "password": "root"
+            }))
+            .send_request(&mut app)
+            .await;
+        let cookie = res
+            .headers()
+            .get(actix_web::http::header::SET_COOKIE)
+            .unwrap();
+        let cookie = Cookie::from_str(&cookie.to_str().unwrap()).unwrap();
+
+        let res = test::TestRequest::get()
+            .uri("/comments?status=pending")
+            .cookie(cookie.clone())
+            .send_request(&mut app)
+            .await;
+
+        assert!(res.status().is_success());
+
+        let body: serde_json::Value = test::read_body_json(res).await;
+
+        assert!(body["comments"].is_array());
+        assert!(body["total"].as_i64().unwrap() >= 1);
+
+        let res = test::TestRequest::post()
+            .uri(&format!("/comments/{}/reply", id))
+            .cookie(cookie.clone())
+            .set_json(&serde_json::json!({ "content": "<script>alert(1)</script>Merci !" }))
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), 201);
+
+        let body: serde_json::Value = test::read_body_json(res).await;
+        let reply = comments::get(&pool, body["id"].as_i64().unwrap() as i32)
+            .await
+            .unwrap();
+
+        assert_eq!(reply.content, "Merci !");
+        assert_eq!(reply.parent_id, Some(id));
+        // Replying approves the comment
+        assert_eq!(comments::get(&pool, id).await.unwrap().status, "approved");
+
+        let res = test::TestRequest::patch()
+            .uri(&format!("/comments/{}", id))
+            .cookie(cookie.clone())
+            .set_json(&serde_json::json!({ "status": "spam" }))
+            .send_request(&mut app)
+            .await;
+
+        assert!(res.status().is_success());
+
+        // Neither the spam nor the replies of its thread can be answered
+        for target in &[id, reply.id] {
+            let res = test::TestRequest::post()
+                .uri(&format!("/comments/{}/reply", target))
+                .cookie(cookie.clone())
+                .set_json(&serde_json::json!({ "content": "Merci !" }))
+                .send_request(&mut app)
+                .await;
+
+            assert_eq!(res.status(), 400);
+        }
+
+        let res = test::TestRequest::patch()
+            .uri(&format!("/comments/{}", id))
+            .cookie(cookie.clone())
+            .set_json(&serde_json::json!({ "status": "deleted" }))
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), 400);
+
+        let res = test::TestRequest::delete()
+            .uri(&format!("/comments/{}", id))
+            .cookie(cookie)
+            .send_request(&mut app)
+            .await;
+
+        assert!(res.status().is_success());
+    }
+}
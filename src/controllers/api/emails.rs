@@ -0,0 +1,41 @@
+use crate::services::emails;
+use actix_identity::Identity;
+use actix_web::{get, post, web, HttpResponse};
+use sqlx::PgPool;
+
+/// Emails given up on or waiting for another attempt
+#[get("/failed")]
+pub async fn get_failed(pool: web::Data<PgPool>, session: Identity) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    match emails::get_failed(pool.as_ref()).await {
+        Ok(emails) => HttpResponse::Ok().json(emails),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Send an email again on the next run of the worker
+#[post("/{id}/retry")]
+pub async fn retry(
+    pool: web::Data<PgPool>,
+    session: Identity,
+    web::Path(id): web::Path<i32>,
+) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    match emails::retry(pool.as_ref(), id).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
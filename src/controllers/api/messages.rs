@@ -0,0 +1,374 @@
+use crate::{
+    services::{
+        auto_reply::{self, AutoReply},
+        emails,
+        messages::{self, Filter, Status},
+    },
+    utils::mail::{Addresses, Email},
+};
+use actix_identity::Identity;
+use actix_web::{get, patch, post, put, web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+/// Number of messages per page of the inbox
+const PAGE_SIZE: i64 = 20;
+
+/// Maximum length (in characters) of a search
+const MAX_SEARCH_LENGTH: usize = 120;
+
+#[derive(Deserialize)]
+pub struct InboxQuery {
+    /// Every message neither archived nor spam by default
+    status: Option<Status>,
+    search: Option<String>,
+    /// Page of the inbox, starting from 1
+    page: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct StatusForm {
+    status: Status,
+}
+
+#[derive(Deserialize)]
+pub struct ReplyForm {
+    content: String,
+}
+
+#[get("")]
+pub async fn get_messages(
+    pool: web::Data<PgPool>,
+    session: Identity,
+    query: web::Query<InboxQuery>,
+) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let search = query
+        .search
+        .as_deref()
+        .map(str::trim)
+        .filter(|search| !search.is_empty());
+    let page = query.page.unwrap_or(1);
+
+    if page < 1 || search.map_or(false, |search| search.chars().count() > MAX_SEARCH_LENGTH) {
+        return HttpResponse::BadRequest().finish();
+    }
+
+    let filter = Filter {
+        status: query.status,
+        search,
+    };
+    let (messages, total) = futures::join!(
+        messages::get_all(pool.as_ref(), &filter, PAGE_SIZE, (page - 1) * PAGE_SIZE),
+        messages::count(pool.as_ref(), &filter)
+    );
+
+    match (messages, total) {
+        (Ok(messages), Ok(total)) => HttpResponse::Ok().json(serde_json::json!({
+            "messages": messages,
+            "total": total,
+            "page_size": PAGE_SIZE,
+        })),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/unread")]
+pub async fn count_unread(pool: web::Data<PgPool>, session: Identity) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    match messages::count_unread(pool.as_ref()).await {
+        Ok(count) => HttpResponse::Ok().json(serde_json::json!({ "count": count })),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/auto-reply")]
+pub async fn get_auto_reply(pool: web::Data<PgPool>, session: Identity) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    match auto_reply::get(pool.as_ref()).await {
+        Ok(auto_reply) => HttpResponse::Ok().json(auto_reply),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[put("/auto-reply")]
+pub async fn update_auto_reply(
+    pool: web::Data<PgPool>,
+    session: Identity,
+    mut form: web::Json<AutoReply>,
+) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    form.subject = form.subject.trim().to_string();
+    form.body = form.body.trim().to_string();
+
+    if form.subject.is_empty()
+        || form.subject.chars().count() > 255
+        || form.body.is_empty()
+        || form.body.len() > 10_000
+    {
+        return HttpResponse::BadRequest().finish();
+    }
+
+    match auto_reply::update(pool.as_ref(), &form).await {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Message and its replies, a new message is marked as read
+#[get("/{id}")]
+pub async fn get_message(
+    pool: web::Data<PgPool>,
+    session: Identity,
+    web::Path(id): web::Path<i32>,
+) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    if let Err(e) = messages::mark_as_read(pool.as_ref(), id).await {
+        eprintln!("{}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let (message, replies) = futures::join!(
+        messages::get(pool.as_ref(), id),
+        messages::get_replies(pool.as_ref(), id)
+    );
+
+    match (message, replies) {
+        (Ok(message), Ok(replies)) => HttpResponse::Ok().json(serde_json::json!({
+            "message": message,
+            "replies": replies,
+        })),
+        (Err(sqlx::Error::RowNotFound), _) => HttpResponse::NotFound().finish(),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[patch("/{id}")]
+pub async fn update_message(
+    pool: web::Data<PgPool>,
+    session: Identity,
+    web::Path(id): web::Path<i32>,
+    form: web::Json<StatusForm>,
+) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    match messages::set_status(pool.as_ref(), id, form.status).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Send a reply to the author of a message, by email through the outbox
+#[post("/{id}/reply")]
+pub async fn reply(
+    pool: web::Data<PgPool>,
+    session: Identity,
+    web::Path(id): web::Path<i32>,
+    form: web::Json<ReplyForm>,
+) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let content = form.content.trim();
+
+    if content.is_empty() || content.len() > 10_000 {
+        return HttpResponse::BadRequest().finish();
+    }
+
+    let message = match messages::get(pool.as_ref(), id).await {
+        Ok(message) => message,
+        Err(sqlx::Error::RowNotFound) => return HttpResponse::NotFound().finish(),
+        Err(e) => {
+            eprintln!("{}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let email = Email {
+        text: Some(content.to_string()),
+        ..Addresses::from_env().email(&message.email, "Re : votre message")
+    };
+
+    if let Err(e) = emails::enqueue(pool.as_ref(), &email).await {
+        eprintln!("{}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    match messages::add_reply(pool.as_ref(), id, content).await {
+        Ok(reply_id) => HttpResponse::Created().json(serde_json::json!({ "id": reply_id })),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::{create_pool, services};
+    use actix_identity::{CookieIdentityPolicy, IdentityService};
+    use actix_web::{cookie::Cookie, test, web, App};
+    use dotenv::dotenv;
+
+    #[actix_rt::test]
+    async fn test_inbox() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let mut app = test::init_service(
+            App::new()
+                .wrap(IdentityService::new(
+                    CookieIdentityPolicy::new(&[0; 32])
+                        .name("auth-cookie")
+                        .secure(true),
+                ))
+                .data(pool.clone())
+                .service(web::scope("/user").service(crate::controllers::user::login))
+                .service(
+                    web::scope("/messages")
+                        .service(super::get_messages)
+                        .service(super::count_unread)
+                        .service(super::get_auto_reply)
+                        .service(super::update_auto_reply)
+                        .service(super::get_message)
+                        .service(super::update_message),
+                ),
+        )
+        .await;
+        let id = services::messages::add(
+            &pool,
+            "Jean",
+            "Dupont",
+            Some("0601020304"),
+            "jean.dupont@example.com",
+            "Lorem ipsum dolor sit amet, consectetur adipiscing elit.",
+            services::messages::Status::New,
+        )
+        .await
+        .unwrap();
+
+        let res = test::TestRequest::get()
+            .uri("/messages/unread")
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), 401);
+
+        let res = test::TestRequest::post()
+            .uri("/user/login")
+            .set_form(&serde_json::json!({
+                "email": "contact@ludivinefarat.fr",
+                "password": "root"
+            }))
+            .send_request(&mut app)
+            .await;
+        let cookie = res
+            .headers()
+            .get(actix_web::http::header::SET_COOKIE)
+            .unwrap();
+        let cookie = Cookie::from_str(&cookie.to_str().unwrap()).unwrap();
+
+        let res = test::TestRequest::get()
+            .uri("/messages?status=new&search=dupont")
+            .cookie(cookie.clone())
+            .send_request(&mut app)
+            .await;
+
+        assert!(res.status().is_success());
+
+        let body: serde_json::Value = test::read_body_json(res).await;
+
+        assert!(body["messages"].is_array());
+        assert!(body["total"].as_i64().unwrap() >= 1);
+
+        let res = test::TestRequest::get()
+            .uri(&format!("/messages/{}", id))
+            .cookie(cookie.clone())
+            .send_request(&mut app)
+            .await;
+
+        assert!(res.status().is_success());
+
+        let body: serde_json::Value = test::read_body_json(res).await;
+
+        assert_eq!(body["message"]["status"], "read");
+        assert!(body["replies"].is_array());
+
+        let res = test::TestRequest::get()
+            .uri("/messages/auto-reply")
+            .cookie(cookie.clone())
+            .send_request(&mut app)
+            .await;
+
+        assert!(res.status().is_success());
+
+        let mut auto_reply: serde_json::Value = test::read_body_json(res).await;
+        auto_reply["subject"] = serde_json::Value::String(" ".to_string());
+
+        let res = test::TestRequest::put()
+            .uri("/messages/auto-reply")
+            .cookie(cookie.clone())
+            .set_json(&auto_reply)
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), 400);
+
+        let res = test::TestRequest::patch()
+            .uri(&format!("/messages/{}", id))
+            .cookie(cookie.clone())
+            .set_json(&serde_json::json!({ "status": "spam" }))
+            .send_request(&mut app)
+            .await;
+
+        assert!(res.status().is_success());
+
+        let res = test::TestRequest::patch()
+            .uri(&format!("/messages/{}", id))
+            .cookie(cookie)
+            .set_json(&serde_json::json!({ "status": "deleted" }))
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), 400);
+    }
+}
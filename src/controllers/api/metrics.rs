@@ -0,0 +1,610 @@
+use crate::services::metrics::{
+    events,
+    export::{self, Format, Table},
+    live::{self, Live},
+    stats::{self, Boundary, Dimension, Granularity, Period},
+};
+use actix_identity::Identity;
+use actix_web::{
+    dev::BodyEncoding,
+    get,
+    http::{header, ContentEncoding},
+    web, HttpResponse,
+};
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+/// Number of rows of the views and referrers reports
+const REPORT_LIMIT: i64 = 20;
+
+/// Duration (in days) of the default period, ending today
+const DEFAULT_DAYS: i64 = 30;
+
+/// Maximum duration (in days) of a period
+const MAX_DAYS: i64 = 366;
+
+/// Maximum number of steps of a funnel
+const MAX_FUNNEL_STEPS: usize = 6;
+
+/// Delay (in seconds) between two updates of the real-time counter
+const LIVE_INTERVAL: u64 = 5;
+
+#[derive(Deserialize)]
+pub struct StatisticsQuery {
+    /// First day of the period (`YYYY-MM-DD`)
+    start: Option<NaiveDate>,
+    /// Last day of the period, included
+    end: Option<NaiveDate>,
+    /// Count the views of the bots, excluded by default
+    #[serde(default)]
+    bots: bool,
+}
+
+impl StatisticsQuery {
+    fn period(&self) -> Option<Period> {
+        get_period(self.start, self.end, self.bots)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FunnelQuery {
+    start: Option<NaiveDate>,
+    end: Option<NaiveDate>,
+    #[serde(default)]
+    bots: bool,
+    /// Names of the events, separated by commas
+    steps: String,
+}
+
+impl FunnelQuery {
+    fn steps(&self) -> Option<Vec<String>> {
+        let steps = self
+            .steps
+            .split(',')
+            .map(|step| step.trim().to_string())
+            .collect::<Vec<_>>();
+
+        if steps.len() > MAX_FUNNEL_STEPS || steps.iter().any(String::is_empty) {
+            return None;
+        }
+
+        Some(steps)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    start: Option<NaiveDate>,
+    end: Option<NaiveDate>,
+    #[serde(default)]
+    format: Format,
+    /// Names of the exported columns, separated by commas, every column by
+    /// default
+    columns: Option<String>,
+    /// Drop the hashed IP and the session id
+    #[serde(default)]
+    anonymize: bool,
+}
+
+/// Period from the first day to the last one included, the last
+/// `DEFAULT_DAYS` days by default
+fn get_period(start: Option<NaiveDate>, end: Option<NaiveDate>, bots: bool) -> Option<Period> {
+    let end = end.unwrap_or_else(|| Utc::today().naive_utc());
+    let start = start.unwrap_or_else(|| end - Duration::days(DEFAULT_DAYS - 1));
+
+    if start > end || end - start >= Duration::days(MAX_DAYS) {
+        return None;
+    }
+
+    Some(Period {
+        start: Utc.from_utc_date(&start).and_hms(0, 0, 0),
+        end: Utc
+            .from_utc_date(&(end + Duration::days(1)))
+            .and_hms(0, 0, 0),
+        include_bots: bots,
+    })
+}
+
+/// Every report of the period, compared with the previous one
+async fn report(pool: &PgPool, period: Period) -> Result<serde_json::Value, sqlx::Error> {
+    let previous = period.previous();
+    let (overview, previous_overview, views, referrers) = futures::join!(
+        stats::get_overview(pool, &period),
+        stats::get_overview(pool, &previous),
+        stats::get_views(pool, &period, REPORT_LIMIT),
+        stats::get_referrers(pool, &period, REPORT_LIMIT)
+    );
+    let (sources, campaigns, events) = futures::join!(
+        stats::get_sources(pool, &period),
+        stats::get_campaigns(pool, &period, REPORT_LIMIT),
+        stats::get_events(pool, &period)
+    );
+    let (entries, exits) = futures::join!(
+        stats::get_entries(pool, &period, Boundary::Entry, REPORT_LIMIT),
+        stats::get_entries(pool, &period, Boundary::Exit, REPORT_LIMIT)
+    );
+
+    // Short periods are detailed by hour
+    let granularity = if period.end - period.start <= Duration::days(2) {
+        Granularity::Hour
+    } else {
+        Granularity::Day
+    };
+    let (browsers, os, devices, timeline) = futures::join!(
+        stats::get_breakdown(pool, &period, Dimension::Browser),
+        stats::get_breakdown(pool, &period, Dimension::Os),
+        stats::get_breakdown(pool, &period, Dimension::DeviceType),
+        stats::get_timeline(pool, &period, granularity)
+    );
+
+    Ok(serde_json::json!({
+        "period": period,
+        "previous_period": previous,
+        "overview": overview?,
+        "previous_overview": previous_overview?,
+        "views": views?,
+        "referrers": referrers?,
+        "sources": sources?,
+        "campaigns": campaigns?,
+        "events": events?,
+        "entries": entries?,
+        "exits": exits?,
+        "browsers": browsers?,
+        "os": os?,
+        "devices": devices?,
+        "timeline": timeline?,
+    }))
+}
+
+#[get("")]
+pub async fn get_statistics(
+    pool: web::Data<PgPool>,
+    session: Identity,
+    query: web::Query<StatisticsQuery>,
+) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let period = match query.period() {
+        Some(period) => period,
+        None => return HttpResponse::BadRequest().finish(),
+    };
+
+    match report(pool.as_ref(), period).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/funnel")]
+pub async fn get_funnel(
+    pool: web::Data<PgPool>,
+    session: Identity,
+    query: web::Query<FunnelQuery>,
+) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let (period, steps) = match (
+        get_period(query.start, query.end, query.bots),
+        query.steps(),
+    ) {
+        (Some(period), Some(steps)) => (period, steps),
+        _ => return HttpResponse::BadRequest().finish(),
+    };
+
+    match events::get_funnel(pool.as_ref(), &period, &steps).await {
+        Ok(funnel) => HttpResponse::Ok().json(funnel),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Rows of a table of the metrics during the period, streamed as CSV or NDJSON
+#[get("/export/{table}")]
+pub async fn export(
+    pool: web::Data<PgPool>,
+    session: Identity,
+    web::Path(table): web::Path<String>,
+    query: web::Query<ExportQuery>,
+) -> HttpResponse {
+    use futures::StreamExt;
+
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let table = match Table::from_name(&table) {
+        Some(table) => table,
+        None => return HttpResponse::NotFound().finish(),
+    };
+    let (period, columns) = match (
+        get_period(query.start, query.end, true),
+        table.select(query.columns.as_deref(), query.anonymize),
+    ) {
+        (Some(period), Some(columns)) => (period, columns),
+        _ => return HttpResponse::BadRequest().finish(),
+    };
+    let filename = format!(
+        "{}_{}_{}.{}",
+        table.name(),
+        period.start.format("%Y-%m-%d"),
+        (period.end - Duration::days(1)).format("%Y-%m-%d"),
+        query.format.extension()
+    );
+    let first_line = futures::stream::iter(query.format.header(&columns)).map(Ok);
+    let rows = export::stream(
+        pool.get_ref().clone(),
+        table,
+        &columns,
+        query.format,
+        &period,
+    );
+
+    HttpResponse::Ok()
+        .content_type(query.format.content_type())
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .streaming(first_line.chain(rows).map(|line| {
+            line.map(web::Bytes::from)
+                .map_err(actix_web::error::ErrorInternalServerError)
+        }))
+}
+
+/// Server-sent events of the visitors on the site and of the contents they
+/// are viewing, every `LIVE_INTERVAL` seconds
+#[get("/live")]
+pub async fn get_live(pool: web::Data<PgPool>, session: Identity) -> HttpResponse {
+    use actix_web::rt::time;
+
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let receiver = live::subscribe();
+    let state = match Live::load(pool.as_ref()).await {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("{}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let interval = time::interval(std::time::Duration::from_secs(LIVE_INTERVAL));
+    let events = futures::stream::unfold(
+        (state, receiver, pool, interval),
+        |(mut state, mut receiver, pool, mut interval)| async move {
+            interval.tick().await;
+
+            loop {
+                match receiver.try_next() {
+                    Ok(Some(activity)) => state.apply(activity),
+                    Ok(None) => return None,
+                    Err(_) => break,
+                }
+            }
+
+            let snapshot = match state.snapshot(pool.as_ref()).await {
+                Ok(snapshot) => snapshot,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return None;
+                }
+            };
+            let event = format!("data: {}\n\n", serde_json::to_string(&snapshot).ok()?);
+
+            Some((
+                Ok::<_, actix_web::Error>(web::Bytes::from(event)),
+                (state, receiver, pool, interval),
+            ))
+        },
+    );
+
+    // A compressed stream would hold the events until the buffer of the
+    // encoder is full
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .encoding(ContentEncoding::Identity)
+        .streaming(Box::pin(events))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::create_pool;
+    use actix_identity::{CookieIdentityPolicy, IdentityService};
+    use actix_web::{cookie::Cookie, test, web, App};
+    use dotenv::dotenv;
+
+    #[actix_rt::test]
+    async fn test_get_statistics() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let mut app = test::init_service(
+            App::new()
+                .wrap(IdentityService::new(
+                    CookieIdentityPolicy::new(&[0; 32])
+                        .name("auth-cookie")
+                        .secure(true),
+                ))
+                .data(pool.clone())
+                .service(web::scope("/user").service(crate::controllers::user::login))
+                .service(web::scope("/metrics").service(super::get_statistics)),
+        )
+        .await;
+        let res = test::TestRequest::post()
+            .uri("/user/login")
+            .set_form(&serde_json::json!({
+                "email": "contact@ludivinefarat.fr",
+                "password": "root"
+            }))
+            .send_request(&mut app)
+            .await;
+        assert!(res.status().is_success());
+
+        let cookie = res.headers().get(actix_web::http::header::SET_COOKIE);
+
+        assert!(cookie.is_some());
+
+        let cookie = Cookie::from_str(&cookie.unwrap().to_str().unwrap()).unwrap();
+        let res = test::TestRequest::get()
+            .uri("/metrics?start=2021-01-01&end=2021-01-31")
+            .cookie(cookie.clone())
+            .send_request(&mut app)
+            .await;
+
+        assert!(res.status().is_success());
+
+        let body: serde_json::Value = test::read_body_json(res).await;
+
+        assert!(body["overview"]["views"].is_number());
+        assert!(body["previous_overview"]["daily_visitors"].is_number());
+        assert!(body["views"].is_array());
+        assert!(body["timeline"].is_array());
+        assert!(body["campaigns"].is_array());
+        assert!(body["events"].is_array());
+        assert!(body["entries"].is_array());
+        assert!(body["exits"].is_array());
+        assert_eq!(body["previous_period"]["end"], body["period"]["start"]);
+
+        let res = test::TestRequest::get()
+            .uri("/metrics?start=2021-02-01&end=2021-01-01")
+            .cookie(cookie)
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_funnel() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let mut app = test::init_service(
+            App::new()
+                .wrap(IdentityService::new(
+                    CookieIdentityPolicy::new(&[0; 32])
+                        .name("auth-cookie")
+                        .secure(true),
+                ))
+                .data(pool.clone())
+                .service(web::scope("/user").service(crate::controllers::user::login))
+                .service(web::scope("/metrics").service(super::get_funnel)),
+        )
+        .await;
+        let res = test::TestRequest::post()
+            .uri("/user/login")
+            .set_form(&serde_json::json!({
+                "email": "contact@ludivinefarat.fr",
+                "password": "root"
+            }))
+            .send_request(&mut app)
+            .await;
+        assert!(res.status().is_success());
+
+        let cookie = res.headers().get(actix_web::http::header::SET_COOKIE);
+        let cookie = Cookie::from_str(&cookie.unwrap().to_str().unwrap()).unwrap();
+        let res = test::TestRequest::get()
+            .uri("/metrics/funnel?steps=project_opened,video_played,contact_form_sent")
+            .cookie(cookie.clone())
+            .send_request(&mut app)
+            .await;
+
+        assert!(res.status().is_success());
+
+        let body: serde_json::Value = test::read_body_json(res).await;
+
+        assert_eq!(body.as_array().unwrap().len(), 3);
+        assert_eq!(body[2]["name"], "contact_form_sent");
+        assert!(body[0]["visitors"].is_number());
+
+        let res = test::TestRequest::get()
+            .uri("/metrics/funnel?steps=project_opened,,contact_form_sent")
+            .cookie(cookie)
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), 400);
+    }
+
+    #[actix_rt::test]
+    async fn test_export() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let mut app = test::init_service(
+            App::new()
+                .wrap(IdentityService::new(
+                    CookieIdentityPolicy::new(&[0; 32])
+                        .name("auth-cookie")
+                        .secure(true),
+                ))
+                .data(pool.clone())
+                .service(web::scope("/user").service(crate::controllers::user::login))
+                .service(web::scope("/metrics").service(super::export)),
+        )
+        .await;
+        let res = test::TestRequest::post()
+            .uri("/user/login")
+            .set_form(&serde_json::json!({
+                "email": "contact@ludivinefarat.fr",
+                "password": "root"
+            }))
+            .send_request(&mut app)
+            .await;
+        assert!(res.status().is_success());
+
+        let cookie = res.headers().get(actix_web::http::header::SET_COOKIE);
+        let cookie = Cookie::from_str(&cookie.unwrap().to_str().unwrap()).unwrap();
+        let res = test::TestRequest::get()
+            .uri("/metrics/export/metrics?columns=date,ip,page_id&anonymize=true")
+            .cookie(cookie.clone())
+            .send_request(&mut app)
+            .await;
+
+        assert!(res.status().is_success());
+        assert_eq!(
+            res.headers()
+                .get(actix_web::http::header::CONTENT_TYPE)
+                .unwrap(),
+            "text/csv; charset=utf-8"
+        );
+
+        let body = test::read_body(res).await;
+        let body = std::str::from_utf8(&body).unwrap();
+
+        assert_eq!(body.lines().next(), Some("date,page_id"));
+
+        for uri in &[
+            "/metrics/export/metrics?columns=date,password",
+            "/metrics/export/sessions?columns=ip&anonymize=true",
+            "/metrics/export/metrics?format=xml",
+        ] {
+            let res = test::TestRequest::get()
+                .uri(uri)
+                .cookie(cookie.clone())
+                .send_request(&mut app)
+                .await;
+
+            assert_eq!(res.status(), 400, "{}", uri);
+        }
+
+        let res = test::TestRequest::get()
+            .uri("/metrics/export/users")
+            .cookie(cookie)
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), 404);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_live() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let mut app = test::init_service(
+            App::new()
+                .wrap(IdentityService::new(
+                    CookieIdentityPolicy::new(&[0; 32])
+                        .name("auth-cookie")
+                        .secure(true),
+                ))
+                .data(pool.clone())
+                .service(web::scope("/metrics").service(super::get_live)),
+        )
+        .await;
+        let res = test::TestRequest::get()
+            .uri("/metrics/live")
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), 401);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_live_compressed() {
+        use actix_web::{http::header, middleware::Compress};
+        use futures::StreamExt;
+
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let mut app = test::init_service(
+            App::new()
+                .wrap(Compress::default())
+                .wrap(IdentityService::new(
+                    CookieIdentityPolicy::new(&[0; 32])
+                        .name("auth-cookie")
+                        .secure(true),
+                ))
+                .data(pool.clone())
+                .service(web::scope("/user").service(crate::controllers::user::login))
+                .service(web::scope("/metrics").service(super::get_live)),
+        )
+        .await;
+        let res = test::TestRequest::post()
+            .uri("/user/login")
+            .set_form(&serde_json::json!({
+                "email": "contact@ludivinefarat.fr",
+                "password": "root"
+            }))
+            .send_request(&mut app)
+            .await;
+        assert!(res.status().is_success());
+
+        let cookie = res.headers().get(header::SET_COOKIE);
+        let cookie = Cookie::from_str(&cookie.unwrap().to_str().unwrap()).unwrap();
+        let mut res = test::TestRequest::get()
+            .uri("/metrics/live")
+            .header(header::ACCEPT_ENCODING, "gzip, deflate, br")
+            .cookie(cookie)
+            .send_request(&mut app)
+            .await;
+
+        assert!(res.status().is_success());
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+
+        // The first event is sent as soon as the stream starts
+        let event = res.take_body().next().await.unwrap().unwrap();
+
+        assert!(event.starts_with(b"data: {"));
+    }
+
+    #[actix_rt::test]
+    async fn test_get_statistics_not_logged() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let mut app = test::init_service(
+            App::new()
+                .wrap(IdentityService::new(
+                    CookieIdentityPolicy::new(&[0; 32])
+                        .name("auth-cookie")
+                        .secure(true),
+                ))
+                .data(pool.clone())
+                .service(web::scope("/metrics").service(super::get_statistics)),
+        )
+        .await;
+        let res = test::TestRequest::get()
+            .uri("/metrics")
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), 401);
+    }
+}
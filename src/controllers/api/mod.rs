@@ -1,16 +1,28 @@
 use crate::{
-    services,
-    utils::{image::Uploader, patch::Patch},
+    services::{self, attempts::Kind, messages::Status},
+    utils::{
+        image::Uploader,
+        mail::{self, templates, Addresses, Email},
+        patch::Patch,
+        spam::{self, Submission},
+        storage::Storage,
+        svg,
+        upload::{self, File, Multipart},
+    },
 };
-use actix_extract_multipart::{File, Multipart};
 use actix_identity::Identity;
-use actix_web::{patch, post, put, web, HttpResponse};
+use actix_web::{patch, post, put, web, HttpRequest, HttpResponse, ResponseError};
 use regex::Regex;
 use serde::Deserialize;
 use sqlx::PgPool;
 
 pub mod blog;
+pub mod comments;
+pub mod emails;
+pub mod messages;
+pub mod metrics;
 pub mod portfolio;
+pub mod subscribers;
 
 #[derive(Deserialize)]
 pub struct UpdateForm {
@@ -49,21 +61,23 @@ pub struct HomeImage {
 #[patch("/image")]
 pub async fn update_home_informations(
     session: Identity,
+    storage: web::Data<dyn Storage>,
     data: Multipart<HomeImage>,
 ) -> HttpResponse {
     if session.identity().is_none() {
         return HttpResponse::Unauthorized().finish();
     }
 
-    let mut uploader = crate::utils::image::Uploader::new();
+    let mut uploader = crate::utils::image::Uploader::new(storage.into_inner());
 
-    if !&["image/jpeg", "image/png", "image/webp"].contains(&data.image.file_type().as_str()) {
-        return HttpResponse::BadRequest().finish();
+    if let Err(e) = data.image.accept(upload::IMAGES) {
+        return e.error_response();
     }
 
-    if let Ok(image) = image::load_from_memory(data.image.data()) {
+    if let Ok(image) = data.image.image() {
         if uploader
             .handle(&image, "index", Some((500, 500)), Some((1000, 1000)), true)
+            .await
             .is_err()
         {
             return HttpResponse::InternalServerError().finish();
@@ -153,6 +167,77 @@ async fn update_little_plus_informations(
     }
 }
 
+/// Accepted types of the logo
+const SVG: &[&str] = &["image/svg+xml"];
+
+/// Accepted types of the favicon, raster images are converted to a favicon set
+const FAVICON: &[&str] = &["image/svg+xml", "image/png", "image/jpeg", "image/webp"];
+
+/// Files editable from the settings are served from the root of the site
+fn public_path(name: &str) -> String {
+    if cfg!(debug_assertions) {
+        format!("./public/{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Sanitize an uploaded SVG before writing it to the root of the site
+fn write_svg(file: &File, name: &str) -> Result<(), HttpResponse> {
+    let data = file
+        .read()
+        .map_err(|_| HttpResponse::InternalServerError().finish())?;
+    let svg = svg::sanitize(&data).map_err(|e| {
+        eprintln!("{}", e);
+        HttpResponse::BadRequest().json(serde_json::json!({ "error": "invalid_svg" }))
+    })?;
+
+    std::fs::write(public_path(name), svg).map_err(|e| {
+        eprintln!("{}", e);
+        HttpResponse::InternalServerError().finish()
+    })
+}
+
+/// Generate the favicon set of a raster image and the web app manifest
+/// referencing it
+fn write_favicons(file: &File, background_color: &str) -> Result<(), HttpResponse> {
+    let image = file
+        .image()
+        .map_err(|_| HttpResponse::BadRequest().finish())?;
+    let directory = if cfg!(debug_assertions) {
+        "./public"
+    } else {
+        "."
+    };
+
+    if let Err(e) = crate::utils::image::favicons(&image, directory) {
+        eprintln!("{}", e);
+        return Err(HttpResponse::InternalServerError().finish());
+    }
+
+    let manifest = serde_json::json!({
+        "name": "Ludivine Farat - Designer Graphique Freelance",
+        "short_name": "Ludivine Farat",
+        "icons": crate::utils::image::FAVICONS
+            .iter()
+            .filter(|(size, _)| *size >= 192)
+            .map(|(size, name)| serde_json::json!({
+                "src": format!("/{}", name),
+                "sizes": format!("{0}x{0}", size),
+                "type": "image/png",
+            }))
+            .collect::<Vec<_>>(),
+        "theme_color": background_color,
+        "background_color": background_color,
+        "display": "standalone",
+    });
+
+    std::fs::write(public_path("site.webmanifest"), manifest.to_string()).map_err(|e| {
+        eprintln!("{}", e);
+        HttpResponse::InternalServerError().finish()
+    })
+}
+
 #[derive(serde::Deserialize, serde::Serialize, Debug)]
 pub struct UpdateParametersForm {
     #[serde(skip_serializing)]
@@ -165,6 +250,8 @@ pub struct UpdateParametersForm {
     title_color: Patch<String>,
     #[serde(default)]
     text_color: Option<String>,
+    #[serde(default)]
+    comments_enabled: Patch<bool>,
 }
 
 #[patch("")]
@@ -173,63 +260,53 @@ pub async fn update_settings(
     pool: web::Data<PgPool>,
     form: Multipart<UpdateParametersForm>,
 ) -> HttpResponse {
-    use std::io::prelude::*;
-
     if session.identity().is_none() {
         return HttpResponse::Unauthorized().finish();
     }
 
-    match &form.logo {
-        Patch::Value(logo) => {
-            match std::fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(if cfg!(debug_assertions) {
-                    "./public/logo.svg"
-                } else {
-                    "logo.svg"
-                }) {
-                Ok(mut file) => {
-                    if let Err(_) = file.write_all(&logo.data()) {
-                        return HttpResponse::InternalServerError().finish();
-                    }
-                }
-                Err(_) => return HttpResponse::InternalServerError().finish(),
-            }
+    let mut fields = crate::utils::patch::extract_fields(&*form);
+
+    if let Patch::Value(logo) = &form.logo {
+        if let Err(e) = logo.accept(SVG) {
+            return e.error_response();
+        }
+
+        if let Err(response) = write_svg(logo, "logo.svg") {
+            return response;
         }
-        _ => (),
     }
 
-    match &form.favicon {
-        Patch::Value(favicon) => {
-            match std::fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(if cfg!(debug_assertions) {
-                    "./public/favicon.svg"
-                } else {
-                    "favicon.svg"
-                }) {
-                Ok(mut file) => {
-                    if let Err(e) = file.write_all(&favicon.data()) {
-                        println!("{:?}", e);
-                        return HttpResponse::InternalServerError().finish();
-                    }
-                }
-                _ => return HttpResponse::InternalServerError().finish(),
+    if let Patch::Value(favicon) = &form.favicon {
+        if let Err(e) = favicon.accept(FAVICON) {
+            return e.error_response();
+        }
+
+        let format = if favicon.file_type() == "image/svg+xml" {
+            write_svg(favicon, "favicon.svg").map(|_| "svg")
+        } else {
+            let background_color = match &form.background_color {
+                Patch::Value(color) => color.clone(),
+                _ => match services::settings::get(pool.as_ref()).await {
+                    Ok(settings) => settings.background_color,
+                    Err(_) => return HttpResponse::InternalServerError().finish(),
+                },
+            };
+
+            write_favicons(favicon, &background_color).map(|_| "png")
+        };
+
+        match format {
+            Ok(format) => {
+                fields.insert(
+                    "favicon_format".to_string(),
+                    serde_json::Value::String(format.to_string()),
+                );
             }
+            Err(response) => return response,
         }
-        _ => (),
     }
 
-    match services::settings::partial_update(
-        pool.as_ref(),
-        crate::utils::patch::extract_fields(&*form),
-    )
-    .await
-    {
+    match services::settings::partial_update(pool.as_ref(), fields).await {
         Ok(_) => HttpResponse::Ok().finish(),
         Err(e) => {
             eprintln!("{}", e);
@@ -238,6 +315,17 @@ pub async fn update_settings(
     }
 }
 
+/// Messages an IP can send in an hour before the next ones are put in the spam
+/// folder
+const CONTACT_LIMIT: i64 = 5;
+
+/// Messages an IP can send in an hour before the next ones are refused, to
+/// keep a flood out of the database
+const CONTACT_HARD_LIMIT: i64 = 20;
+
+/// Acknowledgements sent to an address in 24 hours
+const AUTO_REPLY_LIMIT: i64 = 1;
+
 #[derive(serde::Deserialize, Debug)]
 pub struct ContactForm {
     firstname: String,
@@ -245,12 +333,25 @@ pub struct ContactForm {
     phone_number: Option<String>,
     email: String,
     content: String,
+    /// Hidden field, only filled by bots
+    website: Option<String>,
+    /// Signed token given with the form, see `utils::spam`
+    token: Option<String>,
+    proof: Option<u64>,
 }
 
 #[post("")]
-pub async fn contact(mut form: web::Form<ContactForm>) -> HttpResponse {
-    use lettre::{SmtpClient, Transport};
-    use lettre_email::EmailBuilder;
+pub async fn contact(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    mut form: web::Form<ContactForm>,
+) -> HttpResponse {
+    let ip = super::client_ip(&req);
+    let attempts_counter = services::attempts::count(&pool, &ip, Kind::Contact).await;
+
+    if attempts_counter >= CONTACT_HARD_LIMIT {
+        return HttpResponse::TooManyRequests().finish();
+    }
 
     // Trim form fields
     form.firstname = form.firstname.trim().to_string();
@@ -263,7 +364,6 @@ pub async fn contact(mut form: web::Form<ContactForm>) -> HttpResponse {
 
     // Controls form informations
     let phone_regex = Regex::new(r"^((\+)33|0|0033)[1-9](\d{2}){4}$").unwrap();
-    let email_regex = Regex::new(r#"^(([^<>()\[\]\\.,;:\s@"]+(\.[^<>()\[\]\\.,;:\s@"]+)*)|(".+"))@((\[[0-9]{1,3}\.[0-9]{1,3}\.[0-9]{1,3}\.[0-9]{1,3}])|(([a-zA-Z\-0-9]+\.)+[a-zA-Z]{2,}))$"#).unwrap();
 
     if form.firstname.len() < 2
         || form.firstname.len() > 120
@@ -271,43 +371,130 @@ pub async fn contact(mut form: web::Form<ContactForm>) -> HttpResponse {
         || form.lastname.len() > 120
         || (form.phone_number.is_some()
             && !phone_regex.is_match(form.phone_number.as_ref().unwrap()))
-        || !email_regex.is_match(&form.email)
+        || !mail::is_valid_email(&form.email)
         || form.content.len() < 30
         || form.content.len() > 500
     {
         return HttpResponse::BadRequest().finish();
     }
 
-    // Format email content
-    let mut content = format!(
-        "<u>Nom :</u> {}<br />
-        <u>Prénom :</u> {} <br />",
-        form.lastname, form.firstname
-    );
-
-    if let Some(phone_number) = &form.phone_number {
-        content += &format!("<u>Numéro :</u> {}<br />", phone_number);
+    if let Err(e) = services::attempts::add(&pool, &form.email, &ip, Kind::Contact).await {
+        eprintln!("{}", e);
+        return HttpResponse::InternalServerError().finish();
     }
 
-    content += &format!("<u>Message :</u> {}<br />", form.content);
+    let verdict = spam::verdict(
+        &pool,
+        &Submission {
+            honeypot: form.website.as_deref(),
+            token: form.token.as_deref(),
+            proof: form.proof,
+            fields: &[
+                form.firstname.as_str(),
+                form.lastname.as_str(),
+                form.phone_number.as_deref().unwrap_or_default(),
+            ],
+            content: &form.content,
+        },
+        attempts_counter,
+        CONTACT_LIMIT,
+    )
+    .await;
+    let status = match verdict {
+        Ok(Ok(_)) => Status::New,
+        Ok(Err(reason)) => {
+            eprintln!("Contact message put in the spam folder: {}", reason);
+            Status::Spam
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    // The message is kept in the inbox of the back office even if the email
+    // is never sent
+    if let Err(e) = services::messages::add(
+        pool.as_ref(),
+        &form.firstname,
+        &form.lastname,
+        form.phone_number.as_deref(),
+        &form.email,
+        &form.content,
+        status,
+    )
+    .await
+    {
+        eprintln!("{}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
 
-    // TODO : see to pass as Secure (SSL or equivalent)
-    let email = EmailBuilder::new()
-        .to("hello@ludivinefarat.fr")
-        .from(form.email.as_str())
-        .subject("")
-        .html(content.as_str())
-        .build();
+    // A spam gets the same response as a message, not to tell the bots which
+    // check failed
+    if status == Status::Spam {
+        return HttpResponse::Ok().finish();
+    }
 
-    if let Ok(email) = email {
-        if let Ok(stmt_client) = SmtpClient::new_unencrypted_localhost() {
-            if stmt_client.transport().send(email.into()).is_ok() {
-                return HttpResponse::Ok().finish();
+    // Sent from the site, spoofing the address of the visitor would fail the
+    // SPF and DMARC checks
+    let addresses = Addresses::from_env();
+    let notification = templates::Contact {
+        firstname: &form.firstname,
+        lastname: &form.lastname,
+        phone_number: form.phone_number.as_deref(),
+        email: &form.email,
+        content: &form.content,
+    };
+    // Acknowledged once a day per address at most, not to relay emails to any
+    // address typed in the form
+    let auto_reply = match futures::join!(
+        services::auto_reply::get(pool.as_ref()),
+        services::messages::count_recent_from(pool.as_ref(), &form.email)
+    ) {
+        (Ok(auto_reply), Ok(count)) if auto_reply.enabled && count <= AUTO_REPLY_LIMIT => {
+            Some(auto_reply)
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            None
+        }
+        _ => None,
+    };
+    let auto_reply = auto_reply.as_ref().map(|auto_reply| {
+        addresses.render(
+            &form.email,
+            &templates::AutoReply {
+                subject: &auto_reply.subject,
+                body: &auto_reply.body,
+                firstname: &form.firstname,
+                content: &form.content,
+            },
+        )
+    });
+    let emails = addresses
+        .admins
+        .iter()
+        .map(|admin| {
+            addresses.render(admin, &notification).map(|email| Email {
+                reply_to: Some(form.email.clone()),
+                ..email
+            })
+        })
+        .chain(auto_reply)
+        .collect::<Result<Vec<_>, _>>();
+
+    match emails {
+        Ok(emails) => {
+            for email in emails {
+                if let Err(e) = services::emails::enqueue(pool.as_ref(), &email).await {
+                    eprintln!("{}", e);
+                }
             }
         }
+        Err(e) => eprintln!("{}", e),
     }
 
-    HttpResponse::InternalServerError().finish()
+    HttpResponse::Ok().finish()
 }
 
 #[cfg(test)]
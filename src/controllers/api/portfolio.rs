@@ -1,11 +1,186 @@
-use crate::{services, utils::patch::Patch};
+use crate::{
+    services,
+    utils::{
+        image::Uploader,
+        patch::Patch,
+        storage::Storage,
+        upload::{File, Multipart},
+        video::{self, VideoFormat},
+    },
+};
 use actix_identity::Identity;
-use actix_web::{delete, get, patch, post, put, web, HttpResponse};
+use actix_web::{
+    delete, error::BlockingError, get, patch, post, put, web, HttpResponse, ResponseError,
+};
 use ammonia::Builder;
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
 use std::{collections::HashSet, ops::DerefMut};
 
+/// Maximum number of assets of a project
+const MAX_ASSETS: usize = 5;
+
+/// Accepted types of the files of a project
+const ACCEPTED_FILES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/webp",
+    "video/mp4",
+    "video/webm",
+];
+
+/// Store an uploaded file as an asset of the project. Images are resized,
+/// videos are kept as is and get a poster extracted from one of their frames
+async fn insert_asset(
+    connection: &mut PgConnection,
+    uploader: &mut Uploader,
+    project_id: i16,
+    file: &File,
+    order: i16,
+) -> Result<i16, HttpResponse> {
+    file.accept(ACCEPTED_FILES)
+        .map_err(|e| e.error_response())?;
+
+    let name = {
+        use slugmin::slugify;
+
+        slugify(&format!(
+            "{}_{}",
+            file.name(),
+            chrono::Utc::now().timestamp()
+        ))
+    };
+
+    if let Some(format) = VideoFormat::from_mime_type(file.file_type()) {
+        let path = format!("{}.{}", name, format.extension());
+
+        if let Err(e) = uploader
+            .put_file(&path, file.path(), format.mime_type())
+            .await
+        {
+            eprintln!("{}", e);
+            return Err(HttpResponse::InternalServerError().finish());
+        }
+
+        let video_path = file.path().to_path_buf();
+        let poster = match web::block(move || video::poster(&video_path)).await {
+            Ok(poster) => poster,
+            Err(BlockingError::Error(e)) if e.is_invalid_video() => {
+                eprintln!("{}", e);
+                return Err(HttpResponse::BadRequest().finish());
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                return Err(HttpResponse::InternalServerError().finish());
+            }
+        };
+        let poster_name = format!("{}_poster", name);
+        let metadata = uploader
+            .handle(
+                &poster,
+                &poster_name,
+                Some((500, 500)),
+                Some((800, 800)),
+                true,
+            )
+            .await
+            .map_err(|_| HttpResponse::InternalServerError().finish())?;
+        let poster_id = services::files::insert(
+            &mut *connection,
+            None,
+            &format!(
+                "{}.{}",
+                poster_name,
+                if poster.color().has_alpha() {
+                    "png"
+                } else {
+                    "jpg"
+                }
+            ),
+            Some(&metadata),
+        )
+        .await
+        .map_err(|_| HttpResponse::InternalServerError().finish())?;
+        // The dimensions and the placeholder are the ones of the poster
+        let file_id = services::files::insert(&mut *connection, Some(file.name()), &path, None)
+            .await
+            .map_err(|_| HttpResponse::InternalServerError().finish())?;
+
+        return services::projects::assets::insert_video(
+            &mut *connection,
+            project_id,
+            file_id,
+            poster_id,
+            order,
+        )
+        .await
+        .map_err(|_| HttpResponse::InternalServerError().finish());
+    }
+
+    let image = file
+        .image()
+        .map_err(|_| HttpResponse::BadRequest().finish())?;
+    let metadata = uploader
+        .handle(&image, &name, Some((500, 500)), Some((800, 800)), true)
+        .await
+        .map_err(|_| HttpResponse::InternalServerError().finish())?;
+    let file_id = services::files::insert(
+        &mut *connection,
+        Some(file.name()),
+        &format!(
+            "{}.{}",
+            name,
+            if image.color().has_alpha() {
+                "png"
+            } else {
+                "jpg"
+            }
+        ),
+        Some(&metadata),
+    )
+    .await
+    .map_err(|_| HttpResponse::InternalServerError().finish())?;
+
+    services::projects::assets::insert(&mut *connection, project_id, file_id, order)
+        .await
+        .map_err(|_| HttpResponse::InternalServerError().finish())
+}
+
+/// Parse the Vimeo and YouTube links sent by the back office
+fn parse_embeds(
+    urls: &Option<Vec<String>>,
+) -> Result<Vec<(video::Provider, String)>, HttpResponse> {
+    let mut embeds = vec![];
+
+    if let Some(urls) = urls {
+        for url in urls.iter().filter(|url| !url.trim().is_empty()) {
+            match video::parse_embed(url) {
+                Some(embed) => embeds.push(embed),
+                None => return Err(HttpResponse::BadRequest().finish()),
+            }
+        }
+    }
+
+    Ok(embeds)
+}
+
+/// Files of the asset to remove from the storage once it has been deleted
+fn asset_files(path: Option<&str>, poster: Option<&str>) -> Vec<String> {
+    let mut files = vec![];
+
+    match path {
+        Some(path) if VideoFormat::from_path(path).is_some() => files.push(path.to_string()),
+        Some(path) => files.append(&mut crate::utils::image::variants(path)),
+        None => (),
+    }
+
+    if let Some(poster) = poster {
+        files.append(&mut crate::utils::image::variants(poster));
+    }
+
+    files
+}
+
 #[get("/projects/{id}")]
 pub async fn get_project(
     pool: web::Data<PgPool>,
@@ -30,7 +205,9 @@ pub async fn get_project(
     #[derive(Serialize)]
     struct Asset {
         id: i16,
-        path: String,
+        kind: String,
+        path: Option<String>,
+        embed_url: Option<String>,
         order: usize,
     }
 
@@ -56,7 +233,9 @@ pub async fn get_project(
                     .enumerate()
                     .map(|(i, asset)| Asset {
                         id: asset.id,
-                        path: asset.path.clone(),
+                        kind: asset.kind.clone(),
+                        path: asset.preview().map(String::from),
+                        embed_url: asset.player_url(),
                         order: i
                     })
                     .collect::<Vec<Asset>>(),
@@ -184,13 +363,15 @@ pub struct ProjectAddForm {
     description: Option<String>,
     content: String,
     categories: Option<Vec<i16>>,
-    files: Vec<actix_extract_multipart::File>, // TODO : change to Option
+    files: Vec<File>, // TODO : change to Option
+    embeds: Option<Vec<String>>,
 }
 
 #[post("/projects")]
 pub async fn insert_project(
     pool: web::Data<PgPool>,
-    mut form: actix_extract_multipart::Multipart<ProjectAddForm>,
+    storage: web::Data<dyn Storage>,
+    mut form: Multipart<ProjectAddForm>,
     session: Identity,
 ) -> HttpResponse {
     if session.identity().is_none() {
@@ -240,7 +421,16 @@ pub async fn insert_project(
         }
     }
 
-    let mut uploader = crate::utils::image::Uploader::new();
+    let embeds = match parse_embeds(&form.embeds) {
+        Ok(embeds) => embeds,
+        Err(res) => return res,
+    };
+
+    if form.files.len() + embeds.len() > MAX_ASSETS {
+        return HttpResponse::BadRequest().finish();
+    }
+
+    let mut uploader = Uploader::new(storage.clone().into_inner());
     let mut transaction = pool.begin().await.unwrap();
 
     return match services::projects::insert(
@@ -270,56 +460,25 @@ pub async fn insert_project(
 
             // Handle assets
             for (i, file) in form.files.iter().enumerate() {
-                let name = {
-                    use slugmin::slugify;
-
-                    slugify(&format!(
-                        "{}_{}",
-                        file.name(),
-                        chrono::Utc::now().timestamp()
-                    ))
-                };
-                match image::load_from_memory(file.data()) {
-                    Ok(image) => {
-                        if uploader
-                            .handle(&image, &name, Some((500, 500)), Some((800, 800)), true)
-                            .is_err()
-                        {
-                            return HttpResponse::InternalServerError().finish();
-                        }
+                if let Err(res) =
+                    insert_asset(transaction.deref_mut(), &mut uploader, id, file, i as i16).await
+                {
+                    return res;
+                }
+            }
 
-                        match services::files::insert(
-                            transaction.deref_mut(),
-                            Some(file.name()),
-                            &format!(
-                                "{}.{}",
-                                &name.clone(),
-                                if image.color().has_alpha() {
-                                    "png"
-                                } else {
-                                    "jpg"
-                                }
-                            ),
-                        )
-                        .await
-                        {
-                            Ok(file_id) => {
-                                if services::projects::assets::insert(
-                                    transaction.deref_mut(),
-                                    id,
-                                    file_id,
-                                    i as i16,
-                                )
-                                .await
-                                .is_err()
-                                {
-                                    return HttpResponse::InternalServerError().finish();
-                                }
-                            }
-                            Err(_) => return HttpResponse::InternalServerError().finish(),
-                        }
-                    }
-                    Err(_) => return HttpResponse::InternalServerError().finish(),
+            for (i, (provider, embed_id)) in embeds.iter().enumerate() {
+                if services::projects::assets::insert_embed(
+                    transaction.deref_mut(),
+                    id,
+                    provider.as_str(),
+                    embed_id,
+                    (form.files.len() + i) as i16,
+                )
+                .await
+                .is_err()
+                {
+                    return HttpResponse::InternalServerError().finish();
                 }
             }
 
@@ -353,13 +512,16 @@ pub struct ProjectUpdateForm {
     // assets: Patch<Vec<ProjectUpdateAssetForm>>,
     assets: Patch<Vec<String>>,
     #[serde(skip_serializing)]
-    files: Option<Vec<actix_extract_multipart::File>>,
+    files: Option<Vec<File>>,
+    #[serde(skip_serializing)]
+    embeds: Option<Vec<String>>,
 }
 
 #[patch("/projects/{id}")]
 pub async fn update_project(
     pool: web::Data<PgPool>,
-    mut form: actix_extract_multipart::Multipart<ProjectUpdateForm>,
+    storage: web::Data<dyn Storage>,
+    mut form: Multipart<ProjectUpdateForm>,
     session: Identity,
     web::Path(id): web::Path<i16>,
 ) -> HttpResponse {
@@ -395,7 +557,12 @@ pub async fn update_project(
         form.description = Patch::Value(Some(description));
     }
 
-    let mut uploader = crate::utils::image::Uploader::new();
+    let embeds = match parse_embeds(&form.embeds) {
+        Ok(embeds) => embeds,
+        Err(res) => return res,
+    };
+
+    let mut uploader = Uploader::new(storage.clone().into_inner());
     let mut transaction = pool.begin().await.unwrap();
     let mut images_to_delete = vec![];
 
@@ -449,25 +616,22 @@ pub async fn update_project(
                     if let Some(true) = asset.to_delete {
                         #[derive(sqlx::FromRow)]
                         struct Asset {
-                            path: String,
+                            path: Option<String>,
+                            poster: Option<String>,
                         }
 
-                        match services::projects::assets::get::<Asset>(&pool, "path", asset.id)
-                            .await
+                        match services::projects::assets::get::<Asset>(
+                            &pool,
+                            "f.path AS path, p.path AS poster",
+                            asset.id,
+                        )
+                        .await
                         {
                             Ok(asset) => {
-                                let filename = asset.path.split('.').collect::<Vec<_>>();
-                                let filename = filename.get(0).unwrap();
-
-                                images_to_delete.append(
-                                    &mut [
-                                        format!("./uploads/mobile/{}", asset.path),
-                                        format!("./uploads/mobile/{}.webp", filename),
-                                        format!("./uploads/{}", asset.path),
-                                        format!("./uploads/{}.webp", filename),
-                                    ]
-                                    .to_vec(),
-                                );
+                                images_to_delete.append(&mut asset_files(
+                                    asset.path.as_deref(),
+                                    asset.poster.as_deref(),
+                                ));
                             }
                             Err(_) => return HttpResponse::InternalServerError().finish(),
                         }
@@ -491,68 +655,42 @@ pub async fn update_project(
         }
     }
 
-    if let Some(files) = &form.files {
-        if services::projects::assets::count(&pool, id).await >= 5 {
-            // cant insert more assets
-        } else {
-            let mut available_slots =
-                services::projects::assets::get_available_slots(transaction.deref_mut(), id).await;
-
-            for file in files {
-                let name = {
-                    use slugmin::slugify;
-
-                    slugify(&format!(
-                        "{}_{}",
-                        file.name(),
-                        chrono::Utc::now().timestamp()
-                    ))
-                };
-
-                match image::load_from_memory(file.data()) {
-                    Ok(image) => {
-                        if uploader
-                            .handle(&image, &name, Some((500, 500)), Some((800, 800)), true)
-                            .is_err()
-                        {
-                            return HttpResponse::InternalServerError().finish();
-                        }
+    let files = form.files.as_deref().unwrap_or_default();
 
-                        match services::files::insert(
-                            pool.get_ref(),
-                            Some(file.name()),
-                            &format!(
-                                "{}.{}",
-                                &name.clone(),
-                                if image.color().has_alpha() {
-                                    "png"
-                                } else {
-                                    "jpg"
-                                }
-                            ),
-                        )
-                        .await
-                        {
-                            Ok(file_id) => {
-                                if services::projects::assets::insert(
-                                    pool.get_ref(),
-                                    id,
-                                    file_id,
-                                    available_slots[0],
-                                )
-                                .await
-                                .is_err()
-                                {
-                                    return HttpResponse::InternalServerError().finish();
-                                }
-
-                                available_slots.remove(0);
-                            }
-                            Err(_) => return HttpResponse::InternalServerError().finish(),
-                        }
-                    }
-                    Err(_) => return HttpResponse::InternalServerError().finish(),
-                }
+    if !files.is_empty() || !embeds.is_empty() {
+        let mut available_slots =
+            services::projects::assets::get_available_slots(transaction.deref_mut(), id).await;
+
+        if files.len() + embeds.len() > available_slots.len() {
+            return HttpResponse::BadRequest().finish();
+        }
+
+        for file in files {
+            if let Err(res) = insert_asset(
+                transaction.deref_mut(),
+                &mut uploader,
+                id,
+                file,
+                available_slots.remove(0),
+            )
+            .await
+            {
+                return res;
+            }
+        }
+
+        for (provider, embed_id) in &embeds {
+            if services::projects::assets::insert_embed(
+                transaction.deref_mut(),
+                id,
+                provider.as_str(),
+                embed_id,
+                available_slots.remove(0),
+            )
+            .await
+            .is_err()
+            {
+                return HttpResponse::InternalServerError().finish();
             }
         }
     }
@@ -570,7 +708,7 @@ pub async fn update_project(
 
     transaction.commit().await.unwrap();
     uploader.clear();
-    crate::utils::image::remove_files(&images_to_delete);
+    crate::utils::image::remove_files(storage.into_inner(), images_to_delete).await;
 
     HttpResponse::Ok().finish()
 }
@@ -578,6 +716,7 @@ pub async fn update_project(
 #[delete("/projects/{id}")]
 async fn delete_project(
     pool: web::Data<PgPool>,
+    storage: web::Data<dyn Storage>,
     web::Path(id): web::Path<i16>,
     session: Identity,
 ) -> HttpResponse {
@@ -593,22 +732,14 @@ async fn delete_project(
     let mut files_to_delete = vec![];
 
     assets.iter().for_each(|asset| {
-        let filename = asset.path.split('.').collect::<Vec<_>>();
-        let filename = filename.get(0).unwrap();
-
-        files_to_delete.append(
-            &mut [
-                format!("./uploads/mobile/{}", asset.path),
-                format!("./uploads/mobile/{}.webp", filename),
-                format!("./uploads/{}", asset.path),
-                format!("./uploads/{}.webp", filename),
-            ]
-            .to_vec(),
-        );
+        files_to_delete.append(&mut asset_files(
+            asset.path.as_deref(),
+            asset.poster.as_deref(),
+        ));
     });
 
     services::projects::delete(&pool, id).await;
-    crate::utils::image::remove_files(&files_to_delete);
+    crate::utils::image::remove_files(storage.into_inner(), files_to_delete).await;
 
     HttpResponse::Ok().finish()
 }
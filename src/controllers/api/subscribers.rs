@@ -0,0 +1,114 @@
+use crate::services::subscribers;
+use actix_identity::Identity;
+use actix_web::{delete, get, http::header, web, HttpResponse};
+use sqlx::PgPool;
+
+/// Field of the CSV export, quoted. A leading character a spreadsheet would
+/// read as a formula is escaped
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(&['=', '+', '-', '@'][..]) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+#[get("")]
+pub async fn get_subscribers(pool: web::Data<PgPool>, session: Identity) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    match subscribers::get_all(pool.as_ref()).await {
+        Ok(subscribers) => HttpResponse::Ok().json(subscribers),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Every subscriber as CSV
+#[get("/export")]
+pub async fn export(pool: web::Data<PgPool>, session: Identity) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let subscribers = match subscribers::get_all(pool.as_ref()).await {
+        Ok(subscribers) => subscribers,
+        Err(e) => {
+            eprintln!("{}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let mut csv = String::from("email,status,date,confirmed_date,unsubscribed_date\n");
+
+    for subscriber in subscribers {
+        let fields = [
+            csv_field(&subscriber.email),
+            csv_field(&subscriber.status),
+            csv_field(&subscriber.date.to_rfc3339()),
+            subscriber
+                .confirmed_date
+                .map(|date| csv_field(&date.to_rfc3339()))
+                .unwrap_or_default(),
+            subscriber
+                .unsubscribed_date
+                .map(|date| csv_field(&date.to_rfc3339()))
+                .unwrap_or_default(),
+        ];
+
+        csv += &fields.join(",");
+        csv += "\n";
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"subscribers_{}.csv\"",
+                chrono::Utc::now().format("%Y-%m-%d")
+            ),
+        )
+        .body(csv)
+}
+
+#[delete("/{id}")]
+pub async fn delete_subscriber(
+    pool: web::Data<PgPool>,
+    session: Identity,
+    web::Path(id): web::Path<i32>,
+) -> HttpResponse {
+    if session.identity().is_none() {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    match subscribers::delete(pool.as_ref(), id).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(
+            super::csv_field("jean.dupont@example.com"),
+            "\"jean.dupont@example.com\""
+        );
+        assert_eq!(super::csv_field("a\"b"), "\"a\"\"b\"");
+        assert_eq!(
+            super::csv_field("=1+1@example.com"),
+            "\"'=1+1@example.com\""
+        );
+    }
+}
@@ -1,9 +1,37 @@
 use super::metrics;
-use crate::services;
-use actix_web::{get, web, Error, HttpRequest, HttpResponse};
+use crate::{
+    services::{
+        self,
+        attempts::Kind,
+        blog::{
+            articles::{
+                blocks::{self, Block},
+                markdown, Format,
+            },
+            comments::{self, Comment, NewComment, Status},
+        },
+    },
+    utils::{
+        html,
+        mail::{self, templates, Addresses},
+        spam::{self, Submission},
+    },
+};
+use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
 use askama_actix::{Template, TemplateIntoResponse};
 use chrono::Datelike;
-use sqlx::{FromRow, PgPool};
+use serde::Deserialize;
+use sqlx::{types::Json, FromRow, PgPool};
+
+/// Comments an IP can post in an hour before the next ones are put in the spam
+/// folder
+const COMMENT_LIMIT: i64 = 5;
+
+/// Comments an IP can post in an hour before the next ones are refused
+const COMMENT_HARD_LIMIT: i64 = 20;
+
+/// Maximum length (in characters) of a comment
+const COMMENT_MAX_LENGTH: usize = 2000;
 
 #[derive(FromRow)]
 struct Article {
@@ -13,6 +41,7 @@ struct Article {
     date: String,
     international_date: String,
     cover: String,
+    cover_placeholder: Option<String>,
 }
 
 #[derive(FromRow)]
@@ -28,6 +57,38 @@ struct Page {
     description: Option<String>,
 }
 
+/// Approved comment and its approved replies
+struct Thread {
+    comment: Comment,
+    replies: Vec<Comment>,
+}
+
+/// Threads of the comments, in the order of the comments. A reply whose
+/// comment is not approved is left out
+fn threads(comments: Vec<Comment>) -> Vec<Thread> {
+    let (comments, replies): (Vec<_>, Vec<_>) = comments
+        .into_iter()
+        .partition(|comment| comment.parent_id.is_none());
+    let mut threads = comments
+        .into_iter()
+        .map(|comment| Thread {
+            comment,
+            replies: vec![],
+        })
+        .collect::<Vec<_>>();
+
+    for reply in replies {
+        if let Some(thread) = threads
+            .iter_mut()
+            .find(|thread| Some(thread.comment.id) == reply.parent_id)
+        {
+            thread.replies.push(reply);
+        }
+    }
+
+    threads
+}
+
 #[get("")]
 async fn index(req: HttpRequest, pool: web::Data<PgPool>) -> Result<HttpResponse, Error> {
     if let Ok(page) = services::pages::get::<Page>(&pool, "id, title, description", "/blog").await {
@@ -41,7 +102,8 @@ async fn index(req: HttpRequest, pool: web::Data<PgPool>) -> Result<HttpResponse
                 ba.description,
                 TO_CHAR(ba.date, 'DD/MM/YYYY') AS "date",
                 TO_CHAR(ba.date, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS international_date,
-                f.path AS cover"#,
+                f.path AS cover,
+                f.placeholder AS cover_placeholder"#,
                 Some(true),
                 None,
                 None
@@ -99,7 +161,7 @@ async fn show_category(
     }
 
     let (metric_id, category, categories, articles, settings) = futures::join!(
-        metrics::add(&pool, &req, services::metrics::BelongsTo::BlogPost(id)),
+        metrics::add(&pool, &req, services::metrics::BelongsTo::BlogCategory(id)),
         services::blog::categories::get::<CategoryDetails>(&pool, "name, description, is_seo", id),
         services::blog::categories::get_all::<Category>(&pool, "name, uri", Some(true), None),
         services::blog::articles::get_all::<Article>(
@@ -109,7 +171,8 @@ async fn show_category(
             ba.description,
             TO_CHAR(ba.date, 'DD/MM/YYYY') AS "date",
             TO_CHAR(ba.date, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS international_date,
-            f.path AS cover"#,
+            f.path AS cover,
+            f.placeholder AS cover_placeholder"#,
             Some(true),
             None,
             Some(id)
@@ -166,8 +229,11 @@ async fn show_article(
         title: String,
         category_id: Option<i16>,
         cover_path: String,
+        cover_placeholder: Option<String>,
         description: Option<String>,
-        content: String,
+        format: String,
+        blocks: Json<Vec<Block>>,
+        markdown: Option<String>,
         date: String,
         international_date: String,
         // As international date format
@@ -181,8 +247,11 @@ async fn show_article(
         r#"title,
     category_id,
     f.path AS cover_path,
+    f.placeholder AS cover_placeholder,
     description,
-    content,
+    format,
+    blocks,
+    markdown,
     TO_CHAR(date, 'DD/MM/YYYY') AS "date",
     TO_CHAR(date, 'YYYY-MM-DD"T"HH24:MI:SS"Z"') AS international_date,
     CASE
@@ -197,7 +266,7 @@ async fn show_article(
     )
     .await
     {
-        Ok(mut article) => {
+        Ok(article) => {
             match article.is_published {
                 None | Some(false) => return Ok(HttpResponse::NotFound().finish()),
                 _ => (),
@@ -206,12 +275,19 @@ async fn show_article(
             #[derive(Template)]
             #[template(path = "pages/blog/article.html")]
             struct BlogArticle {
+                id: i16,
                 article: Article,
+                /// Rendered blocks of the article
+                content: String,
                 category: Option<Category>,
                 categories: Vec<Category>,
                 year: i32,
                 metric_token: Option<String>,
-                settings: services::settings::Settings
+                settings: services::settings::Settings,
+                threads: Vec<Thread>,
+                comments_open: bool,
+                spam_token: String,
+                pow_difficulty: u32,
             }
 
             #[derive(FromRow)]
@@ -230,8 +306,8 @@ async fn show_article(
                 );
             }
 
-            let (metric_id, images, categories, settings) = futures::join!(
-                metrics::add(&pool, &req, services::metrics::BelongsTo::BlogPost(id)),
+            let (metric_id, images, categories, settings, approved, comments_open) = futures::join!(
+                metrics::add(&pool, &req, services::metrics::BelongsTo::BlogArticle(id)),
                 services::blog::articles::images::get_all(&pool, id),
                 services::blog::categories::get_all::<Category>(
                     &pool,
@@ -239,31 +315,23 @@ async fn show_article(
                     Some(true),
                     None
                 ),
-                services::settings::get(&pool)
+                services::settings::get(&pool),
+                comments::get_approved(&pool, id),
+                comments::are_open(&pool, id)
             );
-
-            for image in &images {
-                let filename = image.path.split('.').collect::<Vec<_>>();
-                let filename = filename.get(0).unwrap();
-
-                article.content = article.content.replacen(
-                    &format!("[[{}]]", image.id),
-                    &format!(
-                        r#"<picture class="lazy">
-                            <source data-srcset="/uploads/mobile/{}.webp" media="(max-width: 768px)" type="image/webp" />
-                            <source data-srcset="/uploads/mobile/{}" media="(max-width: 768px)" />
-                            <source data-srcset="/uploads/{}.webp" media="(min-width: 768px)" type="image/webp" />
-
-                            <img data-src="/uploads/{}" />
-                        </picture>"#,
-                        filename,
-                        image.path,
-                        filename,
-                        image.path
-                    ),
-                    1
-                );
-            }
+            let (approved, comments_open) = match (approved, comments_open) {
+                (Ok(approved), Ok(comments_open)) => (approved, comments_open),
+                (Err(e), _) | (_, Err(e)) => {
+                    eprintln!("{}", e);
+                    return Ok(HttpResponse::InternalServerError().finish());
+                }
+            };
+
+            let content = if article.format == Format::Markdown.as_str() {
+                markdown::render(article.markdown.as_deref().unwrap_or_default(), &images)
+            } else {
+                blocks::render(&article.blocks, &images)
+            };
 
             let mut token: Option<String> = None;
             if let Ok(Some(id)) = metric_id {
@@ -271,12 +339,18 @@ async fn show_article(
             }
 
             BlogArticle {
+                id,
                 article,
+                content,
                 category,
                 categories,
                 year: chrono::Utc::now().year(),
                 metric_token: token,
-                settings: settings.unwrap()
+                settings: settings.unwrap(),
+                threads: threads(approved),
+                comments_open,
+                spam_token: spam::token(),
+                pow_difficulty: spam::difficulty(),
             }
             .into_response()
         }
@@ -284,6 +358,181 @@ async fn show_article(
     }
 }
 
+#[derive(Deserialize)]
+pub struct CommentForm {
+    author: String,
+    email: Option<String>,
+    content: String,
+    /// Comment answered, the reply joins its thread
+    parent_id: Option<i32>,
+    /// Hidden field, only filled by bots
+    website: Option<String>,
+    /// Signed token given with the form, see `utils::spam`
+    token: Option<String>,
+    proof: Option<u64>,
+}
+
+/// Comment an article, the comment is held for moderation
+#[post("/articles/{id}/comments")]
+async fn add_comment(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    web::Path(id): web::Path<i16>,
+    form: web::Form<CommentForm>,
+) -> HttpResponse {
+    let ip = super::client_ip(&req);
+
+    match comments::are_open(&pool, id).await {
+        Ok(true) => (),
+        Ok(false) => return HttpResponse::Forbidden().finish(),
+        Err(e) => {
+            eprintln!("{}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let attempts_counter = services::attempts::count(&pool, &ip, Kind::Comment).await;
+
+    if attempts_counter >= COMMENT_HARD_LIMIT {
+        return HttpResponse::TooManyRequests().finish();
+    }
+
+    let author = form.author.trim();
+    let email = form
+        .email
+        .as_deref()
+        .map(str::trim)
+        .filter(|email| !email.is_empty());
+    let content = form.content.trim();
+
+    if author.chars().count() < 2
+        || author.chars().count() > 120
+        || email.map_or(false, |email| {
+            email.len() > 320 || !mail::is_valid_email(email)
+        })
+        || content.is_empty()
+        || content.chars().count() > COMMENT_MAX_LENGTH
+    {
+        return HttpResponse::BadRequest().finish();
+    }
+
+    // Threads are one level deep, a reply to a reply answers its comment
+    let parent_id = match form.parent_id {
+        Some(parent_id) => match comments::get(&pool, parent_id).await {
+            Ok(parent) if parent.article_id == id && parent.status == Status::Approved.as_str() => {
+                Some(parent.parent_id.unwrap_or(parent.id))
+            }
+            Ok(_) | Err(sqlx::Error::RowNotFound) => return HttpResponse::BadRequest().finish(),
+            Err(e) => {
+                eprintln!("{}", e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        },
+        None => None,
+    };
+
+    if let Err(e) =
+        services::attempts::add(&pool, email.unwrap_or_default(), &ip, Kind::Comment).await
+    {
+        eprintln!("{}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let verdict = spam::verdict(
+        &pool,
+        &Submission {
+            honeypot: form.website.as_deref(),
+            token: form.token.as_deref(),
+            proof: form.proof,
+            fields: &[author],
+            content,
+        },
+        attempts_counter,
+        COMMENT_LIMIT,
+    )
+    .await;
+    let status = match verdict {
+        Ok(Ok(_)) => Status::Pending,
+        Ok(Err(reason)) => {
+            eprintln!("Comment put in the spam folder: {}", reason);
+            Status::Spam
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let cleaned_content = html::clean(content);
+
+    if cleaned_content.is_empty() {
+        return HttpResponse::BadRequest().finish();
+    }
+
+    if let Err(e) = comments::add(
+        &pool,
+        &NewComment {
+            article_id: id,
+            parent_id,
+            author,
+            email,
+            content: &cleaned_content,
+            status,
+            is_author: false,
+        },
+    )
+    .await
+    {
+        eprintln!("{}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    // A spam gets the same response as a comment, not to tell the bots which
+    // check failed
+    if status == Status::Spam {
+        return HttpResponse::Ok().finish();
+    }
+
+    #[derive(FromRow)]
+    struct Article {
+        title: String,
+        uri: Option<String>,
+    }
+
+    let article = match services::blog::articles::get::<Article>(&pool, "title, uri", id).await {
+        Ok(article) => article,
+        Err(e) => {
+            eprintln!("{}", e);
+            return HttpResponse::Ok().finish();
+        }
+    };
+    let addresses = Addresses::from_env();
+    let url = addresses.url(&format!(
+        "/blog/articles/{}",
+        article.uri.as_deref().unwrap_or_default()
+    ));
+    let moderation_url = addresses.url("/admin/commentaires");
+    let notification = templates::NewComment {
+        author,
+        article: &article.title,
+        url: &url,
+        content,
+        moderation_url: &moderation_url,
+    };
+
+    for admin in &addresses.admins {
+        match addresses.render(admin, &notification) {
+            Ok(email) => {
+                if let Err(e) = services::emails::enqueue(&pool, &email).await {
+                    eprintln!("{}", e);
+                }
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    HttpResponse::Ok().finish()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::controllers;
@@ -427,4 +676,68 @@ mod tests {
 
         assert!(res.status().is_success());
     }
+
+    #[actix_rt::test]
+    async fn test_add_comment() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let mut app = test::init_service(
+            App::new()
+                .data(pool.clone())
+                .service(web::scope("/blog").service(controllers::blog::add_comment)),
+        )
+        .await;
+        let res = test::TestRequest::post()
+            .uri("/blog/articles/3/comments")
+            .set_form(&serde_json::json!({
+                "author": "J",
+                "content": "Très bel article",
+            }))
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        // Not published, so closed
+        let res = test::TestRequest::post()
+            .uri("/blog/articles/1/comments")
+            .set_form(&serde_json::json!({
+                "author": "Jean",
+                "content": "Très bel article",
+            }))
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_threads() {
+        use crate::services::blog::comments::Comment;
+
+        let comment = |id: i32, parent_id: Option<i32>| Comment {
+            id,
+            article_id: 3,
+            parent_id,
+            author: String::from("Jean"),
+            email: None,
+            content: String::from("Très bel article"),
+            status: String::from("approved"),
+            is_author: false,
+            date: chrono::Utc::now(),
+        };
+        let threads = super::threads(vec![
+            comment(1, None),
+            comment(2, None),
+            comment(3, Some(1)),
+            // Its comment is not approved
+            comment(4, Some(5)),
+        ]);
+
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].replies.len(), 1);
+        assert_eq!(threads[0].replies[0].id, 3);
+        assert!(threads[1].replies.is_empty());
+    }
 }
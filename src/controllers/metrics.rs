@@ -1,29 +1,119 @@
 use std::str::FromStr;
 
-use crate::{services, utils::ua::UserAgent};
-use actix_web::{get, http::HeaderValue, post, web, FromRequest, HttpRequest, HttpResponse};
+use crate::{
+    services,
+    utils::{
+        traffic::{Referrer, Utm},
+        ua::UserAgent,
+    },
+};
+use actix_web::{get, http::header, post, web, FromRequest, HttpRequest, HttpResponse};
 use chrono::{DateTime, Utc};
-use ring::digest;
+use regex::Regex;
+use ring::hmac;
 use serde::{Deserialize, Serialize};
 use sqlx::{types::Uuid, FromRow, PgPool};
 
+/// Name of the cookie holding the choice of the visitor: `granted` or `denied`
+pub const CONSENT_COOKIE: &str = "consent";
+
+/// Maximum size (in bytes) of the serialized properties of an event
+const EVENT_PROPERTIES_MAX_SIZE: usize = 1024;
+
+/// The visitor did not opt out of the tracking with the `DNT`, `Sec-GPC` or
+/// `GAR-LOG` headers
+pub fn is_tracking_allowed(req: &HttpRequest) -> bool {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+
+    header("DNT") != Some("1")
+        && header("Sec-GPC") != Some("1")
+        && header("GAR-LOG") != Some("false")
+}
+
+/// The visitor accepted the session cookie
+pub fn has_consent(req: &HttpRequest) -> bool {
+    matches!(req.cookie(CONSENT_COOKIE), Some(cookie) if cookie.value() == "granted")
+}
+
+/// HMAC of the IP address keyed with the salt of the day. The salt is deleted
+/// the next day, so a hash can not be brute-forced back to its IP address and
+/// the visitors can not be followed from one day to another
+async fn hash_ip(pool: &PgPool, req: &HttpRequest) -> Result<String, sqlx::Error> {
+    let salt = services::metrics::salts::get_today(pool).await?;
+    let ip = req
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_default();
+    let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &salt), ip.as_bytes());
+
+    Ok(tag
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Session of the visitor having accepted the cookie, its expiration pushed
+/// back. An unknown or expired session is replaced by a new one when the hash
+/// of the IP address to create it with is given
+async fn resume_session(
+    pool: &PgPool,
+    req: &HttpRequest,
+    sid: Option<&str>,
+    renew_with: Option<&str>,
+) -> Result<Option<(Uuid, DateTime<Utc>)>, sqlx::Error> {
+    let sid = match sid {
+        Some(sid) if has_consent(req) => sid,
+        _ => return Ok(None),
+    };
+
+    if let Ok(sid) = Uuid::from_str(sid) {
+        if let Some(expiration_date) = services::metrics::sessions::touch(pool, sid).await? {
+            return Ok(Some((sid, expiration_date)));
+        }
+    }
+
+    match renew_with {
+        Some(digest_ip) => services::metrics::sessions::add(pool, digest_ip)
+            .await
+            .map(Some),
+        None => Ok(None),
+    }
+}
+
+/// `sid` cookie expiring with its session
+fn session_cookie(sid: Uuid, expiration_date: DateTime<Utc>) -> String {
+    format!(
+        "sid={}; Expires={}; Path=/; SameSite=Strict; Secure",
+        sid.to_hyphenated(),
+        expiration_date.format("%a, %d %b %Y %H:%M:%S GMT")
+    )
+}
+
 pub async fn add(
     pool: &PgPool,
     req: &HttpRequest,
     belongs_to: services::metrics::BelongsTo,
 ) -> Result<Option<Uuid>, actix_web::Error> {
-    if let Some(gar_log) = req.headers().get("GAR-LOG") {
-        if gar_log == HeaderValue::from_static("false") {
-            return Ok(None);
-        }
+    if !is_tracking_allowed(req) {
+        return Ok(None);
     }
 
     let ua = UserAgent::from_request(req, &mut actix_web::dev::Payload::None).await?;
-    let digest_ip = digest::digest(
-        &digest::SHA256,
-        req.peer_addr().unwrap().ip().to_string().as_bytes(),
+    let digest_ip = hash_ip(pool, req)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let referrer = Referrer::parse(
+        req.headers()
+            .get(actix_web::http::header::REFERER)
+            .and_then(|referrer| referrer.to_str().ok()),
+        req.connection_info().host(),
     );
-    let digest_ip = format!("{:?}", digest_ip);
 
     match services::metrics::add(
         &pool,
@@ -33,13 +123,9 @@ pub async fn add(
         ua.name.clone(),
         ua.os.clone(),
         ua.category.clone(),
-        match req.headers().get(actix_web::http::header::REFERER) {
-            Some(referer) => match referer.to_str() {
-                Ok(referer) => Some(referer.to_string()),
-                _ => None,
-            },
-            _ => None,
-        },
+        &referrer,
+        &Utm::from_query(req.query_string()),
+        ua.is_bot,
     )
     .await
     {
@@ -52,14 +138,23 @@ pub async fn add(
 pub enum BelongsTo {
     Page,
     Project,
-    BlogPost,
+    /// `BlogPost` is still sent by the scripts cached before the rename
+    #[serde(alias = "BlogPost")]
+    BlogArticle,
+    BlogCategory,
 }
 
 #[derive(Deserialize)]
 pub struct PageInformations {
     path: String,
-    sid: String,
+    /// Only sent when the visitor accepted the session cookie
+    sid: Option<String>,
     belongs_to: BelongsTo,
+    /// Query string of the URL, holding the UTM parameters of a landing view
+    search: Option<String>,
+    /// `document.referrer`, only sent for the first view of the page load:
+    /// the next views are navigations inside the site
+    referrer: Option<String>,
 }
 
 #[get("/metrics/token")]
@@ -68,6 +163,10 @@ pub async fn create(
     req: HttpRequest,
     infos: web::Query<PageInformations>,
 ) -> HttpResponse {
+    if !is_tracking_allowed(&req) {
+        return HttpResponse::NoContent().finish();
+    }
+
     let mut id: Option<i16> = None;
     match infos.belongs_to {
         BelongsTo::Page => {
@@ -84,26 +183,26 @@ pub async fn create(
                 }
             }
         }
-        BelongsTo::BlogPost | BelongsTo::Project => {
+        BelongsTo::BlogArticle | BelongsTo::BlogCategory | BelongsTo::Project => {
             match infos.path.split('-').collect::<Vec<_>>().last() {
-                Some(post_id) => match post_id.parse::<i16>() {
-                    Ok(post_id) => match infos.belongs_to {
-                        BelongsTo::BlogPost => {
-                            if !services::blog::articles::exists(&pool, post_id).await {
-                                return HttpResponse::NotFound().finish();
+                Some(content_id) => match content_id.parse::<i16>() {
+                    Ok(content_id) => {
+                        let exists = match infos.belongs_to {
+                            BelongsTo::BlogArticle => {
+                                services::blog::articles::exists(&pool, content_id).await
                             }
-
-                            id = Some(post_id);
-                        }
-                        BelongsTo::Project => {
-                            if !services::projects::exists(&pool, post_id).await {
-                                return HttpResponse::NotFound().finish();
+                            BelongsTo::BlogCategory => {
+                                services::blog::categories::exists(&pool, content_id).await
                             }
+                            _ => services::projects::exists(&pool, content_id).await,
+                        };
 
-                            id = Some(post_id)
+                        if !exists {
+                            return HttpResponse::NotFound().finish();
                         }
-                        _ => (),
-                    },
+
+                        id = Some(content_id);
+                    }
                     _ => return HttpResponse::InternalServerError().finish(),
                 },
                 _ => return HttpResponse::InternalServerError().finish(),
@@ -112,40 +211,56 @@ pub async fn create(
     }
 
     if let Ok(ua) = UserAgent::from_request(&req, &mut actix_web::dev::Payload::None).await {
-        let sid = match Uuid::from_str(infos.sid.as_str()) {
-            Ok(val) => val,
-            Err(_) => return HttpResponse::BadRequest().finish(),
+        let digest_ip = match hash_ip(&pool, &req).await {
+            Ok(digest_ip) => digest_ip,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+        let session =
+            match resume_session(&pool, &req, infos.sid.as_deref(), Some(&digest_ip)).await {
+                Ok(session) => session,
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            };
+        let sid = session.map(|(sid, _)| sid);
+        let referrer = match &infos.referrer {
+            Some(referrer) => Referrer::parse(Some(referrer), req.connection_info().host()),
+            None => Referrer::internal(),
         };
+        let utm = Utm::from_query(infos.search.as_deref().unwrap_or_default());
 
-        let digest_ip = digest::digest(
-            &digest::SHA256,
-            req.peer_addr().unwrap().ip().to_string().as_bytes(),
-        );
-        let digest_ip = format!("{:?}", digest_ip);
+        if let Some(sid) = sid.filter(|_| !utm.is_empty()) {
+            if let Err(e) = services::metrics::sessions::set_campaign(&pool, sid, &utm).await {
+                eprintln!("{}", e);
+            }
+        }
 
         if let Ok(metric_id) = services::metrics::add(
             &pool,
             match infos.belongs_to {
                 BelongsTo::Project => services::metrics::BelongsTo::Project(id.unwrap()),
-                BelongsTo::BlogPost => services::metrics::BelongsTo::BlogPost(id.unwrap()),
+                BelongsTo::BlogArticle => services::metrics::BelongsTo::BlogArticle(id.unwrap()),
+                BelongsTo::BlogCategory => services::metrics::BelongsTo::BlogCategory(id.unwrap()),
                 BelongsTo::Page => services::metrics::BelongsTo::Page(id.unwrap()),
             },
-            Some(sid),
+            sid,
             &digest_ip,
             ua.name.clone(),
             ua.os.clone(),
             ua.category.clone(),
-            match req.headers().get(actix_web::http::header::REFERER) {
-                Some(referer) => match referer.to_str() {
-                    Ok(referer) => Some(referer.to_string()),
-                    _ => None,
-                },
-                _ => None,
-            },
+            &referrer,
+            &utm,
+            ua.is_bot,
         )
         .await
         {
-            return HttpResponse::Ok().body(metric_id.to_hyphenated().to_string());
+            let mut res = HttpResponse::Ok();
+
+            // The expiration of the session has been pushed back, or a new
+            // session replaced the expired one
+            if let Some((sid, expiration_date)) = session {
+                res.header(header::SET_COOKIE, session_cookie(sid, expiration_date));
+            }
+
+            return res.body(metric_id.to_hyphenated().to_string());
         }
     }
 
@@ -160,30 +275,121 @@ pub struct Token {
 }
 
 #[post("/metrics/log")]
-pub async fn log(pool: web::Data<PgPool>, form: web::Form<Token>) -> HttpResponse {
+pub async fn log(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    form: web::Form<Token>,
+) -> HttpResponse {
     let token = if let Ok(token) = Uuid::from_str(&form.token) {
         token
     } else {
         return HttpResponse::BadRequest().finish();
     };
-    let session_id: Option<Uuid> = match &form.session_id {
-        Some(val) => match Uuid::from_str(val.as_str()) {
-            Ok(res) => Some(res),
-            Err(_) => None,
-        },
-        None => None,
-    };
 
     if !services::metrics::exists(&pool, token).await {
         return HttpResponse::NotFound().finish();
     }
 
-    match services::metrics::update_end_date(&pool, session_id, token).await {
-        Ok(_) => HttpResponse::Ok().finish(),
+    // An expired session is not renewed here: the visitor is leaving the view
+    // and the next one will get a new session
+    let session = match resume_session(&pool, &req, form.session_id.as_deref(), None).await {
+        Ok(session) => session,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    match services::metrics::update_end_date(&pool, session.map(|(sid, _)| sid), token).await {
+        Ok(_) => {
+            let mut res = HttpResponse::Ok();
+
+            if let Some((sid, expiration_date)) = session {
+                res.header(header::SET_COOKIE, session_cookie(sid, expiration_date));
+            }
+
+            res.finish()
+        }
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
 }
 
+#[derive(Deserialize)]
+pub struct Event {
+    /// Snake case name, e.g. `contact_form_sent`
+    name: String,
+    /// JSON object describing the event: project opened, URL of the link...
+    properties: Option<serde_json::Value>,
+    /// Token of the view the event happened on
+    token: Option<String>,
+    /// Only sent when the visitor accepted the session cookie
+    sid: Option<String>,
+}
+
+#[post("/metrics/event")]
+pub async fn create_event(
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+    event: web::Json<Event>,
+) -> HttpResponse {
+    if !is_tracking_allowed(&req) {
+        return HttpResponse::NoContent().finish();
+    }
+
+    let name_regex = Regex::new(r"^[a-z][a-z0-9_]{0,59}$").unwrap();
+    if !name_regex.is_match(&event.name) {
+        return HttpResponse::BadRequest().finish();
+    }
+
+    let properties = match &event.properties {
+        None | Some(serde_json::Value::Null) => None,
+        Some(properties @ serde_json::Value::Object(_))
+            if properties.to_string().len() <= EVENT_PROPERTIES_MAX_SIZE =>
+        {
+            Some(properties.clone())
+        }
+        _ => return HttpResponse::BadRequest().finish(),
+    };
+
+    // The view of the token can have been purged or not recorded at all
+    let metric_id = match &event.token {
+        Some(token) => match Uuid::from_str(token) {
+            Ok(token) if services::metrics::exists(&pool, token).await => Some(token),
+            Ok(_) => None,
+            Err(_) => return HttpResponse::BadRequest().finish(),
+        },
+        None => None,
+    };
+    let session_id = match resume_session(&pool, &req, event.sid.as_deref(), None).await {
+        Ok(session) => session.map(|(sid, _)| sid),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let ua = match UserAgent::from_request(&req, &mut actix_web::dev::Payload::None).await {
+        Ok(ua) => ua,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+    let digest_ip = match hash_ip(&pool, &req).await {
+        Ok(digest_ip) => digest_ip,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    match services::metrics::events::add(
+        &pool,
+        &event.name,
+        properties,
+        metric_id,
+        session_id,
+        &digest_ip,
+        ua.is_bot,
+    )
+    .await
+    {
+        Ok(_) => HttpResponse::Created().finish(),
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
 // ------------------------------------------------------------------------------ //
 // -------------------------------- USER SESSION -------------------------------- //
 // ------------------------------------------------------------------------------ //
@@ -196,11 +402,14 @@ pub struct SessionData {
 
 #[get("/metrics/session")]
 pub async fn create_session(pool: web::Data<PgPool>, req: HttpRequest) -> HttpResponse {
-    let digest_ip = digest::digest(
-        &digest::SHA256,
-        req.peer_addr().unwrap().ip().to_string().as_bytes(),
-    );
-    let digest_ip = format!("{:?}", digest_ip);
+    if !is_tracking_allowed(&req) || !has_consent(&req) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let digest_ip = match hash_ip(&pool, &req).await {
+        Ok(digest_ip) => digest_ip,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
 
     if let Ok(session_data) = services::metrics::sessions::add(&pool, &digest_ip).await {
         let sid = session_data.0.to_hyphenated().to_string();
@@ -210,3 +419,306 @@ pub async fn create_session(pool: web::Data<PgPool>, req: HttpRequest) -> HttpRe
 
     HttpResponse::InternalServerError().finish()
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::create_pool;
+    use actix_web::{cookie::Cookie, test, App};
+    use dotenv::dotenv;
+    use sqlx::types::Uuid;
+    use std::str::FromStr;
+
+    /// Request a token for a path and return the columns the view has been
+    /// attributed to: `page_id`, `project_id`, `article_id` and `category_id`
+    async fn create_token(
+        path: &str,
+        belongs_to: &str,
+    ) -> (Option<i16>, Option<i16>, Option<i16>, Option<i16>) {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let (sid, _) = crate::services::metrics::sessions::add(&pool, "test")
+            .await
+            .unwrap();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(super::create)).await;
+        let res = test::TestRequest::get()
+            .uri(&format!(
+                "/metrics/token?path={}&sid={}&belongs_to={}",
+                path,
+                sid.to_hyphenated(),
+                belongs_to
+            ))
+            .peer_addr("127.0.0.1:8080".parse().unwrap())
+            .header(
+                "User-Agent",
+                "Mozilla/5.0 (X11; Linux x86_64; rv:95.0) Gecko/20100101 Firefox/95.0",
+            )
+            .send_request(&mut app)
+            .await;
+
+        assert!(res.status().is_success());
+
+        let token =
+            Uuid::from_str(std::str::from_utf8(&test::read_body(res).await).unwrap()).unwrap();
+        let row = sqlx::query!(
+            "SELECT page_id, project_id, article_id, category_id FROM metrics WHERE id = $1",
+            token
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        (row.page_id, row.project_id, row.article_id, row.category_id)
+    }
+
+    #[actix_rt::test]
+    async fn test_create_token_page() {
+        assert_eq!(create_token("/", "Page").await, (Some(1), None, None, None));
+    }
+
+    #[actix_rt::test]
+    async fn test_create_token_project() {
+        assert_eq!(
+            create_token("/portfolio/lorem-1", "Project").await,
+            (None, Some(1), None, None)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_create_token_blog_article() {
+        assert_eq!(
+            create_token(
+                "/blog/articles/les-aventures-de-lulu-partie-3",
+                "BlogArticle"
+            )
+            .await,
+            (None, None, Some(3), None)
+        );
+        assert_eq!(
+            create_token("/blog/articles/les-aventures-de-lulu-partie-3", "BlogPost").await,
+            (None, None, Some(3), None)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_create_token_blog_category() {
+        assert_eq!(
+            create_token("/blog/categories/print-1", "BlogCategory").await,
+            (None, None, None, Some(1))
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_create_token_do_not_track() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(super::create)).await;
+
+        for (name, value) in &[("DNT", "1"), ("Sec-GPC", "1"), ("GAR-LOG", "false")] {
+            let res = test::TestRequest::get()
+                .uri("/metrics/token?path=/&belongs_to=Page")
+                .peer_addr("127.0.0.1:8080".parse().unwrap())
+                .header(*name, *value)
+                .send_request(&mut app)
+                .await;
+
+            assert_eq!(res.status(), 204, "{}", name);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_create_session_consent() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(super::create_session)).await;
+        let res = test::TestRequest::get()
+            .uri("/metrics/session")
+            .peer_addr("127.0.0.1:8080".parse().unwrap())
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), 403);
+
+        let res = test::TestRequest::get()
+            .uri("/metrics/session")
+            .peer_addr("127.0.0.1:8080".parse().unwrap())
+            .cookie(Cookie::new(super::CONSENT_COOKIE, "denied"))
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), 403);
+
+        let res = test::TestRequest::get()
+            .uri("/metrics/session")
+            .peer_addr("127.0.0.1:8080".parse().unwrap())
+            .cookie(Cookie::new(super::CONSENT_COOKIE, "granted"))
+            .send_request(&mut app)
+            .await;
+
+        assert!(res.status().is_success());
+
+        let body: serde_json::Value = test::read_body_json(res).await;
+        let sid = Uuid::from_str(body["sid"].as_str().unwrap()).unwrap();
+        let res = sqlx::query!("SELECT ip FROM metric_sessions WHERE id = $1", sid)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        let ip = res.ip.unwrap();
+
+        // Hex encoded HMAC, not the `Debug` output of a plain digest
+        assert_eq!(ip.len(), 64);
+        assert!(ip.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[actix_rt::test]
+    async fn test_create_event() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let (sid, _) = crate::services::metrics::sessions::add(&pool, "test")
+            .await
+            .unwrap();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(super::create_event)).await;
+        let res = test::TestRequest::post()
+            .uri("/metrics/event")
+            .peer_addr("127.0.0.1:8080".parse().unwrap())
+            .cookie(Cookie::new(super::CONSENT_COOKIE, "granted"))
+            .set_json(&serde_json::json!({
+                "name": "project_opened",
+                "properties": { "project_id": 1 },
+                "sid": sid.to_hyphenated().to_string()
+            }))
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), 201);
+
+        let res = sqlx::query!(
+            r#"SELECT properties FROM metric_events
+                WHERE session_id = $1 AND "name" = 'project_opened'"#,
+            sid
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(res.properties, Some(serde_json::json!({ "project_id": 1 })));
+
+        for body in &[
+            serde_json::json!({ "name": "Project opened" }),
+            serde_json::json!({ "name": "project_opened", "properties": [1] }),
+            serde_json::json!({ "name": "project_opened", "token": "lorem" }),
+        ] {
+            let res = test::TestRequest::post()
+                .uri("/metrics/event")
+                .peer_addr("127.0.0.1:8080".parse().unwrap())
+                .set_json(body)
+                .send_request(&mut app)
+                .await;
+
+            assert_eq!(res.status(), 400, "{}", body);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_create_token_expired_session() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let (sid, _) = crate::services::metrics::sessions::add(&pool, "test")
+            .await
+            .unwrap();
+
+        sqlx::query!(
+            "UPDATE metric_sessions SET expiration_date = NOW() - INTERVAL '1 minute' WHERE id = $1",
+            sid
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(super::create)).await;
+        let res = test::TestRequest::get()
+            .uri(&format!(
+                "/metrics/token?path=/&belongs_to=Page&sid={}",
+                sid.to_hyphenated()
+            ))
+            .peer_addr("127.0.0.1:8080".parse().unwrap())
+            .cookie(Cookie::new(super::CONSENT_COOKIE, "granted"))
+            .header(
+                "User-Agent",
+                "Mozilla/5.0 (X11; Linux x86_64; rv:95.0) Gecko/20100101 Firefox/95.0",
+            )
+            .send_request(&mut app)
+            .await;
+
+        assert!(res.status().is_success());
+
+        // The expired session is replaced by a new one, sent as a cookie
+        let cookie = res
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == "sid")
+            .unwrap();
+        let new_sid = Uuid::from_str(cookie.value()).unwrap();
+
+        assert_ne!(new_sid, sid);
+
+        let token =
+            Uuid::from_str(std::str::from_utf8(&test::read_body(res).await).unwrap()).unwrap();
+        let row = sqlx::query!("SELECT session_id FROM metrics WHERE id = $1", token)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(row.session_id, Some(new_sid));
+    }
+
+    #[actix_rt::test]
+    async fn test_create_token_traffic() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let mut app =
+            test::init_service(App::new().data(pool.clone()).service(super::create)).await;
+        let res = test::TestRequest::get()
+            .uri(
+                "/metrics/token?path=/portfolio/lorem-1&belongs_to=Project\
+                &search=%3Futm_source%3DInstagram%26utm_campaign%3Dstories\
+                &referrer=https%3A%2F%2Fl.instagram.com%2F",
+            )
+            .peer_addr("127.0.0.1:8080".parse().unwrap())
+            .header(
+                "User-Agent",
+                "Mozilla/5.0 (X11; Linux x86_64; rv:95.0) Gecko/20100101 Firefox/95.0",
+            )
+            .send_request(&mut app)
+            .await;
+
+        assert!(res.status().is_success());
+
+        let token =
+            Uuid::from_str(std::str::from_utf8(&test::read_body(res).await).unwrap()).unwrap();
+        let row = sqlx::query!(
+            "SELECT referrer_domain, referrer_kind, utm_source, utm_campaign
+                FROM metrics
+                WHERE id = $1",
+            token
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(row.referrer_domain.as_deref(), Some("instagram.com"));
+        assert_eq!(row.referrer_kind, "social");
+        assert_eq!(row.utm_source.as_deref(), Some("instagram"));
+        assert_eq!(row.utm_campaign.as_deref(), Some("stories"));
+    }
+}
@@ -9,9 +9,19 @@ pub mod admin;
 pub mod api;
 pub mod blog;
 pub mod metrics;
+pub mod newsletter;
 pub mod portfolio;
 pub mod user;
 
+/// IP the attempts of a form are counted by, the same one in development
+fn client_ip(req: &HttpRequest) -> String {
+    if cfg!(debug_assertions) {
+        "localhost".to_string()
+    } else {
+        req.peer_addr().unwrap().ip().to_string()
+    }
+}
+
 #[derive(sqlx::FromRow)]
 struct Page {
     id: i16,
@@ -202,6 +212,8 @@ async fn contact(req: HttpRequest, pool: web::Data<PgPool>) -> Result<HttpRespon
                 year: i32,
                 metric_token: Option<String>,
                 settings: services::settings::Settings,
+                spam_token: String,
+                pow_difficulty: u32,
             }
 
             return Contact {
@@ -210,6 +222,8 @@ async fn contact(req: HttpRequest, pool: web::Data<PgPool>) -> Result<HttpRespon
                 year: chrono::Utc::now().year(),
                 metric_token: token,
                 settings,
+                spam_token: crate::utils::spam::token(),
+                pow_difficulty: crate::utils::spam::difficulty(),
             }
             .into_response();
         }
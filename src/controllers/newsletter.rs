@@ -0,0 +1,374 @@
+//! Subscriptions to the new articles of the blog. The confirmation and
+//! unsubscribe links carry tokens signed with the `NEWSLETTER_SECRET` key, which
+//! must stay the same for the links of the sent emails to keep working.
+
+use crate::{
+    services::{
+        self,
+        attempts::Kind,
+        blog::articles,
+        emails,
+        subscribers::{self, Subscriber},
+    },
+    utils::{
+        mail::{self, templates, Addresses},
+        signature,
+    },
+};
+use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
+use askama_actix::{Template, TemplateIntoResponse};
+use chrono::Datelike;
+use ring::hmac;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+/// Days a confirmation link can be used
+const CONFIRMATION_VALIDITY_DAYS: i64 = 7;
+
+/// Subscriptions an IP can request in an hour
+const SUBSCRIBE_LIMIT: i64 = 5;
+
+#[derive(Deserialize)]
+pub struct SubscribeForm {
+    email: String,
+    /// Hidden field, only filled by bots
+    website: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TokenQuery {
+    token: String,
+}
+
+fn key() -> Option<hmac::Key> {
+    let key = signature::key_from_env("NEWSLETTER_SECRET");
+
+    if key.is_none() {
+        eprintln!("NEWSLETTER_SECRET is not set");
+    }
+
+    key
+}
+
+fn confirmation_token(key: &hmac::Key, id: i32, timestamp: i64) -> String {
+    signature::sign(key, &format!("confirm.{}.{}", id, timestamp))
+}
+
+/// Subscriber of a confirmation token which has not expired
+fn confirmation_id(key: &hmac::Key, token: &str, now: i64) -> Option<i32> {
+    let payload = signature::verify(key, token)?;
+    let mut parts = payload.split('.');
+
+    if parts.next() != Some("confirm") {
+        return None;
+    }
+
+    let id = parts.next()?.parse().ok()?;
+    let timestamp = parts.next()?.parse::<i64>().ok()?;
+
+    if now - timestamp > CONFIRMATION_VALIDITY_DAYS * 24 * 3600 {
+        None
+    } else {
+        Some(id)
+    }
+}
+
+/// Link of the emails sent to a subscriber, valid as long as the key
+fn unsubscribe_url(addresses: &Addresses, key: &hmac::Key, id: i32) -> String {
+    addresses.url(&format!(
+        "/newsletter/desinscription?token={}",
+        signature::sign(key, &format!("unsubscribe.{}", id))
+    ))
+}
+
+fn unsubscription_id(key: &hmac::Key, token: &str) -> Option<i32> {
+    signature::verify(key, token)?
+        .strip_prefix("unsubscribe.")?
+        .parse()
+        .ok()
+}
+
+/// Page telling the visitor the result of a link, with the unsubscribe button
+/// when the token is given
+async fn page(
+    pool: &PgPool,
+    title: &str,
+    message: &str,
+    unsubscribe_token: Option<String>,
+) -> Result<HttpResponse, Error> {
+    #[derive(Template)]
+    #[template(path = "pages/newsletter.html")]
+    struct Newsletter<'a> {
+        title: &'a str,
+        message: &'a str,
+        unsubscribe_token: Option<String>,
+        year: i32,
+        settings: services::settings::Settings,
+    }
+
+    match services::settings::get(pool).await {
+        Ok(settings) => Newsletter {
+            title,
+            message,
+            unsubscribe_token,
+            year: chrono::Utc::now().year(),
+            settings,
+        }
+        .into_response(),
+        Err(_) => Ok(HttpResponse::InternalServerError().finish()),
+    }
+}
+
+/// Subscribe an address and send it the confirmation link. The response is the
+/// same whether the address is already subscribed or not
+#[post("")]
+pub async fn subscribe(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    form: web::Form<SubscribeForm>,
+) -> HttpResponse {
+    let ip = super::client_ip(&req);
+    let email = form.email.trim();
+
+    if email.len() > 250 || !mail::is_valid_email(email) {
+        return HttpResponse::BadRequest().finish();
+    }
+
+    if services::attempts::count(&pool, &ip, Kind::Subscribe).await >= SUBSCRIBE_LIMIT {
+        return HttpResponse::TooManyRequests().finish();
+    }
+
+    if let Err(e) = services::attempts::add(&pool, email, &ip, Kind::Subscribe).await {
+        eprintln!("{}", e);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if form
+        .website
+        .as_deref()
+        .map_or(false, |website| !website.is_empty())
+    {
+        return HttpResponse::Ok().finish();
+    }
+
+    let key = match key() {
+        Some(key) => key,
+        None => return HttpResponse::InternalServerError().finish(),
+    };
+    let id = match subscribers::subscribe(pool.as_ref(), email).await {
+        Ok(Some(id)) => id,
+        Ok(None) => return HttpResponse::Ok().finish(),
+        Err(e) => {
+            eprintln!("{}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let addresses = Addresses::from_env();
+    let url = addresses.url(&format!(
+        "/newsletter/confirmation?token={}",
+        confirmation_token(&key, id, chrono::Utc::now().timestamp())
+    ));
+    let email = addresses.render(
+        email,
+        &templates::SubscriptionConfirmation {
+            url: &url,
+            validity_days: CONFIRMATION_VALIDITY_DAYS,
+        },
+    );
+
+    match email {
+        Ok(email) => match emails::enqueue(pool.as_ref(), &email).await {
+            Ok(_) => HttpResponse::Ok().finish(),
+            Err(e) => {
+                eprintln!("{}", e);
+                HttpResponse::InternalServerError().finish()
+            }
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/confirmation")]
+pub async fn confirm(
+    pool: web::Data<PgPool>,
+    query: web::Query<TokenQuery>,
+) -> Result<HttpResponse, Error> {
+    let id =
+        key().and_then(|key| confirmation_id(&key, &query.token, chrono::Utc::now().timestamp()));
+    let confirmed = match id {
+        Some(id) => subscribers::confirm(pool.as_ref(), id).await,
+        None => Ok(false),
+    };
+
+    match confirmed {
+        Ok(true) => {
+            page(
+                &pool,
+                "Inscription confirmée",
+                "Merci ! Vous recevrez un email à chaque nouvel article du blog.",
+                None,
+            )
+            .await
+        }
+        Ok(false) => {
+            page(
+                &pool,
+                "Lien invalide",
+                "Ce lien a expiré ou a déjà été utilisé. Vous pouvez vous inscrire à nouveau depuis le blog.",
+                None,
+            )
+            .await
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            Ok(HttpResponse::InternalServerError().finish())
+        }
+    }
+}
+
+/// Ask to confirm, a link opened by the antivirus of a mail client must not
+/// unsubscribe
+#[get("/desinscription")]
+pub async fn unsubscribe_page(
+    pool: web::Data<PgPool>,
+    query: web::Query<TokenQuery>,
+) -> Result<HttpResponse, Error> {
+    match key().and_then(|key| unsubscription_id(&key, &query.token)) {
+        Some(_) => {
+            page(
+                &pool,
+                "Désinscription",
+                "Confirmez votre désinscription pour ne plus recevoir d'email à chaque nouvel article du blog.",
+                Some(query.into_inner().token),
+            )
+            .await
+        }
+        None => {
+            page(
+                &pool,
+                "Lien invalide",
+                "Ce lien de désinscription est invalide.",
+                None,
+            )
+            .await
+        }
+    }
+}
+
+#[post("/desinscription")]
+pub async fn unsubscribe(
+    pool: web::Data<PgPool>,
+    form: web::Form<TokenQuery>,
+) -> Result<HttpResponse, Error> {
+    let id = match key().and_then(|key| unsubscription_id(&key, &form.token)) {
+        Some(id) => id,
+        None => {
+            return page(
+                &pool,
+                "Lien invalide",
+                "Ce lien de désinscription est invalide.",
+                None,
+            )
+            .await
+        }
+    };
+
+    match subscribers::unsubscribe(pool.as_ref(), id).await {
+        Ok(_) => {
+            page(
+                &pool,
+                "Désinscription confirmée",
+                "Vous êtes désinscrit, vous ne recevrez plus d'email du blog.",
+                None,
+            )
+            .await
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            Ok(HttpResponse::InternalServerError().finish())
+        }
+    }
+}
+
+/// Queue the email of a published article to the confirmed subscribers, once
+/// per article. The errors are only logged, the article is published anyway
+pub async fn notify_subscribers(pool: &PgPool, article_id: i16) {
+    let key = match key() {
+        Some(key) => key,
+        None => return,
+    };
+    let article = match articles::mark_as_notified(pool, article_id).await {
+        Ok(Some(article)) => article,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let subscribers = match subscribers::get_confirmed(pool).await {
+        Ok(subscribers) => subscribers,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let addresses = Addresses::from_env();
+    let url = addresses.url(&format!(
+        "/blog/articles/{}",
+        article.uri.as_deref().unwrap_or_default()
+    ));
+    let cover_url = addresses.url(&format!("/uploads/{}", article.cover));
+
+    for Subscriber { id, email, .. } in subscribers {
+        let unsubscribe_url = unsubscribe_url(&addresses, &key, id);
+        let email = addresses.render(
+            &email,
+            &templates::NewArticle {
+                title: &article.title,
+                description: article.description.as_deref(),
+                url: &url,
+                cover_url: &cover_url,
+                unsubscribe_url: &unsubscribe_url,
+            },
+        );
+
+        match email {
+            Ok(email) => {
+                if let Err(e) = emails::enqueue(pool, &email).await {
+                    eprintln!("{}", e);
+                }
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::mail::Addresses;
+    use ring::hmac;
+
+    #[test]
+    fn test_tokens() {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+        let now = chrono::Utc::now().timestamp();
+        let token = super::confirmation_token(&key, 1, now);
+
+        assert_eq!(super::confirmation_id(&key, &token, now), Some(1));
+        assert_eq!(
+            super::confirmation_id(&key, &token, now + 8 * 24 * 3600),
+            None
+        );
+        // A confirmation token does not unsubscribe, and conversely
+        assert_eq!(super::unsubscription_id(&key, &token), None);
+
+        let url = super::unsubscribe_url(&Addresses::from_env(), &key, 1);
+        let token = url.split("token=").nth(1).unwrap();
+
+        assert_eq!(super::unsubscription_id(&key, token), Some(1));
+        assert_eq!(super::confirmation_id(&key, token, now), None);
+    }
+}
@@ -32,6 +32,9 @@ async fn index(req: HttpRequest, pool: web::Data<PgPool>) -> Result<HttpResponse
             struct Illustration {
                 path: String,
                 name: Option<String>,
+                width: Option<i32>,
+                height: Option<i32>,
+                placeholder: Option<String>,
                 // fallback_path: String
             }
             #[derive(Template)]
@@ -82,11 +85,17 @@ async fn index(req: HttpRequest, pool: web::Data<PgPool>) -> Result<HttpResponse
 
                 let illustration = match sqlx::query_as!(
                     Illustration,
+                    // Videos are illustrated by their poster
                     r#"SELECT
-                            f.path AS "path", f.name AS "name"
+                            f.path AS "path!", f.name AS "name", f.width, f.height, f.placeholder
                         FROM project_assets pa
-                        JOIN files f ON f.id = pa.file_id
-                        WHERE pa.project_id = $1 AND pa.order = 0"#,
+                        JOIN files f ON f.id = CASE
+                            WHEN pa.kind = 'image' THEN pa.file_id
+                            ELSE pa.poster_id
+                        END
+                        WHERE pa.project_id = $1
+                        ORDER BY pa.order
+                        LIMIT 1"#,
                     project.id
                 )
                 .fetch_one(pool.as_ref())
@@ -111,6 +120,9 @@ async fn index(req: HttpRequest, pool: web::Data<PgPool>) -> Result<HttpResponse
                                 .unwrap()
                         ),
                         name: None,
+                        width: None,
+                        height: None,
+                        placeholder: None,
                     },
                     illustration,
                     categories: c,
@@ -1,4 +1,7 @@
-use crate::services;
+use crate::{
+    services::{self, attempts::Kind},
+    utils::mail::{self, templates::PasswordRecovery, Addresses},
+};
 use actix_identity::Identity;
 use actix_web::{get, post, web, HttpRequest, HttpResponse};
 use regex::Regex;
@@ -20,24 +23,18 @@ pub async fn login(
     form.email = form.email.trim().to_string();
     form.password = form.password.trim().to_string();
 
-    let ip = if cfg!(debug_assertions) {
-        "localhost".to_string()
-    } else {
-        req.peer_addr().unwrap().ip().to_string()
-    };
-    let attempts_counter = services::attempts::count(&pool, &ip, true).await;
+    let ip = super::client_ip(&req);
+    let attempts_counter = services::attempts::count(&pool, &ip, Kind::Login).await;
 
     if attempts_counter > 10 {
         return HttpResponse::TooManyRequests().finish();
     }
 
-    services::attempts::add(&pool, &form.email, &ip, true)
+    services::attempts::add(&pool, &form.email, &ip, Kind::Login)
         .await
         .unwrap();
 
-    let email_regex = Regex::new(r#"^(([^<>()\[\]\\.,;:\s@"]+(\.[^<>()\[\]\\.,;:\s@"]+)*)|(".+"))@((\[[0-9]{1,3}\.[0-9]{1,3}\.[0-9]{1,3}\.[0-9]{1,3}])|(([a-zA-Z\-0-9]+\.)+[a-zA-Z]{2,}))$"#).unwrap();
-
-    if !email_regex.is_match(&form.email) {
+    if !mail::is_valid_email(&form.email) {
         return HttpResponse::BadRequest().finish();
     }
 
@@ -62,7 +59,7 @@ pub async fn login(
             match argon2.verify_password(form.password.as_bytes(), &parsed_hash) {
                 Ok(_) => {
                     if attempts_counter >= 1 {
-                        services::attempts::clear(&pool, &ip).await;
+                        services::attempts::clear(&pool, &ip, Kind::Login).await;
                     }
 
                     // TODO : see what to save in session
@@ -101,30 +98,22 @@ pub async fn lost_password(
 ) -> HttpResponse {
     form.email = form.email.trim().to_string();
 
-    let ip = if cfg!(debug_assertions) {
-        "localhost".to_string()
-    } else {
-        req.peer_addr().unwrap().ip().to_string()
-    };
-    let attempts_counter = services::attempts::count(&pool, &ip, false).await;
+    let ip = super::client_ip(&req);
+    let attempts_counter = services::attempts::count(&pool, &ip, Kind::LostPassword).await;
 
     if attempts_counter > 3 {
         return HttpResponse::TooManyRequests().finish();
     }
 
-    services::attempts::add(&pool, &form.email, &ip, false)
+    services::attempts::add(&pool, &form.email, &ip, Kind::LostPassword)
         .await
         .unwrap();
 
-    let email_regex = Regex::new(r#"^(([^<>()\[\]\\.,;:\s@"]+(\.[^<>()\[\]\\.,;:\s@"]+)*)|(".+"))@((\[[0-9]{1,3}\.[0-9]{1,3}\.[0-9]{1,3}\.[0-9]{1,3}])|(([a-zA-Z\-0-9]+\.)+[a-zA-Z]{2,}))$"#).unwrap();
-
-    if !email_regex.is_match(&form.email) {
+    if !mail::is_valid_email(&form.email) {
         return HttpResponse::BadRequest().finish();
     }
 
     if services::user::exist_for_email(&pool, &form.email).await {
-        use lettre::{SmtpClient, Transport};
-        use lettre_email::EmailBuilder;
         use rand::seq::SliceRandom;
         use rand::thread_rng;
 
@@ -138,7 +127,7 @@ pub async fn lost_password(
         }
 
         if attempts_counter >= 1 {
-            services::attempts::clear(&pool, &ip).await;
+            services::attempts::clear(&pool, &ip, Kind::LostPassword).await;
         }
 
         sqlx::query!(
@@ -149,21 +138,24 @@ pub async fn lost_password(
         .await
         .unwrap();
 
-        let email = EmailBuilder::new()
-            .to("contact@guillaume-gueyraud.fr")
-            .from("hello@ludivinefarat.fr")
-            .subject("Mot de passe oublié - Ludivine Farat")
-            .html(r#"Vous avez effectué la demande de récupération de votre mot de passe, pour le récupérer merci de cliquer sur le bouton ci-dessous afin d'en saisir un nouveau.\n<a href="https://ludivinefarat.fr/admin/recuperation-mot-de-passe">Récupérer mon mot de passe</a>"#)
-            .build();
-
-        if let Ok(email) = email {
-            let mut mailer = SmtpClient::new_unencrypted_localhost().unwrap().transport();
-
-            if mailer.send(email.into()).is_ok() {
-                return HttpResponse::Ok().json(serde_json::json!({
-                    "valid": true
-                }));
+        let addresses = Addresses::from_env();
+        let email = addresses.render(
+            &form.email,
+            &PasswordRecovery {
+                url: &addresses.url("/admin/recuperation-mot-de-passe"),
+                validity_minutes: 30,
+            },
+        );
+
+        match email {
+            Ok(email) => {
+                if services::emails::enqueue(&pool, &email).await.is_ok() {
+                    return HttpResponse::Ok().json(serde_json::json!({
+                        "valid": true
+                    }));
+                }
             }
+            Err(e) => eprintln!("{}", e),
         }
 
         return HttpResponse::InternalServerError().finish();
@@ -7,7 +7,7 @@ use actix_web::{
         HeaderValue,
     },
     middleware::{Compress, Logger},
-    App, Error, HttpRequest, HttpResponse, HttpServer, Result,
+    web, App, Error, HttpRequest, HttpResponse, HttpServer, Result,
 };
 use rustls::{
     internal::pemfile::{certs, pkcs8_private_keys},
@@ -20,6 +20,8 @@ mod routes;
 mod services;
 mod utils;
 
+use utils::storage::Storage;
+
 async fn create_pool() -> Result<sqlx::PgPool, sqlx::Error> {
     let pool: sqlx::PgPool = sqlx::pool::PoolOptions::new()
         .connect(&std::env::var("DATABASE_URL").expect("DATABASE_URL not found"))
@@ -41,18 +43,20 @@ fn serve_file(req: &HttpRequest, path: &Path, cache_duration: i64) -> Result<Htt
 
             Ok(response)
         }
-        Err(_) => {
-            use askama::Template;
+        Err(_) => Ok(not_found()),
+    }
+}
 
-            #[derive(Template)]
-            #[template(path = "pages/404.html")]
-            struct NotFound;
+fn not_found() -> HttpResponse {
+    use askama::Template;
 
-            Ok(HttpResponse::NotFound()
-                .content_type("text/html")
-                .body(NotFound.render().unwrap()))
-        }
-    }
+    #[derive(Template)]
+    #[template(path = "pages/404.html")]
+    struct NotFound;
+
+    HttpResponse::NotFound()
+        .content_type("text/html")
+        .body(NotFound.render().unwrap())
 }
 
 #[get("/{filename:.*}")]
@@ -76,10 +80,96 @@ async fn serve_public_file(req: HttpRequest) -> Result<HttpResponse, Error> {
 }
 
 #[get("/uploads/{filename:.*}")]
-async fn serve_upload_file(req: HttpRequest) -> Result<HttpResponse, Error> {
-    let file_path = format!(".{}", req.path());
-    let path = Path::new(&file_path);
-    serve_file(&req, &path, 30)
+async fn serve_upload_file(
+    req: HttpRequest,
+    storage: web::Data<dyn Storage>,
+    web::Path(filename): web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    Ok(storage
+        .serve(&req, &filename)
+        .unwrap_or_else(|_| not_found()))
+}
+
+/// Copy the uploaded files from a storage backend to another:
+/// `migrate-storage <from> <to>` where backends are `local` or `s3`
+fn migrate_storage(from: &str, to: &str) -> std::io::Result<()> {
+    let from = utils::storage::from_name(from)?;
+    let to = utils::storage::from_name(to)?;
+    let count = utils::storage::migrate(from.as_ref(), to.as_ref())?;
+
+    println!("{} files copied", count);
+
+    Ok(())
+}
+
+/// Flag the bots, refresh the metrics rollups then delete the raw metrics older
+/// than `METRICS_RETENTION_DAYS` days (30 by default), every 15 minutes in a
+/// dedicated thread. Raw metrics are only deleted once their rollups are up to
+/// date
+fn spawn_metrics_jobs(pool: sqlx::PgPool) {
+    use futures::executor::block_on;
+
+    let retention_days = std::env::var("METRICS_RETENTION_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(30);
+
+    std::thread::spawn(move || loop {
+        if let Err(e) = block_on(services::metrics::flag_bots(&pool)) {
+            eprintln!("Metrics bots flagging failed: {}", e);
+        }
+
+        match block_on(services::metrics::rollups::refresh(&pool)) {
+            Ok(_) => {
+                if let Err(e) = block_on(services::metrics::purge(&pool, retention_days)) {
+                    eprintln!("Metrics purge failed: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Metrics rollups refresh failed: {}", e),
+        }
+
+        std::thread::sleep(std::time::Duration::from_secs(15 * 60));
+    });
+}
+
+/// Send the emails of the outbox every 10 seconds in a dedicated thread
+fn spawn_email_worker(pool: sqlx::PgPool, transport: std::sync::Arc<dyn utils::mail::Transport>) {
+    use futures::executor::block_on;
+
+    std::thread::spawn(move || loop {
+        if let Err(e) = block_on(services::emails::process(&pool, transport.as_ref())) {
+            eprintln!("Emails sending failed: {}", e);
+        }
+
+        std::thread::sleep(std::time::Duration::from_secs(10));
+    });
+}
+
+/// Recompute the rollups of the days still having raw metrics, e.g. after an
+/// outage of the server
+async fn refresh_metrics() -> std::io::Result<()> {
+    let pool = create_pool().await.expect("Connection to database failed");
+
+    match services::metrics::rollups::refresh(&pool).await {
+        Ok(Some(since)) => println!("Metrics rollups refreshed since {}", since),
+        Ok(None) => println!("No metrics to roll up"),
+        Err(e) => eprintln!("Metrics rollups refresh failed: {}", e),
+    }
+
+    Ok(())
+}
+
+/// Convert the articles written in HTML to blocks, once, after the update
+/// adding the blocks. The missing columns of the articles are added as well
+async fn migrate_articles() -> std::io::Result<()> {
+    let pool = create_pool().await.expect("Connection to database failed");
+
+    match services::blog::articles::migrate_to_blocks(&pool).await {
+        Ok(count) => println!("{} articles converted", count),
+        Err(e) => eprintln!("Articles conversion failed: {}", e),
+    }
+
+    Ok(())
 }
 
 #[actix_web::main]
@@ -88,6 +178,26 @@ async fn main() -> std::io::Result<()> {
 
     dotenv().ok();
 
+    let args = std::env::args().collect::<Vec<_>>();
+
+    if args.get(1).map(String::as_str) == Some("migrate-storage") {
+        return match (args.get(2), args.get(3)) {
+            (Some(from), Some(to)) => migrate_storage(from, to),
+            _ => {
+                eprintln!("Usage: {} migrate-storage <local|s3> <local|s3>", args[0]);
+                Ok(())
+            }
+        };
+    }
+
+    if args.get(1).map(String::as_str) == Some("refresh-metrics") {
+        return refresh_metrics().await;
+    }
+
+    if args.get(1).map(String::as_str) == Some("migrate-articles") {
+        return migrate_articles().await;
+    }
+
     if cfg!(debug_assertions) {
         std::env::set_var("RUST_LOG", "actix_web=info,sqlx=debug");
         env_logger::init();
@@ -98,6 +208,11 @@ async fn main() -> std::io::Result<()> {
     let server_addr =
         std::env::var("SERVER_ADDR").expect("SERVER_ADDR variable not specified in .env file");
     let pool = create_pool().await.expect("Connection to database failed");
+    let storage = utils::storage::from_env().expect("Invalid storage configuration");
+    let mailer = utils::mail::from_env().expect("Invalid mail configuration");
+
+    spawn_metrics_jobs(pool.clone());
+    spawn_email_worker(pool.clone(), mailer);
 
     // TLS configuration
     let mut config = ServerConfig::new(NoClientAuth::new());
@@ -132,6 +247,7 @@ async fn main() -> std::io::Result<()> {
     let server = HttpServer::new(move || {
         App::new()
             .data(pool.clone())
+            .app_data(web::Data::from(storage.clone()))
             .wrap(Compress::default())
             .wrap(Logger::default())
             .wrap(IdentityService::new(
@@ -10,6 +10,10 @@ pub fn config(cfg: &mut web::ServiceConfig) {
             .service(controllers::admin::motion_design)
             .service(controllers::admin::my_little_plus)
             .service(controllers::admin::settings)
+            .service(controllers::admin::statistics)
+            .service(controllers::admin::messages)
+            .service(controllers::admin::subscribers)
+            .service(controllers::admin::comments)
             .service(controllers::admin::index)
             .service(controllers::admin::blog),
     );
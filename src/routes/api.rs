@@ -1,6 +1,11 @@
-use crate::controllers;
+use crate::{
+    controllers,
+    utils::{upload::UploadConfig, video},
+};
 use actix_web::web;
 
+const MEGABYTE: usize = 1_000_000;
+
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(web::scope("/contact").service(controllers::api::contact));
 
@@ -8,6 +13,11 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         web::scope("/api")
             .service(
                 web::scope("/portfolio")
+                    .app_data(UploadConfig::from_env(
+                        "PORTFOLIO",
+                        video::MAX_SIZE,
+                        3 * video::MAX_SIZE,
+                    ))
                     .service(controllers::api::portfolio::insert_project)
                     .service(controllers::api::portfolio::update_project)
                     .service(controllers::api::portfolio::delete_project)
@@ -18,6 +28,7 @@ pub fn config(cfg: &mut web::ServiceConfig) {
             )
             .service(
                 web::scope("/blog")
+                    .app_data(UploadConfig::from_env("BLOG", 10 * MEGABYTE, 40 * MEGABYTE))
                     .service(controllers::api::blog::get_category)
                     .service(controllers::api::blog::insert_category)
                     .service(controllers::api::blog::update_category)
@@ -27,6 +38,41 @@ pub fn config(cfg: &mut web::ServiceConfig) {
                     .service(controllers::api::blog::update_article)
                     .service(controllers::api::blog::delete_article),
             )
+            .service(
+                web::scope("/metrics")
+                    .service(controllers::api::metrics::get_statistics)
+                    .service(controllers::api::metrics::get_funnel)
+                    .service(controllers::api::metrics::export)
+                    .service(controllers::api::metrics::get_live),
+            )
+            .service(
+                web::scope("/emails")
+                    .service(controllers::api::emails::get_failed)
+                    .service(controllers::api::emails::retry),
+            )
+            .service(
+                web::scope("/messages")
+                    .service(controllers::api::messages::get_messages)
+                    .service(controllers::api::messages::count_unread)
+                    .service(controllers::api::messages::get_auto_reply)
+                    .service(controllers::api::messages::update_auto_reply)
+                    .service(controllers::api::messages::get_message)
+                    .service(controllers::api::messages::update_message)
+                    .service(controllers::api::messages::reply),
+            )
+            .service(
+                web::scope("/comments")
+                    .service(controllers::api::comments::get_comments)
+                    .service(controllers::api::comments::update_comment)
+                    .service(controllers::api::comments::reply)
+                    .service(controllers::api::comments::delete_comment),
+            )
+            .service(
+                web::scope("/subscribers")
+                    .service(controllers::api::subscribers::get_subscribers)
+                    .service(controllers::api::subscribers::export)
+                    .service(controllers::api::subscribers::delete_subscriber),
+            )
             .service(
                 web::scope("/motion-design")
                     .service(controllers::api::update_motion_design_informations),
@@ -35,7 +81,15 @@ pub fn config(cfg: &mut web::ServiceConfig) {
                 web::scope("/my_little_plus")
                     .service(controllers::api::update_little_plus_informations),
             )
-            .service(web::scope("/home").service(controllers::api::update_home_informations))
-            .service(web::scope("/settings").service(controllers::api::update_settings)),
+            .service(
+                web::scope("/home")
+                    .app_data(UploadConfig::from_env("HOME", 10 * MEGABYTE, 10 * MEGABYTE))
+                    .service(controllers::api::update_home_informations),
+            )
+            .service(
+                web::scope("/settings")
+                    .app_data(UploadConfig::from_env("SETTINGS", MEGABYTE, 2 * MEGABYTE))
+                    .service(controllers::api::update_settings),
+            ),
     );
 }
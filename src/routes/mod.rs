@@ -20,9 +20,18 @@ pub fn config(cfg: &mut web::ServiceConfig) {
             web::scope("/blog")
                 .service(controllers::blog::index)
                 .service(controllers::blog::show_category)
-                .service(controllers::blog::show_article),
+                .service(controllers::blog::show_article)
+                .service(controllers::blog::add_comment),
+        )
+        .service(
+            web::scope("/newsletter")
+                .service(controllers::newsletter::subscribe)
+                .service(controllers::newsletter::confirm)
+                .service(controllers::newsletter::unsubscribe_page)
+                .service(controllers::newsletter::unsubscribe),
         )
         .service(controllers::metrics::log)
         .service(controllers::metrics::create)
+        .service(controllers::metrics::create_event)
         .service(controllers::metrics::create_session);
 }
@@ -1,13 +1,35 @@
 use sqlx::{Error, PgPool};
 
-pub async fn count(pool: &PgPool, ip: &str, is_login: bool) -> i64 {
+/// Form an attempt has been made on, each one being limited separately
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Login,
+    LostPassword,
+    Contact,
+    Subscribe,
+    Comment,
+}
+
+impl Kind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Login => "login",
+            Kind::LostPassword => "lost_password",
+            Kind::Contact => "contact",
+            Kind::Subscribe => "subscribe",
+            Kind::Comment => "comment",
+        }
+    }
+}
+
+pub async fn count(pool: &PgPool, ip: &str, kind: Kind) -> i64 {
     sqlx::query!(
         "SELECT
             COUNT(id)
         FROM attempts
-        WHERE ip = $1 AND is_login = $2 AND date >= NOW() - interval '1 hour'",
+        WHERE ip = $1 AND kind = $2 AND date >= NOW() - interval '1 hour'",
         ip,
-        is_login
+        kind.as_str()
     )
     .fetch_one(pool)
     .await
@@ -16,14 +38,14 @@ pub async fn count(pool: &PgPool, ip: &str, is_login: bool) -> i64 {
     .unwrap()
 }
 
-pub async fn add(pool: &PgPool, email: &str, ip: &str, is_login: bool) -> Result<i16, Error> {
+pub async fn add(pool: &PgPool, email: &str, ip: &str, kind: Kind) -> Result<i32, Error> {
     let res = sqlx::query!(
-        "INSERT INTO attempts (email, ip, is_login)
+        "INSERT INTO attempts (email, ip, kind)
         VALUES ($1, $2, $3)
         RETURNING id",
         email,
         ip,
-        is_login
+        kind.as_str()
     )
     .fetch_one(pool)
     .await?;
@@ -31,12 +53,16 @@ pub async fn add(pool: &PgPool, email: &str, ip: &str, is_login: bool) -> Result
     Ok(res.id)
 }
 
-pub async fn clear(pool: &PgPool, ip: &str) -> bool {
-    let rows = sqlx::query!("DELETE FROM attempts WHERE ip = $1", ip)
-        .execute(pool)
-        .await
-        .unwrap()
-        .rows_affected();
+pub async fn clear(pool: &PgPool, ip: &str, kind: Kind) -> bool {
+    let rows = sqlx::query!(
+        "DELETE FROM attempts WHERE ip = $1 AND kind = $2",
+        ip,
+        kind.as_str()
+    )
+    .execute(pool)
+    .await
+    .unwrap()
+    .rows_affected();
 
     rows == 1
 }
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, PgPool};
+
+/// Acknowledgement sent to the visitors who use the contact form. `{prénom}`
+/// in the subject or the body is replaced by the first name of the visitor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutoReply {
+    pub enabled: bool,
+    pub subject: String,
+    pub body: String,
+}
+
+pub async fn get(pool: &PgPool) -> Result<AutoReply, Error> {
+    sqlx::query_as!(
+        AutoReply,
+        "SELECT enabled, subject, body FROM contact_auto_reply"
+    )
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn update(pool: &PgPool, auto_reply: &AutoReply) -> Result<bool, Error> {
+    let res = sqlx::query!(
+        "UPDATE contact_auto_reply SET enabled = $1, subject = $2, body = $3",
+        auto_reply.enabled,
+        auto_reply.subject,
+        auto_reply.body
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::create_pool;
+    use dotenv::dotenv;
+
+    #[actix_rt::test]
+    async fn test_update() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let auto_reply = super::get(&pool).await.unwrap();
+
+        assert!(super::update(&pool, &auto_reply).await.unwrap());
+        assert_eq!(super::get(&pool).await.unwrap(), auto_reply);
+    }
+}
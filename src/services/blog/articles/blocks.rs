@@ -0,0 +1,604 @@
+//! Content of the articles, as a list of blocks stored in JSON. The blocks are
+//! validated and sanitized when written, then rendered to HTML when displayed
+
+use super::images::BlogArticleImage;
+use crate::utils::{html, video};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+
+/// Maximum number of blocks of an article
+const MAX_BLOCKS: usize = 500;
+
+/// Maximum length (in bytes) of a paragraph, a list item or a quote
+const MAX_TEXT_LENGTH: usize = 10_000;
+
+/// Maximum length (in bytes) of a heading, an alternative text, a caption, a
+/// quote source or an embed URL
+const MAX_LABEL_LENGTH: usize = 255;
+
+/// Maximum number of items of a list
+const MAX_ITEMS: usize = 100;
+
+/// Maximum number of images of a gallery
+const MAX_GALLERY_IMAGES: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    /// Sanitized inline HTML: bold, italic, links and line breaks
+    Paragraph {
+        text: String,
+    },
+    /// From `<h2>` to `<h4>`, the `<h1>` being the title of the article
+    Heading {
+        level: u8,
+        text: String,
+    },
+    /// Items as sanitized inline HTML
+    List {
+        ordered: bool,
+        items: Vec<String>,
+    },
+    Image(Image),
+    Quote {
+        text: String,
+        source: Option<String>,
+    },
+    Gallery {
+        images: Vec<Image>,
+    },
+    /// Video of one of the allowed providers, see `embed_url`
+    Embed {
+        url: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Image {
+    pub source: ImageSource,
+    #[serde(default)]
+    pub alt: String,
+    pub caption: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageSource {
+    /// Image of the article
+    Id(Uuid),
+    /// Index of a picture sent with the content, only accepted when writing
+    Upload(usize),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Invalid {
+    TooManyBlocks,
+    /// Index of the invalid block
+    Block(usize),
+}
+
+impl Block {
+    fn images(&self) -> &[Image] {
+        match self {
+            Block::Image(image) => std::slice::from_ref(image),
+            Block::Gallery { images } => images,
+            _ => &[],
+        }
+    }
+
+    fn images_mut(&mut self) -> &mut [Image] {
+        match self {
+            Block::Image(image) => std::slice::from_mut(image),
+            Block::Gallery { images } => images,
+            _ => &mut [],
+        }
+    }
+
+    /// Block with its texts trimmed and sanitized, `None` when invalid
+    fn validated(self) -> Option<Block> {
+        let block = match self {
+            Block::Paragraph { text } => Block::Paragraph {
+                text: rich_text(&text)?,
+            },
+            Block::Heading { level, text } => {
+                if !(2..=4).contains(&level) {
+                    return None;
+                }
+
+                Block::Heading {
+                    level,
+                    text: plain_text(&text, MAX_LABEL_LENGTH)?,
+                }
+            }
+            Block::List { ordered, items } => {
+                if items.is_empty() || items.len() > MAX_ITEMS {
+                    return None;
+                }
+
+                Block::List {
+                    ordered,
+                    items: items
+                        .iter()
+                        .map(|item| rich_text(item))
+                        .collect::<Option<_>>()?,
+                }
+            }
+            Block::Image(image) => Block::Image(image.validated()?),
+            Block::Quote { text, source } => Block::Quote {
+                text: plain_text(&text, MAX_TEXT_LENGTH)?,
+                source: match source {
+                    Some(source) if !source.trim().is_empty() => {
+                        Some(plain_text(&source, MAX_LABEL_LENGTH)?)
+                    }
+                    _ => None,
+                },
+            },
+            Block::Gallery { images } => {
+                if images.len() < 2 || images.len() > MAX_GALLERY_IMAGES {
+                    return None;
+                }
+
+                Block::Gallery {
+                    images: images
+                        .into_iter()
+                        .map(Image::validated)
+                        .collect::<Option<_>>()?,
+                }
+            }
+            Block::Embed { url } => {
+                let url = url.trim();
+
+                if url.len() > MAX_LABEL_LENGTH || embed_url(url).is_none() {
+                    return None;
+                }
+
+                Block::Embed {
+                    url: url.to_string(),
+                }
+            }
+        };
+
+        Some(block)
+    }
+}
+
+impl Image {
+    fn validated(self) -> Option<Image> {
+        let alt = self.alt.trim().to_string();
+
+        if alt.len() > MAX_LABEL_LENGTH {
+            return None;
+        }
+
+        Some(Image {
+            source: self.source,
+            alt,
+            caption: match self.caption {
+                Some(caption) if !caption.trim().is_empty() => {
+                    Some(plain_text(&caption, MAX_LABEL_LENGTH)?)
+                }
+                _ => None,
+            },
+        })
+    }
+}
+
+/// Sanitized inline HTML, `None` when empty or too long
+fn rich_text(text: &str) -> Option<String> {
+    let text = html::clean_inline(text);
+
+    if text.replace("<br>", "").trim().is_empty() || text.len() > MAX_TEXT_LENGTH {
+        return None;
+    }
+
+    Some(text)
+}
+
+/// Trimmed text, `None` when empty or too long
+fn plain_text(text: &str, max_length: usize) -> Option<String> {
+    let text = text.trim();
+
+    if text.is_empty() || text.len() > max_length {
+        return None;
+    }
+
+    Some(text.to_string())
+}
+
+/// Blocks sent by the back office, trimmed and sanitized
+pub fn validate(blocks: Vec<Block>) -> Result<Vec<Block>, Invalid> {
+    if blocks.len() > MAX_BLOCKS {
+        return Err(Invalid::TooManyBlocks);
+    }
+
+    blocks
+        .into_iter()
+        .enumerate()
+        .map(|(i, block)| block.validated().ok_or(Invalid::Block(i)))
+        .collect()
+}
+
+/// Replace the uploads by the ids of the images they were saved as. The other
+/// images have to be in `images`, the ones of the article
+pub fn resolve(blocks: &mut [Block], uploads: &[Uuid], images: &[Uuid]) -> Result<(), Invalid> {
+    for (i, block) in blocks.iter_mut().enumerate() {
+        for image in block.images_mut() {
+            image.source = match image.source {
+                ImageSource::Upload(upload) => {
+                    ImageSource::Id(*uploads.get(upload).ok_or(Invalid::Block(i))?)
+                }
+                ImageSource::Id(id) if images.contains(&id) => ImageSource::Id(id),
+                ImageSource::Id(_) => return Err(Invalid::Block(i)),
+            };
+        }
+    }
+
+    Ok(())
+}
+
+/// Ids of the images displayed by the blocks
+pub fn image_ids(blocks: &[Block]) -> Vec<Uuid> {
+    blocks
+        .iter()
+        .flat_map(Block::images)
+        .filter_map(|image| match image.source {
+            ImageSource::Id(id) => Some(id),
+            ImageSource::Upload(_) => None,
+        })
+        .collect()
+}
+
+/// URL of the player of a video, only for YouTube and Vimeo, see
+/// `video::parse_embed`
+pub fn embed_url(url: &str) -> Option<String> {
+    video::parse_embed(url).map(|(provider, id)| provider.player_url(&id))
+}
+
+/// Image with its caption, nothing when the image does not exist anymore
+fn figure(image: &Image, images: &[BlogArticleImage]) -> String {
+    let file = match image.source {
+        ImageSource::Id(id) => images.iter().find(|file| file.id == id),
+        ImageSource::Upload(_) => None,
+    };
+
+    match file {
+        Some(file) => format!(
+            "<figure>{}{}</figure>",
+            file.picture(&image.alt),
+            match &image.caption {
+                Some(caption) => format!("<figcaption>{}</figcaption>", html::escape(caption)),
+                None => String::new(),
+            }
+        ),
+        None => String::new(),
+    }
+}
+
+/// HTML of the content of an article, `images` being the ones of the article
+pub fn render(blocks: &[Block], images: &[BlogArticleImage]) -> String {
+    let mut rendered = String::new();
+
+    for block in blocks {
+        match block {
+            Block::Paragraph { text } => rendered += &format!("<p>{}</p>", text),
+            Block::Heading { level, text } => {
+                rendered += &format!("<h{0}>{1}</h{0}>", level, html::escape(text))
+            }
+            Block::List { ordered, items } => {
+                let tag = if *ordered { "ol" } else { "ul" };
+
+                rendered += &format!("<{}>", tag);
+
+                for item in items {
+                    rendered += &format!("<li>{}</li>", item);
+                }
+
+                rendered += &format!("</{}>", tag);
+            }
+            Block::Image(image) => rendered += &figure(image, images),
+            Block::Quote { text, source } => {
+                rendered += &format!(
+                    "<blockquote><p>{}</p>{}</blockquote>",
+                    html::escape(text),
+                    match source {
+                        Some(source) => format!("<cite>{}</cite>", html::escape(source)),
+                        None => String::new(),
+                    }
+                )
+            }
+            Block::Gallery { images: gallery } => {
+                rendered += r#"<div class="gallery">"#;
+
+                for image in gallery {
+                    rendered += &figure(image, images);
+                }
+
+                rendered += "</div>";
+            }
+            Block::Embed { url } => {
+                if let Some(src) = embed_url(url) {
+                    rendered += &format!(
+                        r#"<div class="embed"><iframe src="{}" title="Vidéo" loading="lazy" allow="fullscreen; picture-in-picture" allowfullscreen></iframe></div>"#,
+                        html::escape(&src)
+                    );
+                }
+            }
+        }
+    }
+
+    rendered
+}
+
+/// Blocks of an article written before the blocks existed, as sanitized HTML
+/// with `[[id]]` placeholders for the images
+pub fn from_html(content: &str) -> Vec<Block> {
+    let elements = Regex::new(r"(?s)<(p|ul|ol)>(.*?)</(?:p|ul|ol)>").unwrap();
+    let items = Regex::new(r"(?s)<li>(.*?)</li>").unwrap();
+    let placeholders = Regex::new(r"\[\[([0-9a-fA-F-]{36})\]\]").unwrap();
+    let mut blocks = vec![];
+
+    let push_text = |blocks: &mut Vec<Block>, text: &str| {
+        let mut start = 0;
+
+        for placeholder in placeholders.captures_iter(text) {
+            let bounds = placeholder.get(0).unwrap();
+
+            if let Some(text) = rich_text(&text[start..bounds.start()]) {
+                blocks.push(Block::Paragraph { text });
+            }
+            if let Ok(id) = Uuid::parse_str(&placeholder[1]) {
+                blocks.push(Block::Image(Image {
+                    source: ImageSource::Id(id),
+                    alt: String::new(),
+                    caption: None,
+                }));
+            }
+
+            start = bounds.end();
+        }
+
+        if let Some(text) = rich_text(&text[start..]) {
+            blocks.push(Block::Paragraph { text });
+        }
+    };
+
+    for element in elements.captures_iter(content) {
+        match &element[1] {
+            "p" => push_text(&mut blocks, &element[2]),
+            tag => {
+                let items = items
+                    .captures_iter(&element[2])
+                    .filter_map(|item| rich_text(&item[1]))
+                    .collect::<Vec<_>>();
+
+                if !items.is_empty() {
+                    blocks.push(Block::List {
+                        ordered: tag == "ol",
+                        items,
+                    });
+                }
+            }
+        }
+    }
+
+    // Content not made of paragraphs nor lists
+    if blocks.is_empty() {
+        push_text(&mut blocks, content);
+    }
+
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Block, Image, ImageSource, Invalid};
+    use crate::services::blog::articles::images::BlogArticleImage;
+    use sqlx::types::Uuid;
+
+    fn image(source: ImageSource) -> Image {
+        Image {
+            source,
+            alt: String::new(),
+            caption: None,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let blocks: Vec<Block> = serde_json::from_value(serde_json::json!([
+            { "type": "heading", "level": 2, "text": " Introduction " },
+            { "type": "paragraph", "text": "<p>Bonjour <b>à tous</b><script>alert(1)</script></p>" },
+            { "type": "image", "source": { "upload": 0 }, "alt": "Lorem", "caption": " " },
+            { "type": "embed", "url": "https://www.youtube.com/watch?v=dQw4w9WgXcQ" }
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            super::validate(blocks).unwrap(),
+            vec![
+                Block::Heading {
+                    level: 2,
+                    text: String::from("Introduction")
+                },
+                Block::Paragraph {
+                    text: String::from("Bonjour <b>à tous</b>")
+                },
+                Block::Image(Image {
+                    source: ImageSource::Upload(0),
+                    alt: String::from("Lorem"),
+                    caption: None
+                }),
+                Block::Embed {
+                    url: String::from("https://www.youtube.com/watch?v=dQw4w9WgXcQ")
+                }
+            ]
+        );
+
+        let invalid = vec![
+            Block::Heading {
+                level: 1,
+                text: String::from("Titre"),
+            },
+            Block::Paragraph {
+                text: String::from("<br>"),
+            },
+            Block::Gallery {
+                images: vec![image(ImageSource::Upload(0))],
+            },
+            Block::Embed {
+                url: String::from("https://example.com/video"),
+            },
+        ];
+
+        for block in invalid {
+            assert_eq!(
+                super::validate(vec![block.clone()]),
+                Err(Invalid::Block(0)),
+                "{:?}",
+                block
+            );
+        }
+
+        assert!(serde_json::from_value::<Vec<Block>>(
+            serde_json::json!([{ "type": "script", "text": "alert(1)" }])
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let existing = Uuid::parse_str("936da01f-9abd-4d9d-80c7-02af85c822a8").unwrap();
+        let uploaded = Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+        let mut blocks = vec![
+            Block::Image(image(ImageSource::Id(existing))),
+            Block::Gallery {
+                images: vec![
+                    image(ImageSource::Upload(0)),
+                    image(ImageSource::Id(existing)),
+                ],
+            },
+        ];
+
+        super::resolve(&mut blocks, &[uploaded], &[existing]).unwrap();
+
+        assert_eq!(
+            super::image_ids(&blocks),
+            vec![existing, uploaded, existing]
+        );
+        assert_eq!(
+            super::resolve(
+                &mut [Block::Image(image(ImageSource::Upload(1)))],
+                &[uploaded],
+                &[]
+            ),
+            Err(Invalid::Block(0))
+        );
+        // Image of another article
+        assert_eq!(
+            super::resolve(
+                &mut [Block::Image(image(ImageSource::Id(uploaded)))],
+                &[],
+                &[]
+            ),
+            Err(Invalid::Block(0))
+        );
+    }
+
+    #[test]
+    fn test_embed_url() {
+        assert_eq!(
+            super::embed_url("https://youtu.be/dQw4w9WgXcQ").as_deref(),
+            Some("https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ")
+        );
+        assert_eq!(
+            super::embed_url("https://www.youtube.com/embed/dQw4w9WgXcQ?showinfo=0").as_deref(),
+            Some("https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ")
+        );
+        assert_eq!(
+            super::embed_url("https://player.vimeo.com/video/76979871/").as_deref(),
+            Some("https://player.vimeo.com/video/76979871?title=0&byline=0&portrait=0")
+        );
+        assert_eq!(
+            super::embed_url("http://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            None
+        );
+        assert_eq!(
+            super::embed_url("https://www.youtube.com.example.com/embed/dQw4w9WgXcQ"),
+            None
+        );
+        assert_eq!(super::embed_url("javascript:alert(1)"), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let id = Uuid::parse_str("936da01f-9abd-4d9d-80c7-02af85c822a8").unwrap();
+        let images = vec![BlogArticleImage {
+            id,
+            path: String::from("3_0_1640000000.jpg"),
+            width: Some(700),
+            height: Some(400),
+            placeholder: None,
+        }];
+        let html = super::render(
+            &[
+                Block::Heading {
+                    level: 3,
+                    text: String::from("<Lorem>"),
+                },
+                Block::Image(Image {
+                    source: ImageSource::Id(id),
+                    alt: String::from(r#"" onerror="alert(1)"#),
+                    caption: Some(String::from("Légende")),
+                }),
+                Block::Image(image(ImageSource::Id(Uuid::nil()))),
+                Block::List {
+                    ordered: true,
+                    items: vec![String::from("Un"), String::from("Deux")],
+                },
+            ],
+            &images,
+        );
+
+        assert!(html.starts_with("<h3>&lt;Lorem&gt;</h3><figure><picture"));
+        assert!(html.contains(r#"data-src="/uploads/3_0_1640000000.jpg""#));
+        assert!(html.contains(r#"data-srcset="/uploads/mobile/3_0_1640000000.webp""#));
+        assert!(html.contains(r#"alt="&quot; onerror=&quot;alert(1)""#));
+        assert!(html.contains(r#"width="700" height="400""#));
+        assert!(html.contains("<figcaption>Légende</figcaption></figure>"));
+        // The deleted image is not rendered
+        assert_eq!(html.matches("<figure>").count(), 1);
+        assert!(html.ends_with("<ol><li>Un</li><li>Deux</li></ol>"));
+    }
+
+    #[test]
+    fn test_from_html() {
+        let id = Uuid::parse_str("936da01f-9abd-4d9d-80c7-02af85c822a8").unwrap();
+
+        assert_eq!(
+            super::from_html(&format!(
+                "<p>Bonjour <b>à tous</b></p><p><br></p><p>[[{}]]Légende</p><ul><li>Un</li><li>Deux</li></ul>",
+                id
+            )),
+            vec![
+                Block::Paragraph {
+                    text: String::from("Bonjour <b>à tous</b>")
+                },
+                Block::Image(image(ImageSource::Id(id))),
+                Block::Paragraph {
+                    text: String::from("Légende")
+                },
+                Block::List {
+                    ordered: false,
+                    items: vec![String::from("Un"), String::from("Deux")]
+                }
+            ]
+        );
+        assert_eq!(
+            super::from_html("Lorem ipsum"),
+            vec![Block::Paragraph {
+                text: String::from("Lorem ipsum")
+            }]
+        );
+    }
+}
@@ -1,9 +1,43 @@
+use crate::utils::html;
 use sqlx::{types::Uuid, Error, PgPool};
 
 #[derive(sqlx::FromRow)]
 pub struct BlogArticleImage {
     pub id: Uuid,
     pub path: String,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub placeholder: Option<String>,
+}
+
+impl BlogArticleImage {
+    /// Lazy loaded responsive markup of the image
+    pub fn picture(&self, alt: &str) -> String {
+        let filename = self.path.split('.').next().unwrap_or_default();
+
+        format!(
+            r#"<picture class="lazy"{}>
+                <source data-srcset="/uploads/mobile/{}.webp" media="(max-width: 768px)" type="image/webp" />
+                <source data-srcset="/uploads/mobile/{}" media="(max-width: 768px)" />
+                <source data-srcset="/uploads/{}.webp" media="(min-width: 768px)" type="image/webp" />
+
+                <img data-src="/uploads/{}" alt="{}"{} />
+            </picture>"#,
+            match &self.placeholder {
+                Some(placeholder) => format!(r#" style="--placeholder: url({})""#, placeholder),
+                None => String::new(),
+            },
+            filename,
+            self.path,
+            filename,
+            self.path,
+            html::escape(alt),
+            match (self.width, self.height) {
+                (Some(width), Some(height)) => format!(r#" width="{}" height="{}""#, width, height),
+                _ => String::new(),
+            }
+        )
+    }
 }
 
 pub async fn get_all(pool: &PgPool, article_id: i16) -> Vec<BlogArticleImage> {
@@ -11,7 +45,10 @@ pub async fn get_all(pool: &PgPool, article_id: i16) -> Vec<BlogArticleImage> {
         BlogArticleImage,
         r#"SELECT
             bai.id AS "id",
-            f.path AS "path"
+            f.path AS "path",
+            f.width,
+            f.height,
+            f.placeholder
         FROM blog_article_images bai
         JOIN files f ON bai.file_id = f.id
         WHERE bai.article_id = $1"#,
@@ -0,0 +1,300 @@
+//! Content of the articles written in Markdown, rendered with CommonMark plus
+//! the tables, the footnotes and the strikethrough extensions. The images are
+//! the ones of the article, referenced as `image:{id}`
+
+use super::images::BlogArticleImage;
+use crate::utils::html;
+use once_cell::sync::Lazy;
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
+use regex::{Captures, Regex};
+use sqlx::types::Uuid;
+use syntect::{
+    html::{ClassStyle, ClassedHTMLGenerator},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+/// Maximum length (in bytes) of the source of an article
+pub const MAX_LENGTH: usize = 100_000;
+
+/// Prefix of the classes of the highlighted code, see `article.scss`
+const HIGHLIGHT_PREFIX: &str = "hl-";
+
+static SYNTAXES: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
+
+/// Code block with its tokens as classed spans, as plain text when the
+/// language is unknown
+fn highlight(code: &str, language: &str) -> String {
+    let syntax = SYNTAXES
+        .find_syntax_by_token(language)
+        .unwrap_or_else(|| SYNTAXES.find_syntax_plain_text());
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(
+        syntax,
+        &SYNTAXES,
+        ClassStyle::SpacedPrefixed {
+            prefix: HIGHLIGHT_PREFIX,
+        },
+    );
+
+    for line in LinesWithEndings::from(code) {
+        generator.parse_html_for_line_which_includes_newline(line);
+    }
+
+    format!(
+        r#"<pre class="highlight"><code>{}</code></pre>"#,
+        generator.finalize()
+    )
+}
+
+/// The `<h1>` being the title of the article, `#` is a `<h2>` and the levels
+/// stop at `<h4>`
+fn heading_level(level: HeadingLevel) -> usize {
+    (level as usize + 1).min(4)
+}
+
+/// Number of a footnote, by order of appearance
+fn footnote_number(footnotes: &mut Vec<String>, name: &str) -> usize {
+    match footnotes.iter().position(|footnote| footnote == name) {
+        Some(i) => i + 1,
+        None => {
+            footnotes.push(name.to_string());
+            footnotes.len()
+        }
+    }
+}
+
+/// Placeholder of the `i`th image, replaced once the HTML is sanitized. The
+/// key is drawn for each render, so that the text of the article can not
+/// contain a placeholder
+fn placeholder(key: u64, i: usize) -> String {
+    format!("[[picture-{:016x}-{}]]", key, i)
+}
+
+/// Sanitized HTML of an article, `images` being the ones of the article
+pub fn render(source: &str, images: &[BlogArticleImage]) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+
+    let mut events = vec![];
+    // Language and content of the code block being read
+    let mut code: Option<(String, String)> = None;
+    // Image and alternative text of the image being read
+    let mut image: Option<(Option<&BlogArticleImage>, String)> = None;
+    let mut pictures = vec![];
+    let mut footnotes = vec![];
+    let key = rand::random::<u64>();
+
+    for event in Parser::new_ext(source, options) {
+        if let Some((language, content)) = &mut code {
+            match event {
+                Event::Text(text) => content.push_str(&text),
+                Event::End(Tag::CodeBlock(_)) => {
+                    events.push(Event::Html(highlight(content, language).into()));
+                    code = None;
+                }
+                _ => (),
+            }
+
+            continue;
+        }
+
+        if let Some((file, alt)) = &mut image {
+            match event {
+                Event::Text(text) | Event::Code(text) => alt.push_str(&text),
+                Event::End(Tag::Image(..)) => {
+                    if let Some(file) = file {
+                        events.push(Event::Text(placeholder(key, pictures.len()).into()));
+                        pictures.push(file.picture(alt));
+                    }
+
+                    image = None;
+                }
+                _ => (),
+            }
+
+            continue;
+        }
+
+        let event = match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+
+                code = Some((language, String::new()));
+                continue;
+            }
+            // Only the images of the article are displayed
+            Event::Start(Tag::Image(_, url, _)) => {
+                let file = url
+                    .strip_prefix("image:")
+                    .and_then(|id| Uuid::parse_str(id).ok())
+                    .and_then(|id| images.iter().find(|file| file.id == id));
+
+                image = Some((file, String::new()));
+                continue;
+            }
+            Event::Start(Tag::Heading(level, ..)) => {
+                Event::Html(format!("<h{}>", heading_level(level)).into())
+            }
+            Event::End(Tag::Heading(level, ..)) => {
+                Event::Html(format!("</h{}>\n", heading_level(level)).into())
+            }
+            Event::FootnoteReference(name) => {
+                let number = footnote_number(&mut footnotes, &name);
+
+                Event::Html(
+                    format!(
+                        r##"<sup class="footnote-reference"><a href="#fn-{0}">{0}</a></sup>"##,
+                        number
+                    )
+                    .into(),
+                )
+            }
+            Event::Start(Tag::FootnoteDefinition(name)) => {
+                let number = footnote_number(&mut footnotes, &name);
+
+                Event::Html(
+                    format!(
+                        r#"<div class="footnote-definition" id="fn-{0}"><sup class="footnote-definition-label">{0}</sup>"#,
+                        number
+                    )
+                    .into(),
+                )
+            }
+            Event::End(Tag::FootnoteDefinition(_)) => Event::Html("</div>\n".into()),
+            // The HTML written in the source is displayed as is
+            Event::Html(html) => Event::Text(html),
+            event => event,
+        };
+
+        events.push(event);
+    }
+
+    let mut rendered = String::new();
+    pulldown_cmark::html::push_html(&mut rendered, events.into_iter());
+
+    let mut rendered = html::clean_markdown(&rendered);
+
+    for (i, picture) in pictures.iter().enumerate() {
+        rendered = rendered.replacen(&placeholder(key, i), picture, 1);
+    }
+
+    rendered
+}
+
+/// Replace the references to the pictures sent with the source,
+/// `upload:{index}`, by the ones to the images they were saved as. `None` when
+/// a picture was not sent
+pub fn resolve(source: &str, uploads: &[Uuid]) -> Option<String> {
+    let references = Regex::new(r"\bupload:(\d+)").unwrap();
+
+    for reference in references.captures_iter(source) {
+        match reference[1].parse::<usize>() {
+            Ok(i) if i < uploads.len() => (),
+            _ => return None,
+        }
+    }
+
+    Some(
+        references
+            .replace_all(source, |reference: &Captures| {
+                format!("image:{}", uploads[reference[1].parse::<usize>().unwrap()])
+            })
+            .into_owned(),
+    )
+}
+
+/// Ids of the images referenced by the source
+pub fn image_ids(source: &str) -> Vec<Uuid> {
+    Regex::new(r"\bimage:([0-9a-fA-F-]{36})")
+        .unwrap()
+        .captures_iter(source)
+        .filter_map(|reference| Uuid::parse_str(&reference[1]).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::services::blog::articles::images::BlogArticleImage;
+    use sqlx::types::Uuid;
+
+    #[test]
+    fn test_render() {
+        let html = super::render(
+            "# Lorem\n\n#### Ipsum\n\nDolor *sit* **amet**<script>alert(1)</script>\n\n\
+            ```rust\nfn main() {}\n```\n\n\
+            | A | B |\n|---|---|\n| 1 | 2 |\n\n\
+            Note[^note]\n\n[^note]: Consectetur",
+            &[],
+        );
+
+        assert!(html.starts_with("<h2>Lorem</h2>\n<h4>Ipsum</h4>\n"));
+        assert!(html.contains("<p>Dolor <em>sit</em> <strong>amet</strong>&lt;script&gt;"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains(r#"<pre class="highlight"><code><span class="hl-source hl-rust">"#));
+        assert!(html.contains("hl-storage"));
+        assert!(html.contains("<table><thead><tr><th>A</th><th>B</th></tr></thead>"));
+        assert!(html.contains(
+            r##"Note<sup class="footnote-reference"><a href="#fn-1" rel="noopener noreferrer">1</a></sup>"##
+        ));
+        assert!(html.contains(r#"<div class="footnote-definition" id="fn-1">"#));
+    }
+
+    #[test]
+    fn test_render_images() {
+        let id = Uuid::parse_str("936da01f-9abd-4d9d-80c7-02af85c822a8").unwrap();
+        let images = vec![BlogArticleImage {
+            id,
+            path: String::from("3_0_1640000000.jpg"),
+            width: None,
+            height: None,
+            placeholder: None,
+        }];
+        let html = super::render(
+            &format!(
+                "![Un \"chat\"](image:{})\n\n![Ailleurs](https://example.com/x.png)\n\n\
+                ![Supprimée](image:{})",
+                id,
+                Uuid::nil()
+            ),
+            &images,
+        );
+
+        assert_eq!(html.matches("<picture").count(), 1);
+        assert!(html.contains(
+            r#"<img data-src="/uploads/3_0_1640000000.jpg" alt="Un &quot;chat&quot;" />"#
+        ));
+        assert!(!html.contains("example.com"));
+        assert!(!html.contains("[[picture-"));
+
+        // The placeholders written in the text are kept as is
+        let html = super::render(
+            &format!("[[picture-0]] `[[picture-0]]`\n\n![Lorem](image:{})", id),
+            &images,
+        );
+
+        assert!(html.starts_with("<p>[[picture-0]] <code>[[picture-0]]</code></p>"));
+        assert_eq!(html.matches("<picture").count(), 1);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let id = Uuid::parse_str("936da01f-9abd-4d9d-80c7-02af85c822a8").unwrap();
+        let source = super::resolve("![Lorem](upload:0) ![Ipsum](upload:0)", &[id]).unwrap();
+
+        assert_eq!(
+            source,
+            format!("![Lorem](image:{0}) ![Ipsum](image:{0})", id)
+        );
+        assert_eq!(super::image_ids(&source), vec![id, id]);
+        assert_eq!(super::resolve("![Lorem](upload:1)", &[id]), None);
+    }
+}
@@ -1,7 +1,29 @@
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use sqlx::{Error, PgPool};
+use sqlx::{types::Json, Error, PgPool};
 
+pub mod blocks;
 pub mod images;
+pub mod markdown;
+
+/// Source the content of an article is written in
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// See `blocks`, from the editor of the back office
+    Blocks,
+    /// See `markdown`
+    Markdown,
+}
+
+impl Format {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Format::Blocks => "blocks",
+            Format::Markdown => "markdown",
+        }
+    }
+}
 
 pub async fn exists(pool: &PgPool, id: i16) -> bool {
     sqlx::query!("SELECT 1 AS one FROM blog_articles WHERE id = $1", id)
@@ -100,20 +122,18 @@ pub async fn insert(
     cover_id: i32,
     title: &str,
     description: Option<&str>,
-    content: &str,
     is_published: Option<bool>,
     is_seo: Option<bool>,
 ) -> Result<i16, Error> {
     let res = sqlx::query!(
         "INSERT INTO blog_articles
-            (category_id, cover_id, title, description, content, is_published, is_seo)
-        VALUES ($1, $2, $3, $4, $5, $6, $7)
+            (category_id, cover_id, title, description, is_published, is_seo)
+        VALUES ($1, $2, $3, $4, $5, $6)
         RETURNING id",
         category_id,
         cover_id,
         title,
         description,
-        content,
         is_published,
         is_seo
     )
@@ -123,6 +143,76 @@ pub async fn insert(
     Ok(res.id)
 }
 
+/// Replace the content of an article by already validated blocks
+pub async fn set_blocks(
+    pool: impl sqlx::Executor<'_, Database = sqlx::Postgres>,
+    id: i16,
+    blocks: &[blocks::Block],
+) -> Result<bool, Error> {
+    let res = sqlx::query("UPDATE blog_articles SET blocks = $1 WHERE id = $2")
+        .bind(Json(blocks))
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+/// Convert the HTML content of the articles written before the blocks, see
+/// `blocks::from_html`, adding the columns of the blocks, of the Markdown, of
+/// the notifications and of the comments to the databases created before them
+/// (see `sql/tables.sql`). Returns the number of converted articles
+pub async fn migrate_to_blocks(pool: &PgPool) -> Result<usize, Error> {
+    let has_notifications = sqlx::query!(
+        r#"SELECT EXISTS (
+                SELECT 1 FROM information_schema.columns
+                WHERE table_name = 'blog_articles' AND column_name = 'notified_date'
+            ) AS "exists!""#
+    )
+    .fetch_one(pool)
+    .await?
+    .exists;
+
+    sqlx::query(
+        "ALTER TABLE blog_articles
+            ADD COLUMN IF NOT EXISTS format VARCHAR(10) NOT NULL DEFAULT 'blocks'
+                CHECK (format IN ('blocks', 'markdown')),
+            ADD COLUMN IF NOT EXISTS blocks JSONB NOT NULL DEFAULT '[]',
+            ADD COLUMN IF NOT EXISTS markdown TEXT,
+            ADD COLUMN IF NOT EXISTS notified_date TIMESTAMP WITH TIME ZONE,
+            ADD COLUMN IF NOT EXISTS comments_closed BOOLEAN NOT NULL DEFAULT FALSE,
+            ALTER COLUMN content DROP NOT NULL",
+    )
+    .execute(pool)
+    .await?;
+
+    // The subscribers are not notified of the articles published before the
+    // newsletter when they are saved again
+    if !has_notifications {
+        sqlx::query!(r#"UPDATE blog_articles SET notified_date = "date" WHERE is_published"#)
+            .execute(pool)
+            .await?;
+    }
+
+    let articles = sqlx::query!(
+        r#"SELECT id, content AS "content!"
+            FROM blog_articles
+            WHERE content IS NOT NULL"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for article in &articles {
+        sqlx::query("UPDATE blog_articles SET blocks = $1, content = NULL WHERE id = $2")
+            .bind(Json(blocks::from_html(&article.content)))
+            .bind(article.id)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(articles.len())
+}
+
 pub async fn partial_update(
     pool: impl sqlx::Executor<'_, Database = sqlx::Postgres>,
     id: i16,
@@ -167,6 +257,34 @@ pub async fn partial_update(
     Ok(false)
 }
 
+/// Published article the subscribers are notified of
+#[derive(Debug)]
+pub struct Publication {
+    pub title: String,
+    pub description: Option<String>,
+    pub uri: Option<String>,
+    pub cover: String,
+}
+
+/// Mark a published article as notified, once. Returns the article when its
+/// subscribers are still to be notified
+pub async fn mark_as_notified(pool: &PgPool, id: i16) -> Result<Option<Publication>, Error> {
+    sqlx::query_as!(
+        Publication,
+        r#"UPDATE blog_articles ba
+            SET notified_date = NOW()
+            FROM files f
+            WHERE ba.id = $1
+                AND f.id = ba.cover_id
+                AND ba.is_published
+                AND ba.notified_date IS NULL
+            RETURNING ba.title, ba.description, ba.uri, f.path AS cover"#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
 pub async fn delete(pool: &PgPool, id: i16) -> bool {
     sqlx::query!("DELETE FROM blog_articles WHERE id = $1", id)
         .execute(pool)
@@ -0,0 +1,258 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, PgPool};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Waiting for moderation, not displayed
+    Pending,
+    Approved,
+    Rejected,
+    Spam,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Approved => "approved",
+            Status::Rejected => "rejected",
+            Status::Spam => "spam",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Comment {
+    pub id: i32,
+    pub article_id: i16,
+    /// Top level comment answered, `None` for a top level comment
+    pub parent_id: Option<i32>,
+    pub author: String,
+    pub email: Option<String>,
+    /// Sanitized HTML
+    pub content: String,
+    pub status: String,
+    pub is_author: bool,
+    pub date: DateTime<Utc>,
+}
+
+/// Comment of the moderation queue, with its article
+#[derive(Debug, Serialize)]
+pub struct QueuedComment {
+    pub id: i32,
+    pub article_id: i16,
+    pub article_title: String,
+    pub article_uri: Option<String>,
+    pub parent_id: Option<i32>,
+    pub author: String,
+    pub email: Option<String>,
+    pub content: String,
+    pub status: String,
+    pub is_author: bool,
+    pub date: DateTime<Utc>,
+}
+
+pub struct NewComment<'a> {
+    pub article_id: i16,
+    pub parent_id: Option<i32>,
+    pub author: &'a str,
+    pub email: Option<&'a str>,
+    /// Already sanitized HTML
+    pub content: &'a str,
+    pub status: Status,
+    pub is_author: bool,
+}
+
+pub async fn add(pool: &PgPool, comment: &NewComment<'_>) -> Result<i32, Error> {
+    let res = sqlx::query!(
+        "INSERT INTO blog_comments (article_id, parent_id, author, email, content, status, is_author)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id",
+        comment.article_id,
+        comment.parent_id,
+        comment.author,
+        comment.email,
+        comment.content,
+        comment.status.as_str(),
+        comment.is_author
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(res.id)
+}
+
+pub async fn get(pool: &PgPool, id: i32) -> Result<Comment, Error> {
+    sqlx::query_as!(
+        Comment,
+        r#"SELECT id, article_id, parent_id, author, email, content, status, is_author, "date"
+            FROM blog_comments
+            WHERE id = $1"#,
+        id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Approved comments of an article, the oldest first
+pub async fn get_approved(pool: &PgPool, article_id: i16) -> Result<Vec<Comment>, Error> {
+    sqlx::query_as!(
+        Comment,
+        r#"SELECT id, article_id, parent_id, author, email, content, status, is_author, "date"
+            FROM blog_comments
+            WHERE article_id = $1 AND status = 'approved'
+            ORDER BY "date""#,
+        article_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Comments of a status, the most recent first
+pub async fn get_all(
+    pool: &PgPool,
+    status: Status,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<QueuedComment>, Error> {
+    sqlx::query_as!(
+        QueuedComment,
+        r#"SELECT
+                bc.id,
+                bc.article_id,
+                ba.title AS article_title,
+                ba.uri AS article_uri,
+                bc.parent_id,
+                bc.author,
+                bc.email,
+                bc.content,
+                bc.status,
+                bc.is_author,
+                bc."date"
+            FROM blog_comments bc
+            JOIN blog_articles ba ON ba.id = bc.article_id
+            WHERE bc.status = $1
+            ORDER BY bc."date" DESC
+            LIMIT $2 OFFSET $3"#,
+        status.as_str(),
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn count(pool: &PgPool, status: Status) -> Result<i64, Error> {
+    let res = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM blog_comments WHERE status = $1"#,
+        status.as_str()
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(res.count)
+}
+
+/// Whether the article can be commented: published, and neither the article
+/// nor the whole blog having its comments closed
+pub async fn are_open(pool: &PgPool, article_id: i16) -> Result<bool, Error> {
+    let res = sqlx::query!(
+        r#"SELECT (COALESCE(ba.is_published, FALSE)
+                AND NOT ba.comments_closed
+                AND s.comments_enabled) AS "open!"
+            FROM blog_articles ba, settings s
+            WHERE ba.id = $1"#,
+        article_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(res.map_or(false, |res| res.open))
+}
+
+pub async fn set_status(pool: &PgPool, id: i32, status: Status) -> Result<bool, Error> {
+    let res = sqlx::query!(
+        "UPDATE blog_comments SET status = $1 WHERE id = $2",
+        status.as_str(),
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+/// Delete a comment and its replies
+pub async fn delete(pool: &PgPool, id: i32) -> Result<bool, Error> {
+    let res = sqlx::query!("DELETE FROM blog_comments WHERE id = $1", id)
+        .execute(pool)
+        .await?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NewComment, Status};
+    use crate::create_pool;
+    use dotenv::dotenv;
+
+    #[actix_rt::test]
+    async fn test_moderation() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let id = super::add(
+            &pool,
+            &NewComment {
+                article_id: 3,
+                parent_id: None,
+                author: "Jean",
+                email: Some("jean.dupont@example.com"),
+                content: "Très bel article",
+                status: Status::Pending,
+                is_author: false,
+            },
+        )
+        .await
+        .unwrap();
+        let is_displayed =
+            |comments: Vec<super::Comment>| comments.iter().any(|comment| comment.id == id);
+
+        // Held for moderation
+        assert!(!is_displayed(super::get_approved(&pool, 3).await.unwrap()));
+        assert!(super::get_all(&pool, Status::Pending, 100, 0)
+            .await
+            .unwrap()
+            .iter()
+            .any(|comment| comment.id == id));
+
+        assert!(super::set_status(&pool, id, Status::Approved)
+            .await
+            .unwrap());
+        assert!(is_displayed(super::get_approved(&pool, 3).await.unwrap()));
+
+        let reply = super::add(
+            &pool,
+            &NewComment {
+                article_id: 3,
+                parent_id: Some(id),
+                author: "Ludivine Farat",
+                email: None,
+                content: "Merci !",
+                status: Status::Approved,
+                is_author: true,
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(super::get(&pool, reply).await.unwrap().parent_id, Some(id));
+
+        // The replies are deleted with their comment
+        assert!(super::delete(&pool, id).await.unwrap());
+        assert!(super::get(&pool, reply).await.is_err());
+    }
+}
@@ -1,5 +1,6 @@
 pub mod articles;
 pub mod categories;
+pub mod comments;
 
 #[derive(sqlx::FromRow)]
 pub struct Category {
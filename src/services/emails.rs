@@ -0,0 +1,265 @@
+//! Outbox of the emails: the handlers only save the emails to send, a
+//! background worker sends them and retries the failed ones with an
+//! exponential backoff. The emails given up on are listed in the back office.
+
+use crate::utils::mail::{Email, Transport};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Error, PgPool};
+
+/// Attempts before giving up on an email
+const MAX_ATTEMPTS: i16 = 6;
+
+/// Delay (in seconds) before the first retry, doubled after each failure
+const RETRY_DELAY: i64 = 60;
+
+/// Emails sent by each run of the worker
+const BATCH_SIZE: i64 = 20;
+
+#[derive(Debug, Serialize)]
+pub struct QueuedEmail {
+    pub id: i32,
+    pub recipient: String,
+    pub subject: String,
+    /// `pending`, `sent` or `failed`
+    pub status: String,
+    pub attempts: i16,
+    pub last_error: Option<String>,
+    pub next_attempt_date: DateTime<Utc>,
+    pub sent_date: Option<DateTime<Utc>>,
+    pub date: DateTime<Utc>,
+}
+
+/// Save an email to be sent by the worker
+pub async fn enqueue(pool: &PgPool, email: &Email) -> Result<i32, Error> {
+    let res = sqlx::query!(
+        "INSERT INTO email_outbox (recipient, sender, reply_to, subject, text_body, html_body)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id",
+        email.to,
+        email.from,
+        email.reply_to,
+        email.subject,
+        email.text,
+        email.html
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(res.id)
+}
+
+pub async fn get(pool: &PgPool, id: i32) -> Result<QueuedEmail, Error> {
+    sqlx::query_as!(
+        QueuedEmail,
+        r#"SELECT id, recipient, subject, status, attempts, last_error, next_attempt_date,
+                sent_date, "date"
+            FROM email_outbox
+            WHERE id = $1"#,
+        id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Delay (in seconds) before the next attempt of an email which failed
+/// `attempts` times
+fn backoff(attempts: i16) -> i64 {
+    RETRY_DELAY * 2_i64.pow((attempts.max(1) - 1) as u32)
+}
+
+/// Send a pending email and record the result of the attempt. Returns whether
+/// the email has been sent, `false` for an email not pending
+pub async fn deliver(pool: &PgPool, transport: &dyn Transport, id: i32) -> Result<bool, Error> {
+    let row = sqlx::query!(
+        "SELECT recipient, sender, reply_to, subject, text_body, html_body, attempts
+            FROM email_outbox
+            WHERE id = $1 AND status = 'pending'",
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(false),
+    };
+    let email = Email {
+        to: row.recipient,
+        from: row.sender,
+        reply_to: row.reply_to,
+        subject: row.subject,
+        text: row.text_body,
+        html: row.html_body,
+    };
+
+    match transport.send(&email) {
+        Ok(_) => {
+            sqlx::query!(
+                "UPDATE email_outbox
+                    SET status = 'sent', attempts = attempts + 1, sent_date = NOW()
+                    WHERE id = $1",
+                id
+            )
+            .execute(pool)
+            .await?;
+
+            Ok(true)
+        }
+        Err(e) => {
+            let attempts = row.attempts + 1;
+            let status = if attempts >= MAX_ATTEMPTS {
+                "failed"
+            } else {
+                "pending"
+            };
+
+            sqlx::query!(
+                "UPDATE email_outbox
+                    SET status = $1,
+                        attempts = $2,
+                        last_error = $3,
+                        next_attempt_date = NOW() + make_interval(secs => $4)
+                    WHERE id = $5",
+                status,
+                attempts,
+                e.to_string(),
+                backoff(attempts) as f64,
+                id
+            )
+            .execute(pool)
+            .await?;
+
+            Ok(false)
+        }
+    }
+}
+
+/// Send the pending emails due, the oldest first. Returns the number of sent
+/// emails
+pub async fn process(pool: &PgPool, transport: &dyn Transport) -> Result<usize, Error> {
+    let ids = sqlx::query_scalar!(
+        r#"SELECT id AS "id!"
+            FROM email_outbox
+            WHERE status = 'pending' AND next_attempt_date <= NOW()
+            ORDER BY next_attempt_date
+            LIMIT $1"#,
+        BATCH_SIZE
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut sent = 0;
+
+    for id in ids {
+        if deliver(pool, transport, id).await? {
+            sent += 1;
+        }
+    }
+
+    Ok(sent)
+}
+
+/// Emails given up on and emails still pending after a failed attempt, the
+/// most recent first
+pub async fn get_failed(pool: &PgPool) -> Result<Vec<QueuedEmail>, Error> {
+    sqlx::query_as!(
+        QueuedEmail,
+        r#"SELECT id, recipient, subject, status, attempts, last_error, next_attempt_date,
+                sent_date, "date"
+            FROM email_outbox
+            WHERE status = 'failed' OR (status = 'pending' AND attempts > 0)
+            ORDER BY "date" DESC"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Send again an email given up on, or send now a pending one
+pub async fn retry(pool: &PgPool, id: i32) -> Result<bool, Error> {
+    let res = sqlx::query!(
+        "UPDATE email_outbox
+            SET status = 'pending', attempts = 0, next_attempt_date = NOW()
+            WHERE id = $1 AND status <> 'sent'",
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::create_pool;
+    use crate::utils::mail::{Email, MailError, MemoryTransport, Transport};
+    use dotenv::dotenv;
+
+    struct FailingTransport;
+
+    impl Transport for FailingTransport {
+        fn send(&self, _email: &Email) -> Result<(), MailError> {
+            Err(MailError::Send("Connection refused".to_string()))
+        }
+    }
+
+    fn email() -> Email {
+        Email {
+            to: "jean.dupont@example.com".to_string(),
+            from: "hello@ludivinefarat.fr".to_string(),
+            reply_to: None,
+            subject: "Test".to_string(),
+            text: Some("Lorem ipsum dolor sit amet".to_string()),
+            html: None,
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(super::backoff(1), 60);
+        assert_eq!(super::backoff(2), 120);
+        assert_eq!(super::backoff(5), 960);
+    }
+
+    #[actix_rt::test]
+    async fn test_deliver() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let transport = MemoryTransport::default();
+        let id = super::enqueue(&pool, &email()).await.unwrap();
+
+        assert!(super::deliver(&pool, &transport, id).await.unwrap());
+        // A sent email is not sent twice
+        assert!(!super::deliver(&pool, &transport, id).await.unwrap());
+        assert_eq!(transport.sent(), vec![email()]);
+        assert_eq!(super::get(&pool, id).await.unwrap().status, "sent");
+    }
+
+    #[actix_rt::test]
+    async fn test_deliver_failure() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let id = super::enqueue(&pool, &email()).await.unwrap();
+
+        for _ in 0..super::MAX_ATTEMPTS {
+            assert!(!super::deliver(&pool, &FailingTransport, id).await.unwrap());
+        }
+
+        let queued = super::get(&pool, id).await.unwrap();
+
+        assert_eq!(queued.status, "failed");
+        assert_eq!(queued.attempts, super::MAX_ATTEMPTS);
+        assert_eq!(
+            queued.last_error.as_deref(),
+            Some("Email sending failed: Connection refused")
+        );
+        assert!(super::get_failed(&pool)
+            .await
+            .unwrap()
+            .iter()
+            .any(|queued| queued.id == id));
+
+        assert!(super::retry(&pool, id).await.unwrap());
+        assert_eq!(super::get(&pool, id).await.unwrap().status, "pending");
+    }
+}
@@ -1,14 +1,21 @@
+use crate::utils::image::Metadata;
 use sqlx::{Error, PgPool};
 
 pub async fn insert(
     pool: impl sqlx::Executor<'_, Database = sqlx::Postgres>,
     name: Option<&str>,
     path: &str,
+    metadata: Option<&Metadata>,
 ) -> Result<i32, Error> {
     let res = sqlx::query!(
-        r#"INSERT INTO files (name, path) VALUES ($1, $2) RETURNING id"#,
+        r#"INSERT INTO files (name, path, width, height, placeholder)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id"#,
         name,
-        path
+        path,
+        metadata.map(|metadata| metadata.width as i32),
+        metadata.map(|metadata| metadata.height as i32),
+        metadata.map(|metadata| metadata.placeholder.as_str())
     )
     .fetch_one(pool)
     .await?;
@@ -0,0 +1,316 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Error, PgPool};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    New,
+    Read,
+    Replied,
+    Archived,
+    Spam,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::New => "new",
+            Status::Read => "read",
+            Status::Replied => "replied",
+            Status::Archived => "archived",
+            Status::Spam => "spam",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Message {
+    pub id: i32,
+    pub firstname: String,
+    pub lastname: String,
+    pub phone_number: Option<String>,
+    pub email: String,
+    pub content: String,
+    pub status: String,
+    pub date: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Reply {
+    pub id: i32,
+    pub content: String,
+    pub date: DateTime<Utc>,
+}
+
+/// Messages of the inbox to list
+#[derive(Debug, Default)]
+pub struct Filter<'a> {
+    /// Every message neither archived nor spam by default
+    pub status: Option<Status>,
+    /// Part of the name, the email, the phone number or the content
+    pub search: Option<&'a str>,
+}
+
+impl Filter<'_> {
+    /// `ILIKE` pattern of the search, its wildcards escaped
+    fn pattern(&self) -> Option<String> {
+        self.search.map(|search| {
+            format!(
+                "%{}%",
+                search
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            )
+        })
+    }
+}
+
+/// Save a message received with the contact form, `Status::Spam` to put it
+/// directly in the spam folder
+pub async fn add(
+    pool: &PgPool,
+    firstname: &str,
+    lastname: &str,
+    phone_number: Option<&str>,
+    email: &str,
+    content: &str,
+    status: Status,
+) -> Result<i32, Error> {
+    let res = sqlx::query!(
+        "INSERT INTO contact_messages (firstname, lastname, phone_number, email, content, status)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id",
+        firstname,
+        lastname,
+        phone_number,
+        email,
+        content,
+        status.as_str()
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(res.id)
+}
+
+pub async fn get(pool: &PgPool, id: i32) -> Result<Message, Error> {
+    sqlx::query_as!(
+        Message,
+        r#"SELECT id, firstname, lastname, phone_number, email, content, status, "date"
+            FROM contact_messages
+            WHERE id = $1"#,
+        id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Messages of the filter, the most recent first
+pub async fn get_all(
+    pool: &PgPool,
+    filter: &Filter<'_>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Message>, Error> {
+    sqlx::query_as!(
+        Message,
+        r#"SELECT id, firstname, lastname, phone_number, email, content, status, "date"
+            FROM contact_messages
+            WHERE (status = $1 OR ($1 IS NULL AND status NOT IN ('archived', 'spam')))
+                AND ($2::TEXT IS NULL
+                    OR concat_ws(' ', firstname, lastname, email, phone_number, content) ILIKE $2)
+            ORDER BY "date" DESC
+            LIMIT $3 OFFSET $4"#,
+        filter.status.map(|status| status.as_str()),
+        filter.pattern(),
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Number of messages of the filter
+pub async fn count(pool: &PgPool, filter: &Filter<'_>) -> Result<i64, Error> {
+    let res = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!"
+            FROM contact_messages
+            WHERE (status = $1 OR ($1 IS NULL AND status NOT IN ('archived', 'spam')))
+                AND ($2::TEXT IS NULL
+                    OR concat_ws(' ', firstname, lastname, email, phone_number, content) ILIKE $2)"#,
+        filter.status.map(|status| status.as_str()),
+        filter.pattern()
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(res.count)
+}
+
+pub async fn count_unread(pool: &PgPool) -> Result<i64, Error> {
+    let res =
+        sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM contact_messages WHERE status = 'new'"#)
+            .fetch_one(pool)
+            .await?;
+
+    Ok(res.count)
+}
+
+/// Messages received from an address in the last 24 hours, whatever their
+/// status
+pub async fn count_recent_from(pool: &PgPool, email: &str) -> Result<i64, Error> {
+    let res = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!"
+            FROM contact_messages
+            WHERE LOWER(email) = LOWER($1) AND "date" >= NOW() - interval '24 hours'"#,
+        email
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(res.count)
+}
+
+pub async fn set_status(pool: &PgPool, id: i32, status: Status) -> Result<bool, Error> {
+    let res = sqlx::query!(
+        "UPDATE contact_messages SET status = $1 WHERE id = $2",
+        status.as_str(),
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+/// Mark a new message as read, the other statuses are kept
+pub async fn mark_as_read(pool: &PgPool, id: i32) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE contact_messages SET status = 'read' WHERE id = $1 AND status = 'new'",
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_replies(pool: &PgPool, message_id: i32) -> Result<Vec<Reply>, Error> {
+    sqlx::query_as!(
+        Reply,
+        r#"SELECT id, content, "date" FROM contact_replies WHERE message_id = $1 ORDER BY "date""#,
+        message_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Save a reply sent to the author of a message, which becomes replied
+pub async fn add_reply(pool: &PgPool, message_id: i32, content: &str) -> Result<i32, Error> {
+    let mut transaction = pool.begin().await?;
+    let res = sqlx::query!(
+        "INSERT INTO contact_replies (message_id, content) VALUES ($1, $2) RETURNING id",
+        message_id,
+        content
+    )
+    .fetch_one(&mut transaction)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE contact_messages SET status = 'replied' WHERE id = $1",
+        message_id
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(res.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Filter, Status};
+    use crate::create_pool;
+    use dotenv::dotenv;
+
+    #[actix_rt::test]
+    async fn test_inbox() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let search = format!("inbox_{}", chrono::Utc::now().timestamp_nanos());
+        let id = super::add(
+            &pool,
+            "Jean",
+            "Dupont",
+            None,
+            "jean.dupont@example.com",
+            &format!("Lorem ipsum dolor sit amet, consectetur {}", search),
+            Status::New,
+        )
+        .await
+        .unwrap();
+        let filter = Filter {
+            status: None,
+            search: Some(&search),
+        };
+
+        assert_eq!(super::count(&pool, &filter).await.unwrap(), 1);
+        assert!(super::count_unread(&pool).await.unwrap() >= 1);
+        assert!(
+            super::count_recent_from(&pool, "Jean.Dupont@example.com")
+                .await
+                .unwrap()
+                >= 1
+        );
+
+        super::mark_as_read(&pool, id).await.unwrap();
+        super::add_reply(&pool, id, "Merci pour votre message")
+            .await
+            .unwrap();
+
+        let message = super::get(&pool, id).await.unwrap();
+
+        assert_eq!(message.status, "replied");
+        assert_eq!(super::get_replies(&pool, id).await.unwrap().len(), 1);
+
+        // Archived messages leave the inbox
+        super::set_status(&pool, id, Status::Archived)
+            .await
+            .unwrap();
+
+        assert_eq!(super::count(&pool, &filter).await.unwrap(), 0);
+        assert_eq!(
+            super::get_all(
+                &pool,
+                &Filter {
+                    status: Some(Status::Archived),
+                    search: Some(&search),
+                },
+                10,
+                0
+            )
+            .await
+            .unwrap()
+            .len(),
+            1
+        );
+
+        // Wildcards are searched as is
+        assert_eq!(
+            super::count(
+                &pool,
+                &Filter {
+                    status: Some(Status::Archived),
+                    search: Some("%_%_%_%_%_%_%_%_%_%_%_%_%_%_%_%_%_%_%_%_%"),
+                }
+            )
+            .await
+            .unwrap(),
+            0
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! Named interactions of the visitors: contact form sent, video played,
+//! outbound link clicked... Their counts are rolled up with the views, the
+//! funnels are computed from the raw events and so are limited to the
+//! retention period of the metrics.
+
+use super::stats::Period;
+use serde::Serialize;
+use sqlx::{types::Uuid, Error, PgPool};
+
+pub async fn add(
+    pool: &PgPool,
+    name: &str,
+    properties: Option<serde_json::Value>,
+    metric_id: Option<Uuid>,
+    session_id: Option<Uuid>,
+    ip: &str,
+    is_bot: bool,
+) -> Result<Uuid, Error> {
+    let res = sqlx::query!(
+        r#"INSERT INTO metric_events (metric_id, session_id, ip, "name", properties, is_bot)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id"#,
+        metric_id,
+        session_id,
+        ip,
+        name,
+        properties,
+        is_bot
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(res.id)
+}
+
+/// Visitors having reached a step of a funnel
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Step {
+    pub name: String,
+    pub visitors: i64,
+}
+
+/// Visitors going through the events `steps` in this order during the period.
+/// A visitor is its session, or its hashed IP without consent: such a visitor
+/// can not be followed from one day to another
+pub async fn get_funnel(
+    pool: &PgPool,
+    period: &Period,
+    steps: &[String],
+) -> Result<Vec<Step>, Error> {
+    let reached = sqlx::query!(
+        r#"WITH RECURSIVE funnel AS (
+            SELECT 1 AS step, COALESCE(session_id::TEXT, ip) AS visitor, MIN("date") AS "date"
+            FROM metric_events
+            WHERE "name" = ($1::TEXT[])[1]
+                AND "date" >= $2
+                AND "date" < $3
+                AND ($4 OR NOT is_bot)
+            GROUP BY 2
+            UNION ALL
+            SELECT f.step + 1, f.visitor, n."date"
+            FROM funnel f
+                CROSS JOIN LATERAL (
+                    SELECT e."date"
+                    FROM metric_events e
+                    WHERE COALESCE(e.session_id::TEXT, e.ip) = f.visitor
+                        AND e."name" = ($1::TEXT[])[f.step + 1]
+                        AND e."date" >= f."date"
+                        AND e."date" < $3
+                        AND ($4 OR NOT e.is_bot)
+                    ORDER BY e."date"
+                    LIMIT 1
+                ) n
+            WHERE f.step < cardinality($1::TEXT[])
+        )
+        SELECT step AS "step!", COUNT(*) AS "visitors!"
+        FROM funnel
+        GROUP BY 1
+        ORDER BY 1"#,
+        steps,
+        period.start,
+        period.end,
+        period.include_bots
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(steps
+        .iter()
+        .enumerate()
+        .map(|(i, name)| Step {
+            name: name.clone(),
+            visitors: reached
+                .iter()
+                .find(|row| row.step as usize == i + 1)
+                .map(|row| row.visitors)
+                .unwrap_or(0),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::stats::Period;
+    use crate::create_pool;
+    use chrono::{Duration, Utc};
+    use dotenv::dotenv;
+
+    #[actix_rt::test]
+    async fn test_get_funnel() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let (sid, _) = crate::services::metrics::sessions::add(&pool, "test")
+            .await
+            .unwrap();
+        let suffix = Utc::now().timestamp_nanos() % 1_000_000_000;
+        let steps = vec![
+            format!("test_open_{}", suffix),
+            format!("test_play_{}", suffix),
+            format!("test_send_{}", suffix),
+        ];
+
+        // The second step is reached before the first one, it does not count
+        for name in &[&steps[1], &steps[0], &steps[1]] {
+            super::add(
+                &pool,
+                name,
+                Some(serde_json::json!({ "id": 1 })),
+                None,
+                Some(sid),
+                "test",
+                false,
+            )
+            .await
+            .unwrap();
+        }
+
+        let period = Period {
+            start: Utc::now() - Duration::hours(1),
+            end: Utc::now() + Duration::hours(1),
+            include_bots: false,
+        };
+        let funnel = super::get_funnel(&pool, &period, &steps).await.unwrap();
+
+        assert_eq!(
+            funnel.iter().map(|step| step.visitors).collect::<Vec<_>>(),
+            vec![1, 1, 0]
+        );
+        assert_eq!(funnel[2].name, steps[2]);
+    }
+}
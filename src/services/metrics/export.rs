@@ -0,0 +1,385 @@
+//! Export of the raw metrics and of the rollups, formatted by PostgreSQL one
+//! line per row and streamed without loading the whole table in memory.
+
+use super::stats::Period;
+use futures::{channel::mpsc, executor::block_on, SinkExt, StreamExt};
+use serde::Deserialize;
+use sqlx::{Error, PgPool};
+
+/// Rows sent to the client in advance, the query waits for the client to read
+/// them before fetching the next ones
+const BUFFER_SIZE: usize = 256;
+
+/// Columns identifying a visitor, dropped by the anonymized exports
+const PERSONAL_COLUMNS: [&str; 2] = ["ip", "session_id"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Table {
+    Metrics,
+    Sessions,
+    Events,
+    Rollups,
+    TargetRollups,
+    EntryRollups,
+    ReferrerRollups,
+    CampaignRollups,
+    DeviceRollups,
+    EventRollups,
+}
+
+impl Table {
+    /// Table from its name in the URL, e.g. `referrer_rollups`
+    pub fn from_name(name: &str) -> Option<Table> {
+        match name {
+            "metrics" => Some(Table::Metrics),
+            "sessions" => Some(Table::Sessions),
+            "events" => Some(Table::Events),
+            "rollups" => Some(Table::Rollups),
+            "target_rollups" => Some(Table::TargetRollups),
+            "entry_rollups" => Some(Table::EntryRollups),
+            "referrer_rollups" => Some(Table::ReferrerRollups),
+            "campaign_rollups" => Some(Table::CampaignRollups),
+            "device_rollups" => Some(Table::DeviceRollups),
+            "event_rollups" => Some(Table::EventRollups),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Table::Metrics => "metrics",
+            Table::Sessions => "metric_sessions",
+            Table::Events => "metric_events",
+            Table::Rollups => "metric_rollups",
+            Table::TargetRollups => "metric_target_rollups",
+            Table::EntryRollups => "metric_entry_rollups",
+            Table::ReferrerRollups => "metric_referrer_rollups",
+            Table::CampaignRollups => "metric_campaign_rollups",
+            Table::DeviceRollups => "metric_device_rollups",
+            Table::EventRollups => "metric_event_rollups",
+        }
+    }
+
+    /// Exportable columns, in their export order
+    pub fn columns(&self) -> &'static [&'static str] {
+        match self {
+            Table::Metrics => &[
+                "id",
+                "date",
+                "end_date",
+                "session_id",
+                "ip",
+                "page_id",
+                "project_id",
+                "article_id",
+                "category_id",
+                "browser",
+                "os",
+                "device_type",
+                "referrer_domain",
+                "referrer_kind",
+                "utm_source",
+                "utm_medium",
+                "utm_campaign",
+                "utm_term",
+                "utm_content",
+                "is_bot",
+            ],
+            Table::Sessions => &[
+                "session_id",
+                "ip",
+                "start_date",
+                "expiration_date",
+                "utm_source",
+                "utm_medium",
+                "utm_campaign",
+                "utm_term",
+                "utm_content",
+            ],
+            Table::Events => &[
+                "id",
+                "date",
+                "metric_id",
+                "session_id",
+                "ip",
+                "name",
+                "properties",
+                "is_bot",
+            ],
+            Table::Rollups => &[
+                "granularity",
+                "period_start",
+                "is_bot",
+                "views",
+                "visitors",
+                "sessions",
+                "session_views",
+                "bounces",
+                "duration_sum",
+                "durations",
+            ],
+            Table::TargetRollups => &[
+                "granularity",
+                "period_start",
+                "is_bot",
+                "page_id",
+                "project_id",
+                "article_id",
+                "category_id",
+                "views",
+                "visitors",
+                "duration_sum",
+                "durations",
+            ],
+            Table::EntryRollups => &[
+                "period_start",
+                "is_bot",
+                "is_exit",
+                "page_id",
+                "project_id",
+                "article_id",
+                "category_id",
+                "sessions",
+            ],
+            Table::ReferrerRollups => &["period_start", "is_bot", "kind", "domain", "views"],
+            Table::CampaignRollups => &[
+                "period_start",
+                "is_bot",
+                "utm_source",
+                "utm_medium",
+                "utm_campaign",
+                "landings",
+                "views",
+                "visitors",
+                "sessions",
+                "project_views",
+            ],
+            Table::DeviceRollups => &[
+                "period_start",
+                "is_bot",
+                "browser",
+                "os",
+                "device_type",
+                "views",
+            ],
+            Table::EventRollups => &["period_start", "is_bot", "name", "events", "visitors"],
+        }
+    }
+
+    /// Column the period applies to
+    fn date_column(&self) -> &'static str {
+        match self {
+            Table::Metrics | Table::Events => "date",
+            Table::Sessions => "start_date",
+            _ => "period_start",
+        }
+    }
+
+    /// SQL expression of an exported column
+    fn expression(&self, column: &str) -> String {
+        match (self, column) {
+            // The id of a session is its `session_id` in the other exports
+            (Table::Sessions, "session_id") => r#""id""#.to_string(),
+            _ => format!(r#""{}""#, column),
+        }
+    }
+
+    /// Columns of `selection`, or every column, without the personal ones
+    /// when anonymizing. `None` for an unknown column or an empty selection
+    pub fn select(&self, selection: Option<&str>, anonymize: bool) -> Option<Vec<&'static str>> {
+        let columns = match selection {
+            Some(selection) => selection
+                .split(',')
+                .map(|name| {
+                    self.columns()
+                        .iter()
+                        .find(|column| **column == name.trim())
+                        .copied()
+                })
+                .collect::<Option<Vec<_>>>()?,
+            None => self.columns().to_vec(),
+        };
+        let columns = columns
+            .into_iter()
+            .filter(|column| !anonymize || !PERSONAL_COLUMNS.contains(column))
+            .collect::<Vec<_>>();
+
+        (!columns.is_empty()).then(|| columns)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Csv,
+    /// One JSON object per line
+    Ndjson,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::Csv
+    }
+}
+
+impl Format {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Ndjson => "ndjson",
+        }
+    }
+
+    /// First line of the export: the names of the columns of a CSV export
+    pub fn header(&self, columns: &[&str]) -> Option<String> {
+        match self {
+            Format::Csv => Some(format!("{}\n", columns.join(","))),
+            Format::Ndjson => None,
+        }
+    }
+}
+
+/// Query returning every row of the period as a single `TEXT` line
+fn query(table: Table, columns: &[&str], format: Format) -> String {
+    let select = columns
+        .iter()
+        .map(|column| format!(r#"{} AS "{}""#, table.expression(column), column))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let line = match format {
+        Format::Csv => columns
+            .iter()
+            .map(|column| {
+                format!(
+                    r#"COALESCE('"' || replace(t."{}"::TEXT, '"', '""') || '"', '')"#,
+                    column
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" || ',' || "),
+        Format::Ndjson => "row_to_json(t)::TEXT".to_string(),
+    };
+
+    format!(
+        r#"SELECT {line} AS line
+        FROM (
+            SELECT {select}
+            FROM {table}
+            WHERE "{date}" >= $1 AND "{date}" < $2
+            ORDER BY "{date}"
+        ) t"#,
+        line = line,
+        select = select,
+        table = table.name(),
+        date = table.date_column()
+    )
+}
+
+/// Lines of the rows of the period, each one ending with a line feed. The
+/// query runs in a dedicated thread and stops as soon as the receiver is
+/// dropped
+pub fn stream(
+    pool: PgPool,
+    table: Table,
+    columns: &[&str],
+    format: Format,
+    period: &Period,
+) -> mpsc::Receiver<Result<String, Error>> {
+    let (mut sender, receiver) = mpsc::channel(BUFFER_SIZE);
+    let query = query(table, columns, format);
+    let (start, end) = (period.start, period.end);
+
+    std::thread::spawn(move || {
+        block_on(async {
+            let mut rows = sqlx::query_scalar::<_, String>(&query)
+                .bind(start)
+                .bind(end)
+                .fetch(&pool);
+
+            while let Some(row) = rows.next().await {
+                let failed = row.is_err();
+
+                if sender.send(row.map(|line| line + "\n")).await.is_err() || failed {
+                    break;
+                }
+            }
+        })
+    });
+
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Format, Table};
+    use crate::create_pool;
+    use crate::services::metrics::stats::Period;
+    use chrono::{Duration, Utc};
+    use dotenv::dotenv;
+    use futures::StreamExt;
+
+    #[test]
+    fn test_select() {
+        assert_eq!(
+            Table::Metrics.select(Some("date, ip,page_id"), false),
+            Some(vec!["date", "ip", "page_id"])
+        );
+        assert_eq!(
+            Table::Metrics.select(Some("date,ip,page_id"), true),
+            Some(vec!["date", "page_id"])
+        );
+        assert_eq!(Table::Metrics.select(Some("date,password"), false), None);
+        assert_eq!(Table::Sessions.select(Some("ip,session_id"), true), None);
+        assert!(!Table::Events
+            .select(None, true)
+            .unwrap()
+            .contains(&"session_id"));
+    }
+
+    #[actix_rt::test]
+    async fn test_stream() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let (sid, _) = crate::services::metrics::sessions::add(&pool, "test")
+            .await
+            .unwrap();
+        let period = Period {
+            start: Utc::now() - Duration::days(1),
+            end: Utc::now() + Duration::days(1),
+            include_bots: true,
+        };
+        let lines = super::stream(
+            pool.clone(),
+            Table::Sessions,
+            &["session_id", "utm_source"],
+            Format::Ndjson,
+            &period,
+        )
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        let session = lines
+            .iter()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .find(|session| session["session_id"] == sid.to_hyphenated().to_string());
+
+        assert!(lines.iter().all(|line| line.ends_with('\n')));
+        assert_eq!(
+            session,
+            Some(serde_json::json!({
+                "session_id": sid.to_hyphenated().to_string(),
+                "utm_source": null
+            }))
+        );
+    }
+}
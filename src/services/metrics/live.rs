@@ -0,0 +1,288 @@
+//! Views in progress, for the real-time counter of the back office. Every new
+//! or closed view is broadcast to the subscribers, which keep their own state
+//! instead of querying the database on every update.
+
+use super::BelongsTo;
+use chrono::{DateTime, Duration, Utc};
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use serde::Serialize;
+use sqlx::{types::Uuid, Error, PgPool};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+/// Views not closed after this duration (in minutes) are considered as left,
+/// browsers do not always send the end of a view
+const ACTIVE_MINUTES: i64 = 10;
+
+static SUBSCRIBERS: Mutex<Vec<UnboundedSender<Activity>>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Activity {
+    View {
+        id: Uuid,
+        session_id: Option<Uuid>,
+        /// Hashed IP of the visitor
+        ip: String,
+        belongs_to: BelongsTo,
+        is_bot: bool,
+        date: DateTime<Utc>,
+    },
+    End {
+        id: Uuid,
+    },
+}
+
+/// Send an activity to every subscriber, the dropped ones are removed
+pub fn publish(activity: Activity) {
+    if let Ok(mut subscribers) = SUBSCRIBERS.lock() {
+        subscribers.retain(|subscriber| subscriber.unbounded_send(activity.clone()).is_ok());
+    }
+}
+
+/// Receive the activities published from now on
+pub fn subscribe() -> UnboundedReceiver<Activity> {
+    let (sender, receiver) = mpsc::unbounded();
+
+    if let Ok(mut subscribers) = SUBSCRIBERS.lock() {
+        subscribers.push(sender);
+    }
+
+    receiver
+}
+
+#[derive(Debug)]
+struct View {
+    /// Session, or hashed IP without consent
+    visitor: String,
+    session_id: Option<Uuid>,
+    belongs_to: BelongsTo,
+    date: DateTime<Utc>,
+}
+
+/// Content being viewed
+#[derive(Debug, Serialize)]
+pub struct Page {
+    /// `page`, `project`, `article` or `category`
+    pub kind: &'static str,
+    pub id: i16,
+    pub label: Option<String>,
+    pub views: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Snapshot {
+    pub visitors: usize,
+    pub sessions: usize,
+    /// The most viewed first
+    pub pages: Vec<Page>,
+}
+
+/// Views in progress according to the activities received
+#[derive(Default)]
+pub struct Live {
+    views: HashMap<Uuid, View>,
+    labels: HashMap<BelongsTo, Option<String>>,
+}
+
+impl Live {
+    /// Views in progress saved in the database: not closed, recent and not
+    /// belonging to an expired session. The subscription must be made before,
+    /// so that no activity is missed in between
+    pub async fn load(pool: &PgPool) -> Result<Live, Error> {
+        let rows = sqlx::query!(
+            r#"SELECT m.id, m.session_id, m.ip, m.page_id, m.project_id, m.article_id,
+                m.category_id, m."date"
+            FROM metrics m
+                LEFT JOIN metric_sessions s ON s.id = m.session_id
+            WHERE m.end_date IS NULL
+                AND m."date" > NOW() - make_interval(mins => $1)
+                AND NOT m.is_bot
+                AND (m.session_id IS NULL OR s.expiration_date > NOW())"#,
+            ACTIVE_MINUTES as i32
+        )
+        .fetch_all(pool)
+        .await?;
+        let mut live = Live::default();
+
+        for row in rows {
+            let belongs_to = match (row.page_id, row.project_id, row.article_id, row.category_id) {
+                (Some(id), _, _, _) => BelongsTo::Page(id),
+                (_, Some(id), _, _) => BelongsTo::Project(id),
+                (_, _, Some(id), _) => BelongsTo::BlogArticle(id),
+                (_, _, _, Some(id)) => BelongsTo::BlogCategory(id),
+                _ => continue,
+            };
+
+            live.apply(Activity::View {
+                id: row.id,
+                session_id: row.session_id,
+                ip: row.ip.unwrap_or_default(),
+                belongs_to,
+                is_bot: false,
+                date: row.date,
+            });
+        }
+
+        Ok(live)
+    }
+
+    pub fn apply(&mut self, activity: Activity) {
+        match activity {
+            Activity::View {
+                id,
+                session_id,
+                ip,
+                belongs_to,
+                is_bot: false,
+                date,
+            } => {
+                self.views.insert(
+                    id,
+                    View {
+                        visitor: session_id.map(|sid| sid.to_string()).unwrap_or(ip),
+                        session_id,
+                        belongs_to,
+                        date,
+                    },
+                );
+            }
+            Activity::View { is_bot: true, .. } => {}
+            Activity::End { id } => {
+                self.views.remove(&id);
+            }
+        }
+    }
+
+    /// Visitors and contents of the views in progress, the views too old are
+    /// forgotten
+    pub async fn snapshot(&mut self, pool: &PgPool) -> Result<Snapshot, Error> {
+        let limit = Utc::now() - Duration::minutes(ACTIVE_MINUTES);
+        self.views.retain(|_, view| view.date > limit);
+
+        let mut pages = HashMap::<BelongsTo, usize>::new();
+        for view in self.views.values() {
+            *pages.entry(view.belongs_to).or_default() += 1;
+        }
+
+        for belongs_to in pages.keys() {
+            if !self.labels.contains_key(belongs_to) {
+                let label = get_label(pool, *belongs_to).await?;
+                self.labels.insert(*belongs_to, label);
+            }
+        }
+
+        let mut pages = pages
+            .into_iter()
+            .map(|(belongs_to, views)| {
+                let (kind, id) = match belongs_to {
+                    BelongsTo::Page(id) => ("page", id),
+                    BelongsTo::Project(id) => ("project", id),
+                    BelongsTo::BlogArticle(id) => ("article", id),
+                    BelongsTo::BlogCategory(id) => ("category", id),
+                };
+
+                Page {
+                    kind,
+                    id,
+                    label: self.labels.get(&belongs_to).cloned().flatten(),
+                    views,
+                }
+            })
+            .collect::<Vec<_>>();
+        pages.sort_by(|a, b| b.views.cmp(&a.views).then_with(|| a.label.cmp(&b.label)));
+
+        Ok(Snapshot {
+            visitors: self
+                .views
+                .values()
+                .map(|view| &view.visitor)
+                .collect::<HashSet<_>>()
+                .len(),
+            sessions: self
+                .views
+                .values()
+                .filter_map(|view| view.session_id)
+                .collect::<HashSet<_>>()
+                .len(),
+            pages,
+        })
+    }
+}
+
+/// Title of a page or an article, name of a project or a category
+async fn get_label(pool: &PgPool, belongs_to: BelongsTo) -> Result<Option<String>, Error> {
+    let (query, id) = match belongs_to {
+        BelongsTo::Page(id) => ("SELECT title FROM pages WHERE id = $1", id),
+        BelongsTo::Project(id) => ("SELECT name FROM projects WHERE id = $1", id),
+        BelongsTo::BlogArticle(id) => ("SELECT title FROM blog_articles WHERE id = $1", id),
+        BelongsTo::BlogCategory(id) => ("SELECT name FROM blog_categories WHERE id = $1", id),
+    };
+
+    sqlx::query_scalar::<_, String>(query)
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Activity, Live};
+    use crate::create_pool;
+    use crate::services::metrics::{self, BelongsTo};
+    use crate::utils::traffic::{Referrer, Utm};
+    use dotenv::dotenv;
+
+    #[actix_rt::test]
+    async fn test_publish() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let mut receiver = super::subscribe();
+        let id = metrics::add(
+            &pool,
+            BelongsTo::Page(1),
+            None,
+            "test",
+            None,
+            None,
+            None,
+            &Referrer::direct(),
+            &Utm::default(),
+            false,
+        )
+        .await
+        .unwrap();
+        let mut live = Live::default();
+
+        // Activities of the tests running concurrently are received too
+        let mut received = Vec::new();
+        while let Ok(Some(activity)) = receiver.try_next() {
+            received.push(activity.clone());
+            live.apply(activity);
+        }
+
+        assert!(received.iter().any(
+            |activity| matches!(activity, Activity::View { id: view_id, .. } if *view_id == id)
+        ));
+
+        let snapshot = live.snapshot(&pool).await.unwrap();
+
+        assert!(snapshot.visitors >= 1);
+        assert!(snapshot
+            .pages
+            .iter()
+            .any(|page| page.kind == "page" && page.id == 1 && page.label.is_some()));
+
+        metrics::update_end_date(&pool, None, id).await.unwrap();
+
+        while let Ok(Some(activity)) = receiver.try_next() {
+            if activity == (Activity::End { id }) {
+                live.apply(activity);
+            }
+        }
+
+        assert!(!live.views.contains_key(&id));
+    }
+}
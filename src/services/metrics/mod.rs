@@ -1,11 +1,37 @@
+use crate::utils::traffic::{Referrer, Utm};
 use sqlx::{types::Uuid, Error, PgPool};
 
+pub mod events;
+pub mod export;
+pub mod live;
+pub mod rollups;
+pub mod salts;
 pub mod sessions;
+pub mod stats;
 
+/// Minimum number of views of a visitor, none of them closed by `/metrics/log`,
+/// to consider it as a bot
+const BOT_MIN_VIEWS: i64 = 3;
+
+/// Content a view is attributed to, each one has its own column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum BelongsTo {
     Page(i16),
     Project(i16),
-    BlogPost(i16),
+    BlogArticle(i16),
+    BlogCategory(i16),
+}
+
+impl BelongsTo {
+    /// Column of the `metrics` table and id of the content
+    fn column(&self) -> (&'static str, i16) {
+        match *self {
+            BelongsTo::Page(id) => ("page_id", id),
+            BelongsTo::Project(id) => ("project_id", id),
+            BelongsTo::BlogArticle(id) => ("article_id", id),
+            BelongsTo::BlogCategory(id) => ("category_id", id),
+        }
+    }
 }
 
 pub async fn exists(pool: &PgPool, id: Uuid) -> bool {
@@ -23,47 +49,55 @@ pub async fn add(
     browser: Option<String>,
     os: Option<String>,
     device_type: Option<String>,
-    referer: Option<String>,
+    referrer: &Referrer,
+    utm: &Utm,
+    is_bot: bool,
 ) -> Result<Uuid, Error> {
     use sqlx::Row;
 
-    let mut id: Option<i16> = None;
-
+    let (column, id) = belongs_to.column();
     let query = &format!(
-        "INSERT INTO metrics ({}, session_id, ip, browser, os, device_type, referer)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "INSERT INTO metrics ({}, session_id, ip, browser, os, device_type, referrer_domain,
+                referrer_kind, utm_source, utm_medium, utm_campaign, utm_term, utm_content, is_bot)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
             RETURNING id",
-        match belongs_to {
-            BelongsTo::Page(page_id) => {
-                id = Some(page_id);
-                "page_id"
-            }
-            BelongsTo::Project(project_id) => {
-                id = Some(project_id);
-                "project_id"
-            }
-            BelongsTo::BlogPost(post_id) => {
-                id = Some(post_id);
-                "post_id"
-            }
-        }
+        column
     );
 
     let res = sqlx::query(query)
-        .bind(id.unwrap())
+        .bind(id)
         .bind(session_id)
         .bind(ip)
         .bind(browser)
         .bind(os)
         .bind(device_type)
-        .bind(referer)
+        .bind(&referrer.domain)
+        .bind(referrer.kind.as_str())
+        .bind(&utm.source)
+        .bind(&utm.medium)
+        .bind(&utm.campaign)
+        .bind(&utm.term)
+        .bind(&utm.content)
+        .bind(is_bot)
         .fetch_one(pool)
         .await?;
     let id = res.try_get("id")?;
 
+    live::publish(live::Activity::View {
+        id,
+        session_id,
+        ip: ip.to_string(),
+        belongs_to,
+        is_bot,
+        date: chrono::Utc::now(),
+    });
+
     Ok(id)
 }
 
+/// Close a view. Its session is only replaced by a valid one, the visitor can
+/// have refused the cookie or its session can have expired since the view
+/// started
 pub async fn update_end_date(
     pool: &PgPool,
     session_id: Option<Uuid>,
@@ -72,7 +106,7 @@ pub async fn update_end_date(
     let res = sqlx::query!(
         "UPDATE metrics
             SET end_date = NOW(),
-                session_id = $1
+                session_id = COALESCE($1, session_id)
             WHERE id = $2",
         session_id,
         id
@@ -80,5 +114,240 @@ pub async fn update_end_date(
     .execute(pool)
     .await?;
 
+    if res.rows_affected() == 1 {
+        live::publish(live::Activity::End { id });
+    }
+
     Ok(res.rows_affected() == 1)
 }
+
+/// Flag as bots the visitors that never ran the tracking script: browsers close
+/// their views with `/metrics/log` and get a session, crawlers do not execute
+/// JavaScript. Views of the last 30 minutes are not judged yet. Returns the
+/// number of flagged views
+pub async fn flag_bots(pool: &PgPool) -> Result<u64, Error> {
+    let res = sqlx::query!(
+        r#"UPDATE metrics SET is_bot = true
+        WHERE NOT is_bot AND ip IN (
+            SELECT ip
+                FROM metrics
+                WHERE "date" < NOW() - INTERVAL '30 minutes'
+                GROUP BY ip
+                HAVING COUNT(*) >= $1 AND COUNT(end_date) = 0 AND COUNT(session_id) = 0
+        )"#,
+        BOT_MIN_VIEWS
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
+/// Delete the views and the events of the days (UTC) older than
+/// `retention_days` days along with the expired sessions, returns the number of deleted views. The
+/// rollups must have been refreshed before, whole days are deleted so the
+/// rollups of a day are either recomputed from all its views or kept as is
+pub async fn purge(pool: &PgPool, retention_days: i32) -> Result<u64, Error> {
+    let mut transaction = pool.begin().await?;
+    let res = sqlx::query!(
+        r#"DELETE FROM metrics
+            WHERE "date" < date_trunc('day', NOW(), 'UTC') - make_interval(days => $1)"#,
+        retention_days
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    sqlx::query!(
+        r#"DELETE FROM metric_events
+            WHERE "date" < date_trunc('day', NOW(), 'UTC') - make_interval(days => $1)"#,
+        retention_days
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM metric_sessions
+            WHERE expiration_date < date_trunc('day', NOW(), 'UTC') - make_interval(days => $1)",
+        retention_days
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(res.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BelongsTo;
+    use crate::create_pool;
+    use crate::utils::traffic::{Referrer, Utm};
+    use dotenv::dotenv;
+    use sqlx::types::Uuid;
+
+    /// `page_id`, `project_id`, `article_id` and `category_id` of a view
+    async fn get_target(
+        pool: &sqlx::PgPool,
+        id: Uuid,
+    ) -> (Option<i16>, Option<i16>, Option<i16>, Option<i16>) {
+        let row = sqlx::query!(
+            "SELECT page_id, project_id, article_id, category_id FROM metrics WHERE id = $1",
+            id
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+
+        (row.page_id, row.project_id, row.article_id, row.category_id)
+    }
+
+    #[actix_rt::test]
+    async fn test_add_every_target() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let targets = vec![
+            (BelongsTo::Page(1), (Some(1), None, None, None)),
+            (BelongsTo::Project(1), (None, Some(1), None, None)),
+            (BelongsTo::BlogArticle(3), (None, None, Some(3), None)),
+            (BelongsTo::BlogCategory(1), (None, None, None, Some(1))),
+        ];
+
+        for (belongs_to, expected) in targets {
+            let id = super::add(
+                &pool,
+                belongs_to,
+                None,
+                "test",
+                Some("Firefox".to_string()),
+                Some("Linux".to_string()),
+                Some("pc".to_string()),
+                &Referrer::direct(),
+                &Utm::default(),
+                false,
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(get_target(&pool, id).await, expected, "{:?}", belongs_to);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_purge() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let id = super::add(
+            &pool,
+            BelongsTo::Page(1),
+            None,
+            "test",
+            None,
+            None,
+            None,
+            &Referrer::direct(),
+            &Utm::default(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        sqlx::query!(
+            r#"UPDATE metrics SET "date" = '2000-01-01 12:00:00+00' WHERE id = $1"#,
+            id
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        super::rollups::refresh(&pool).await.unwrap();
+
+        assert!(super::purge(&pool, 30).await.unwrap() >= 1);
+        assert!(!super::exists(&pool, id).await);
+
+        // The rollups of the purged days are kept
+        let res = sqlx::query!(
+            r#"SELECT views FROM metric_target_rollups
+                WHERE granularity = 'day'
+                    AND period_start = '2000-01-01 00:00:00+00'
+                    AND page_id = 1"#
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        assert!(res.views >= 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_flag_bots() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let ip = format!("bot_{}", chrono::Utc::now().timestamp_nanos());
+        let mut ids = Vec::new();
+
+        for _ in 0..super::BOT_MIN_VIEWS {
+            ids.push(
+                super::add(
+                    &pool,
+                    BelongsTo::Page(1),
+                    None,
+                    &ip,
+                    None,
+                    None,
+                    None,
+                    &Referrer::direct(),
+                    &Utm::default(),
+                    false,
+                )
+                .await
+                .unwrap(),
+            );
+        }
+
+        sqlx::query!(
+            r#"UPDATE metrics SET "date" = NOW() - INTERVAL '1 hour' WHERE ip = $1"#,
+            ip
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert!(super::flag_bots(&pool).await.unwrap() >= ids.len() as u64);
+
+        let res = sqlx::query!(
+            r#"SELECT COUNT(*) AS "count!" FROM metrics WHERE ip = $1 AND is_bot"#,
+            ip
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(res.count, ids.len() as i64);
+    }
+
+    #[actix_rt::test]
+    async fn test_add_unknown_target() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+
+        assert!(super::add(
+            &pool,
+            BelongsTo::BlogCategory(i16::MAX),
+            None,
+            "test",
+            None,
+            None,
+            None,
+            &Referrer::direct(),
+            &Utm::default(),
+            false
+        )
+        .await
+        .is_err());
+    }
+}
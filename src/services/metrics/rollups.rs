@@ -0,0 +1,325 @@
+//! Hourly and daily rollups of the metrics. Every day still having raw
+//! metrics is recomputed from scratch, so refreshing is idempotent and can be
+//! run again after an outage: the rollups of the purged days are left as is.
+
+use chrono::{DateTime, Utc};
+use sqlx::{Error, PgPool};
+
+/// Rollup tables, emptied from the first day having raw metrics before being
+/// computed again
+const TABLES: [&str; 7] = [
+    "metric_rollups",
+    "metric_target_rollups",
+    "metric_entry_rollups",
+    "metric_referrer_rollups",
+    "metric_campaign_rollups",
+    "metric_device_rollups",
+    "metric_event_rollups",
+];
+
+/// Key of the advisory lock taken while refreshing, two concurrent refreshes
+/// would not see the rows inserted by each other and count them twice
+const LOCK_KEY: i64 = 0x6d65_7472_6963_73;
+
+/// Recompute the rollups of the days having raw metrics. Returns the start of
+/// the first recomputed day, `None` when there is no raw metric
+pub async fn refresh(pool: &PgPool) -> Result<Option<DateTime<Utc>>, Error> {
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!("SELECT pg_advisory_xact_lock($1)", LOCK_KEY)
+        .execute(&mut transaction)
+        .await?;
+
+    let since = sqlx::query!(
+        r#"SELECT date_trunc('day', LEAST(
+            (SELECT MIN("date") FROM metrics),
+            (SELECT MIN("date") FROM metric_events)
+        ), 'UTC') AS since"#
+    )
+    .fetch_one(&mut transaction)
+    .await?
+    .since;
+    let since = match since {
+        Some(since) => since,
+        None => return Ok(None),
+    };
+
+    for table in TABLES.iter() {
+        sqlx::query(&format!("DELETE FROM {} WHERE period_start >= $1", table))
+            .bind(since)
+            .execute(&mut transaction)
+            .await?;
+    }
+
+    sqlx::query!(
+        r#"WITH views AS (
+            SELECT
+                m.*,
+                CASE
+                    WHEN m.session_id IS NOT NULL
+                        AND COUNT(*) OVER (PARTITION BY m.session_id) = 1
+                    THEN 1
+                END AS bounce
+            FROM metrics m
+            WHERE m."date" >= $1
+        )
+        INSERT INTO metric_rollups
+            (granularity, period_start, is_bot, views, visitors, sessions, session_views, bounces,
+            duration_sum, durations)
+        SELECT
+            g.granularity,
+            date_trunc(g.granularity, v."date", 'UTC'),
+            v.is_bot,
+            COUNT(*),
+            COUNT(DISTINCT v.ip),
+            COUNT(DISTINCT v.session_id),
+            COUNT(v.session_id),
+            COUNT(v.bounce),
+            COALESCE(SUM(EXTRACT(EPOCH FROM v.end_date - v."date")), 0),
+            COUNT(v.end_date)
+        FROM views v
+            CROSS JOIN (VALUES ('hour'), ('day')) AS g (granularity)
+        GROUP BY 1, 2, 3"#,
+        since
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    sqlx::query!(
+        r#"INSERT INTO metric_target_rollups
+            (granularity, period_start, is_bot, page_id, project_id, article_id, category_id,
+            views, visitors, duration_sum, durations)
+        SELECT
+            g.granularity,
+            date_trunc(g.granularity, m."date", 'UTC'),
+            m.is_bot,
+            m.page_id,
+            m.project_id,
+            m.article_id,
+            m.category_id,
+            COUNT(*),
+            COUNT(DISTINCT m.ip),
+            COALESCE(SUM(EXTRACT(EPOCH FROM m.end_date - m."date")), 0),
+            COUNT(m.end_date)
+        FROM metrics m
+            CROSS JOIN (VALUES ('hour'), ('day')) AS g (granularity)
+        WHERE m."date" >= $1
+            AND num_nonnulls(m.page_id, m.project_id, m.article_id, m.category_id) = 1
+        GROUP BY 1, 2, 3, 4, 5, 6, 7"#,
+        since
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    // The first and the last views of the sessions, counted on the day they
+    // happened
+    sqlx::query!(
+        r#"WITH views AS (
+            SELECT
+                m.*,
+                ROW_NUMBER() OVER (PARTITION BY m.session_id ORDER BY m."date") AS rank,
+                ROW_NUMBER() OVER (PARTITION BY m.session_id ORDER BY m."date" DESC) AS reverse_rank
+            FROM metrics m
+            WHERE m."date" >= $1
+                AND m.session_id IS NOT NULL
+                AND num_nonnulls(m.page_id, m.project_id, m.article_id, m.category_id) = 1
+        )
+        INSERT INTO metric_entry_rollups
+            (period_start, is_bot, is_exit, page_id, project_id, article_id, category_id, sessions)
+        SELECT
+            date_trunc('day', v."date", 'UTC'),
+            v.is_bot,
+            e.is_exit,
+            v.page_id,
+            v.project_id,
+            v.article_id,
+            v.category_id,
+            COUNT(*)
+        FROM views v
+            CROSS JOIN (VALUES (false), (true)) AS e (is_exit)
+        WHERE (NOT e.is_exit AND v.rank = 1) OR (e.is_exit AND v.reverse_rank = 1)
+        GROUP BY 1, 2, 3, 4, 5, 6, 7"#,
+        since
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    sqlx::query!(
+        r#"INSERT INTO metric_referrer_rollups (period_start, is_bot, kind, "domain", views)
+        SELECT date_trunc('day', "date", 'UTC'), is_bot, referrer_kind, referrer_domain, COUNT(*)
+        FROM metrics
+        WHERE "date" >= $1
+        GROUP BY 1, 2, 3, 4"#,
+        since
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    // A view belongs to the campaign of its landing URL, else to the one of
+    // its session
+    sqlx::query!(
+        r#"INSERT INTO metric_campaign_rollups
+            (period_start, is_bot, utm_source, utm_medium, utm_campaign,
+            landings, views, visitors, sessions, project_views)
+        SELECT
+            date_trunc('day', m."date", 'UTC'),
+            m.is_bot,
+            COALESCE(m.utm_source, s.utm_source),
+            CASE WHEN m.utm_source IS NOT NULL THEN m.utm_medium ELSE s.utm_medium END,
+            CASE WHEN m.utm_source IS NOT NULL THEN m.utm_campaign ELSE s.utm_campaign END,
+            COUNT(m.utm_source),
+            COUNT(*),
+            COUNT(DISTINCT m.ip),
+            COUNT(DISTINCT m.session_id),
+            COUNT(m.project_id)
+        FROM metrics m
+            LEFT JOIN metric_sessions s ON s.id = m.session_id
+        WHERE m."date" >= $1
+            AND COALESCE(m.utm_source, s.utm_source) IS NOT NULL
+        GROUP BY 1, 2, 3, 4, 5"#,
+        since
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    sqlx::query!(
+        r#"INSERT INTO metric_device_rollups (period_start, is_bot, browser, os, device_type, views)
+        SELECT date_trunc('day', "date", 'UTC'), is_bot, browser, os, device_type, COUNT(*)
+        FROM metrics
+        WHERE "date" >= $1
+        GROUP BY 1, 2, 3, 4, 5"#,
+        since
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    sqlx::query!(
+        r#"INSERT INTO metric_event_rollups (period_start, is_bot, "name", events, visitors)
+        SELECT
+            date_trunc('day', "date", 'UTC'),
+            is_bot,
+            "name",
+            COUNT(*),
+            COUNT(DISTINCT COALESCE(session_id::TEXT, ip))
+        FROM metric_events
+        WHERE "date" >= $1
+        GROUP BY 1, 2, 3"#,
+        since
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(Some(since))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::create_pool;
+    use crate::services::metrics::{self, BelongsTo};
+    use crate::utils::traffic::{Referrer, Utm};
+    use dotenv::dotenv;
+
+    async fn get_today_views(pool: &sqlx::PgPool) -> (i64, i64) {
+        let res = sqlx::query!(
+            r#"SELECT
+                (SELECT COALESCE(SUM(views), 0) FROM metric_rollups
+                    WHERE granularity = 'day' AND period_start = date_trunc('day', NOW(), 'UTC')) AS "total!",
+                (SELECT COALESCE(SUM(views), 0) FROM metric_target_rollups
+                    WHERE granularity = 'day' AND period_start = date_trunc('day', NOW(), 'UTC')
+                        AND page_id = 1) AS "page!""#
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+
+        (res.total, res.page)
+    }
+
+    #[actix_rt::test]
+    async fn test_refresh() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+
+        metrics::add(
+            &pool,
+            BelongsTo::Page(1),
+            None,
+            "test",
+            None,
+            None,
+            None,
+            &Referrer::parse(
+                Some("https://www.instagram.com/ludivinefarat/"),
+                "ludivinefarat.fr",
+            ),
+            &Utm::from_query("utm_source=instagram&utm_campaign=rollups_test"),
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert!(super::refresh(&pool).await.unwrap().is_some());
+
+        let (total, page) = get_today_views(&pool).await;
+
+        assert!(total >= 1);
+        assert!(page >= 1);
+
+        let res = sqlx::query!(
+            r#"SELECT views FROM metric_referrer_rollups
+                WHERE period_start = date_trunc('day', NOW(), 'UTC')
+                    AND "domain" = 'instagram.com'
+                    AND kind = 'social'
+                    AND NOT is_bot"#
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        assert!(res.views >= 1);
+
+        let res = sqlx::query!(
+            r#"SELECT landings FROM metric_campaign_rollups
+                WHERE period_start = date_trunc('day', NOW(), 'UTC')
+                    AND utm_source = 'instagram'
+                    AND utm_campaign = 'rollups_test'
+                    AND NOT is_bot"#
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        assert!(res.landings >= 1);
+    }
+
+    #[actix_rt::test]
+    async fn test_refresh_is_idempotent() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let count_today = || async {
+            sqlx::query!(
+                r#"SELECT COUNT(*) AS "count!" FROM metrics
+                    WHERE "date" >= date_trunc('day', NOW(), 'UTC')"#
+            )
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+            .count
+        };
+        let before = count_today().await;
+
+        super::refresh(&pool).await.unwrap();
+        super::refresh(&pool).await.unwrap();
+
+        // Views added by the tests running concurrently can be counted too, but
+        // the views must not be counted twice
+        let (total, _) = get_today_views(&pool).await;
+
+        assert!(total >= before);
+        assert!(total <= count_today().await);
+    }
+}
@@ -0,0 +1,79 @@
+use chrono::{NaiveDate, Utc};
+use ring::rand::{SecureRandom, SystemRandom};
+use sqlx::{Error, PgPool};
+use std::sync::Mutex;
+
+/// Salt of the day kept in memory, the database being only read when the day
+/// changes
+static SALT: Mutex<Option<(NaiveDate, Vec<u8>)>> = Mutex::new(None);
+
+/// Salt of the day, created on the first call of the day. The salts of the
+/// previous days are deleted at the same time
+pub async fn get_today(pool: &PgPool) -> Result<Vec<u8>, Error> {
+    let today = Utc::today().naive_utc();
+
+    if let Some((day, salt)) = &*SALT.lock().unwrap_or_else(|e| e.into_inner()) {
+        if *day == today {
+            return Ok(salt.clone());
+        }
+    }
+
+    // The salt stored by another worker is kept when they race
+    let salt = create(pool, today).await?;
+
+    *SALT.lock().unwrap_or_else(|e| e.into_inner()) = Some((today, salt.clone()));
+
+    Ok(salt)
+}
+
+async fn create(pool: &PgPool, today: NaiveDate) -> Result<Vec<u8>, Error> {
+    let mut salt = [0; 32];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| Error::Protocol("Cannot generate a salt".to_string()))?;
+
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!(r#"DELETE FROM metric_salts WHERE "day" < $1"#, today)
+        .execute(&mut transaction)
+        .await?;
+    sqlx::query!(
+        r#"INSERT INTO metric_salts ("day", salt) VALUES ($1, $2) ON CONFLICT ("day") DO NOTHING"#,
+        today,
+        &salt[..]
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    let res = sqlx::query!(r#"SELECT salt FROM metric_salts WHERE "day" = $1"#, today)
+        .fetch_one(&mut transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(res.salt)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::create_pool;
+    use dotenv::dotenv;
+
+    #[actix_rt::test]
+    async fn test_get_today() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let salt = super::get_today(&pool).await.unwrap();
+
+        assert_eq!(salt.len(), 32);
+        assert_eq!(super::get_today(&pool).await.unwrap(), salt);
+        // The cached salt is the stored one
+        assert_eq!(
+            super::create(&pool, chrono::Utc::today().naive_utc())
+                .await
+                .unwrap(),
+            salt
+        );
+    }
+}
@@ -1,13 +1,91 @@
+use crate::utils::traffic::Utm;
 use chrono::{DateTime, Utc};
 use sqlx::{types::Uuid, PgPool};
 
+/// Minutes without activity after which a session expires
+pub const DURATION_MINUTES: i32 = 30;
+
 pub async fn add(pool: &PgPool, ip: &str) -> Result<(Uuid, DateTime<Utc>), sqlx::Error> {
     let res = sqlx::query!(
-        "INSERT INTO metric_sessions (ip) VALUES ($1) RETURNING id as sid, expiration_date as vud",
-        ip
+        "INSERT INTO metric_sessions (ip, expiration_date)
+            VALUES ($1, NOW() + make_interval(mins => $2))
+            RETURNING id as sid, expiration_date as vud",
+        ip,
+        DURATION_MINUTES
     )
     .fetch_one(pool)
     .await?;
 
     Ok((res.sid, res.vud))
 }
+
+/// Push back the expiration of a session on an activity of its visitor.
+/// Returns the new expiration date, `None` when the session is unknown or
+/// already expired
+pub async fn touch(pool: &PgPool, id: Uuid) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let res = sqlx::query!(
+        "UPDATE metric_sessions
+            SET expiration_date = NOW() + make_interval(mins => $2)
+            WHERE id = $1 AND expiration_date > NOW()
+            RETURNING expiration_date",
+        id,
+        DURATION_MINUTES
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(res.map(|session| session.expiration_date))
+}
+
+/// Attribute the session to the campaign of a landing URL, unless it already
+/// has one: the first campaign of a session is the one that brought it
+pub async fn set_campaign(pool: &PgPool, id: Uuid, utm: &Utm) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE metric_sessions
+            SET utm_source = $2,
+                utm_medium = $3,
+                utm_campaign = $4,
+                utm_term = $5,
+                utm_content = $6
+            WHERE id = $1 AND utm_source IS NULL",
+        id,
+        utm.source,
+        utm.medium,
+        utm.campaign,
+        utm.term,
+        utm.content
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::create_pool;
+    use dotenv::dotenv;
+    use sqlx::types::Uuid;
+
+    #[actix_rt::test]
+    async fn test_touch() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let (sid, vud) = super::add(&pool, "test").await.unwrap();
+        let expiration_date = super::touch(&pool, sid).await.unwrap();
+
+        assert!(matches!(expiration_date, Some(date) if date >= vud));
+
+        sqlx::query!(
+            "UPDATE metric_sessions SET expiration_date = NOW() - INTERVAL '1 minute' WHERE id = $1",
+            sid
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(super::touch(&pool, sid).await.unwrap(), None);
+        assert_eq!(super::touch(&pool, Uuid::nil()).await.unwrap(), None);
+    }
+}
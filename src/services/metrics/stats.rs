@@ -0,0 +1,436 @@
+//! Reports of the back office, computed from the rollups of the metrics.
+//! Periods start and end at midnight UTC, like the daily rollups.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Error, PgPool};
+
+/// Range of dates `[start, end[` of a report
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Period {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Count the views flagged as bots too
+    #[serde(skip)]
+    pub include_bots: bool,
+}
+
+impl Period {
+    /// Period of the same duration right before this one
+    pub fn previous(&self) -> Period {
+        Period {
+            start: self.start - (self.end - self.start),
+            end: self.start,
+            include_bots: self.include_bots,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Overview {
+    /// Sum of the distinct hashed IPs of each day. The salt changing every
+    /// day, a visitor coming back on several days is counted each day
+    pub daily_visitors: i64,
+    pub sessions: i64,
+    pub views: i64,
+    /// Average time on page (in seconds) of the views that have been closed
+    pub average_duration: Option<f64>,
+    /// Share of the sessions with a single view
+    pub bounce_rate: Option<f64>,
+    /// Average number of views of the sessions
+    pub pages_per_session: Option<f64>,
+}
+
+pub async fn get_overview(pool: &PgPool, period: &Period) -> Result<Overview, Error> {
+    sqlx::query_as!(
+        Overview,
+        r#"SELECT
+            COALESCE(SUM(visitors), 0) AS "daily_visitors!",
+            COALESCE(SUM(sessions), 0) AS "sessions!",
+            COALESCE(SUM(views), 0) AS "views!",
+            SUM(duration_sum) / NULLIF(SUM(durations), 0) AS average_duration,
+            SUM(bounces)::FLOAT8 / NULLIF(SUM(sessions), 0) AS bounce_rate,
+            SUM(session_views)::FLOAT8 / NULLIF(SUM(sessions), 0) AS pages_per_session
+        FROM metric_rollups
+        WHERE granularity = 'day'
+            AND period_start >= $1
+            AND period_start < $2
+            AND ($3 OR NOT is_bot)"#,
+        period.start,
+        period.end,
+        period.include_bots
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Views of a page, a project, an article or a category
+#[derive(Debug, Serialize)]
+pub struct Views {
+    /// `page`, `project`, `article` or `category`
+    pub kind: String,
+    pub id: i16,
+    pub label: String,
+    pub views: i64,
+    pub previous_views: i64,
+    /// Sum of the daily visitors, see `Overview::daily_visitors`
+    pub daily_visitors: i64,
+    pub average_duration: Option<f64>,
+}
+
+/// Most viewed pages, projects, articles and categories of the period, with their views
+/// during the previous period
+pub async fn get_views(pool: &PgPool, period: &Period, limit: i64) -> Result<Vec<Views>, Error> {
+    let previous = period.previous();
+
+    sqlx::query_as!(
+        Views,
+        r#"SELECT
+            CASE
+                WHEN r.page_id IS NOT NULL THEN 'page'
+                WHEN r.project_id IS NOT NULL THEN 'project'
+                WHEN r.article_id IS NOT NULL THEN 'article'
+                ELSE 'category'
+            END AS "kind!",
+            COALESCE(r.page_id, r.project_id, r.article_id, r.category_id) AS "id!",
+            COALESCE(p.title, pr.name, a.title, c.name) AS "label!",
+            COALESCE(SUM(r.views) FILTER (WHERE r.period_start >= $2), 0) AS "views!",
+            COALESCE(SUM(r.views) FILTER (WHERE r.period_start < $2), 0) AS "previous_views!",
+            COALESCE(SUM(r.visitors) FILTER (WHERE r.period_start >= $2), 0) AS "daily_visitors!",
+            SUM(r.duration_sum) FILTER (WHERE r.period_start >= $2)
+                / NULLIF(SUM(r.durations) FILTER (WHERE r.period_start >= $2), 0) AS average_duration
+        FROM metric_target_rollups r
+            LEFT JOIN pages p ON p.id = r.page_id
+            LEFT JOIN projects pr ON pr.id = r.project_id
+            LEFT JOIN blog_articles a ON a.id = r.article_id
+            LEFT JOIN blog_categories c ON c.id = r.category_id
+        WHERE r.granularity = 'day'
+            AND r.period_start >= $1
+            AND r.period_start < $3
+            AND ($5 OR NOT r.is_bot)
+        GROUP BY 1, 2, 3
+        HAVING SUM(r.views) FILTER (WHERE r.period_start >= $2) > 0
+        ORDER BY 4 DESC, 3
+        LIMIT $4"#,
+        previous.start,
+        period.start,
+        period.end,
+        limit,
+        period.include_bots
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// First or last view of the sessions
+pub enum Boundary {
+    Entry,
+    Exit,
+}
+
+/// Sessions having started or ended on a page, a project, an article or a
+/// category
+#[derive(Debug, Serialize)]
+pub struct Entry {
+    /// `page`, `project`, `article` or `category`
+    pub kind: String,
+    pub id: i16,
+    pub label: String,
+    pub sessions: i64,
+    pub previous_sessions: i64,
+}
+
+/// Contents the most sessions of the period started or ended on
+pub async fn get_entries(
+    pool: &PgPool,
+    period: &Period,
+    boundary: Boundary,
+    limit: i64,
+) -> Result<Vec<Entry>, Error> {
+    let previous = period.previous();
+
+    sqlx::query_as!(
+        Entry,
+        r#"SELECT
+            CASE
+                WHEN r.page_id IS NOT NULL THEN 'page'
+                WHEN r.project_id IS NOT NULL THEN 'project'
+                WHEN r.article_id IS NOT NULL THEN 'article'
+                ELSE 'category'
+            END AS "kind!",
+            COALESCE(r.page_id, r.project_id, r.article_id, r.category_id) AS "id!",
+            COALESCE(p.title, pr.name, a.title, c.name) AS "label!",
+            COALESCE(SUM(r.sessions) FILTER (WHERE r.period_start >= $2), 0) AS "sessions!",
+            COALESCE(SUM(r.sessions) FILTER (WHERE r.period_start < $2), 0) AS "previous_sessions!"
+        FROM metric_entry_rollups r
+            LEFT JOIN pages p ON p.id = r.page_id
+            LEFT JOIN projects pr ON pr.id = r.project_id
+            LEFT JOIN blog_articles a ON a.id = r.article_id
+            LEFT JOIN blog_categories c ON c.id = r.category_id
+        WHERE r.period_start >= $1
+            AND r.period_start < $3
+            AND ($5 OR NOT r.is_bot)
+            AND r.is_exit = $6
+        GROUP BY 1, 2, 3
+        HAVING SUM(r.sessions) FILTER (WHERE r.period_start >= $2) > 0
+        ORDER BY 4 DESC, 3
+        LIMIT $4"#,
+        previous.start,
+        period.start,
+        period.end,
+        limit,
+        period.include_bots,
+        matches!(boundary, Boundary::Exit)
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Number of views for a value of a dimension (referrer, browser...)
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Share {
+    pub label: Option<String>,
+    pub views: i64,
+    pub previous_views: i64,
+}
+
+/// Top referrer domains of the search engines, social networks and websites
+pub async fn get_referrers(
+    pool: &PgPool,
+    period: &Period,
+    limit: i64,
+) -> Result<Vec<Share>, Error> {
+    let previous = period.previous();
+
+    sqlx::query_as!(
+        Share,
+        r#"SELECT
+            "domain" AS label,
+            COALESCE(SUM(views) FILTER (WHERE period_start >= $2), 0) AS "views!",
+            COALESCE(SUM(views) FILTER (WHERE period_start < $2), 0) AS "previous_views!"
+        FROM metric_referrer_rollups
+        WHERE period_start >= $1
+            AND period_start < $3
+            AND ($5 OR NOT is_bot)
+            AND kind IN ('search', 'social', 'website')
+        GROUP BY 1
+        HAVING SUM(views) FILTER (WHERE period_start >= $2) > 0
+        ORDER BY 2 DESC
+        LIMIT $4"#,
+        previous.start,
+        period.start,
+        period.end,
+        limit,
+        period.include_bots
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Views by kind of referrer: `direct`, `internal`, `search`, `social` or
+/// `website`
+pub async fn get_sources(pool: &PgPool, period: &Period) -> Result<Vec<Share>, Error> {
+    let previous = period.previous();
+
+    sqlx::query_as!(
+        Share,
+        r#"SELECT
+            kind AS label,
+            COALESCE(SUM(views) FILTER (WHERE period_start >= $2), 0) AS "views!",
+            COALESCE(SUM(views) FILTER (WHERE period_start < $2), 0) AS "previous_views!"
+        FROM metric_referrer_rollups
+        WHERE period_start >= $1 AND period_start < $3 AND ($4 OR NOT is_bot)
+        GROUP BY 1
+        HAVING SUM(views) FILTER (WHERE period_start >= $2) > 0
+        ORDER BY 2 DESC"#,
+        previous.start,
+        period.start,
+        period.end,
+        period.include_bots
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Traffic brought by a UTM campaign
+#[derive(Debug, Serialize)]
+pub struct Campaign {
+    pub source: String,
+    pub medium: Option<String>,
+    pub campaign: Option<String>,
+    /// Views of the landing URLs with the UTM parameters
+    pub landings: i64,
+    pub views: i64,
+    /// Sum of the daily visitors, see `Overview::daily_visitors`
+    pub daily_visitors: i64,
+    pub sessions: i64,
+    /// Views of the projects of the portfolio
+    pub project_views: i64,
+    pub previous_views: i64,
+}
+
+/// Campaigns of the period, by UTM source, medium and campaign
+pub async fn get_campaigns(
+    pool: &PgPool,
+    period: &Period,
+    limit: i64,
+) -> Result<Vec<Campaign>, Error> {
+    let previous = period.previous();
+
+    sqlx::query_as!(
+        Campaign,
+        r#"SELECT
+            utm_source AS "source!",
+            utm_medium AS medium,
+            utm_campaign AS campaign,
+            COALESCE(SUM(landings) FILTER (WHERE period_start >= $2), 0) AS "landings!",
+            COALESCE(SUM(views) FILTER (WHERE period_start >= $2), 0) AS "views!",
+            COALESCE(SUM(visitors) FILTER (WHERE period_start >= $2), 0) AS "daily_visitors!",
+            COALESCE(SUM(sessions) FILTER (WHERE period_start >= $2), 0) AS "sessions!",
+            COALESCE(SUM(project_views) FILTER (WHERE period_start >= $2), 0) AS "project_views!",
+            COALESCE(SUM(views) FILTER (WHERE period_start < $2), 0) AS "previous_views!"
+        FROM metric_campaign_rollups
+        WHERE period_start >= $1
+            AND period_start < $3
+            AND ($5 OR NOT is_bot)
+            AND utm_source IS NOT NULL
+        GROUP BY 1, 2, 3
+        HAVING SUM(views) FILTER (WHERE period_start >= $2) > 0
+        ORDER BY 5 DESC
+        LIMIT $4"#,
+        previous.start,
+        period.start,
+        period.end,
+        limit,
+        period.include_bots
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Occurrences of a named event
+#[derive(Debug, Serialize)]
+pub struct EventCount {
+    pub name: String,
+    pub events: i64,
+    /// Sum of the daily visitors, see `Overview::daily_visitors`
+    pub daily_visitors: i64,
+    pub previous_events: i64,
+}
+
+/// Events of the period by name, the most frequent first
+pub async fn get_events(pool: &PgPool, period: &Period) -> Result<Vec<EventCount>, Error> {
+    let previous = period.previous();
+
+    sqlx::query_as!(
+        EventCount,
+        r#"SELECT
+            "name" AS "name!",
+            COALESCE(SUM(events) FILTER (WHERE period_start >= $2), 0) AS "events!",
+            COALESCE(SUM(visitors) FILTER (WHERE period_start >= $2), 0) AS "daily_visitors!",
+            COALESCE(SUM(events) FILTER (WHERE period_start < $2), 0) AS "previous_events!"
+        FROM metric_event_rollups
+        WHERE period_start >= $1 AND period_start < $3 AND ($4 OR NOT is_bot)
+        GROUP BY 1
+        HAVING SUM(events) FILTER (WHERE period_start >= $2) > 0
+        ORDER BY 2 DESC, 1"#,
+        previous.start,
+        period.start,
+        period.end,
+        period.include_bots
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub enum Dimension {
+    Browser,
+    Os,
+    DeviceType,
+}
+
+impl Dimension {
+    fn column(&self) -> &'static str {
+        match self {
+            Dimension::Browser => "browser",
+            Dimension::Os => "os",
+            Dimension::DeviceType => "device_type",
+        }
+    }
+}
+
+/// Views by browser, OS or device type
+pub async fn get_breakdown(
+    pool: &PgPool,
+    period: &Period,
+    dimension: Dimension,
+) -> Result<Vec<Share>, Error> {
+    let previous = period.previous();
+
+    sqlx::query_as::<_, Share>(&format!(
+        r#"SELECT
+            {0} AS label,
+            COALESCE(SUM(views) FILTER (WHERE period_start >= $2), 0) AS views,
+            COALESCE(SUM(views) FILTER (WHERE period_start < $2), 0) AS previous_views
+        FROM metric_device_rollups
+        WHERE period_start >= $1 AND period_start < $3 AND ($4 OR NOT is_bot)
+        GROUP BY {0}
+        HAVING SUM(views) FILTER (WHERE period_start >= $2) > 0
+        ORDER BY 2 DESC"#,
+        dimension.column()
+    ))
+    .bind(previous.start)
+    .bind(period.start)
+    .bind(period.end)
+    .bind(period.include_bots)
+    .fetch_all(pool)
+    .await
+}
+
+/// Size of the points of a timeline
+pub enum Granularity {
+    Hour,
+    Day,
+}
+
+/// Views and visitors of an hour or a day
+#[derive(Debug, Serialize)]
+pub struct Point {
+    pub date: DateTime<Utc>,
+    pub views: i64,
+    /// Distinct hashed IPs of the hour or the day, a point never spanning
+    /// several salts
+    pub visitors: i64,
+}
+
+/// Views and visitors of every hour or day of the period, the missing ones
+/// have no view
+pub async fn get_timeline(
+    pool: &PgPool,
+    period: &Period,
+    granularity: Granularity,
+) -> Result<Vec<Point>, Error> {
+    sqlx::query_as!(
+        Point,
+        r#"SELECT
+            period_start AS "date!",
+            SUM(views) AS "views!",
+            SUM(visitors) AS "visitors!"
+        FROM metric_rollups
+        WHERE granularity = $1
+            AND period_start >= $2
+            AND period_start < $3
+            AND ($4 OR NOT is_bot)
+        GROUP BY 1
+        ORDER BY 1"#,
+        match granularity {
+            Granularity::Hour => "hour",
+            Granularity::Day => "day",
+        },
+        period.start,
+        period.end,
+        period.include_bots
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -1,8 +1,13 @@
 pub mod attempts;
+pub mod auto_reply;
 pub mod blog;
+pub mod emails;
 pub mod files;
+pub mod messages;
 pub mod metrics;
 pub mod pages;
 pub mod projects;
 pub mod settings;
+pub mod spam_tokens;
+pub mod subscribers;
 pub mod user;
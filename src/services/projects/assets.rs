@@ -26,8 +26,9 @@ pub async fn get<
         &format!(
             "SELECT
                 {}
-            FROM projects_assets pa
-            JOIN files f ON f.id = pa.file_id
+            FROM project_assets pa
+            LEFT JOIN files f ON f.id = pa.file_id
+            LEFT JOIN files p ON p.id = pa.poster_id
             WHERE pa.id = $1",
             fields
         )
@@ -69,9 +70,18 @@ pub async fn get_all(pool: &PgPool, project_id: i16) -> Vec<super::Asset> {
     sqlx::query_as!(
         super::Asset,
         r#"SELECT
-            pa.id AS "id", f.path AS "path"
+            pa.id AS "id",
+            pa.kind AS "kind",
+            f.path AS "path?",
+            p.path AS "poster?",
+            pa.embed_provider,
+            pa.embed_id,
+            COALESCE(f.width, p.width) AS "width",
+            COALESCE(f.height, p.height) AS "height",
+            COALESCE(f.placeholder, p.placeholder) AS "placeholder"
         FROM project_assets pa
-        JOIN files f ON f.id = pa.file_id
+        LEFT JOIN files f ON f.id = pa.file_id
+        LEFT JOIN files p ON p.id = pa.poster_id
         WHERE project_id = $1
         ORDER BY pa.order"#,
         project_id
@@ -102,6 +112,52 @@ pub async fn insert(
     Ok(res.id)
 }
 
+pub async fn insert_video(
+    pool: impl sqlx::Executor<'_, Database = sqlx::Postgres>,
+    project_id: i16,
+    file_id: i32,
+    poster_id: i32,
+    order: i16,
+) -> Result<i16, Error> {
+    let res = sqlx::query!(
+        r#"INSERT INTO project_assets
+            (project_id, kind, file_id, poster_id, "order")
+        VALUES ($1, 'video', $2, $3, $4)
+        RETURNING id"#,
+        project_id,
+        file_id,
+        poster_id,
+        order
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(res.id)
+}
+
+pub async fn insert_embed(
+    pool: impl sqlx::Executor<'_, Database = sqlx::Postgres>,
+    project_id: i16,
+    provider: &str,
+    embed_id: &str,
+    order: i16,
+) -> Result<i16, Error> {
+    let res = sqlx::query!(
+        r#"INSERT INTO project_assets
+            (project_id, kind, embed_provider, embed_id, "order")
+        VALUES ($1, 'embed', $2, $3, $4)
+        RETURNING id"#,
+        project_id,
+        provider,
+        embed_id,
+        order
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(res.id)
+}
+
 pub async fn update(
     pool: impl sqlx::Executor<'_, Database = sqlx::Postgres>,
     id: i16,
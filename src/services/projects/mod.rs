@@ -21,10 +21,40 @@ pub struct Project {
     pub date: DateTime<Utc>,
 }
 
+/// An asset is either an image, an uploaded video or a video hosted by a
+/// provider (`kind` is respectively "image", "video" or "embed")
 #[derive(Debug, Clone)]
 pub struct Asset {
     pub id: i16,
-    pub path: String,
+    pub kind: String,
+    pub path: Option<String>,
+    pub poster: Option<String>,
+    pub embed_provider: Option<String>,
+    pub embed_id: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub placeholder: Option<String>,
+}
+
+impl Asset {
+    /// Path of the image representing the asset
+    pub fn preview(&self) -> Option<&str> {
+        match self.kind.as_str() {
+            "image" => self.path.as_deref(),
+            _ => self.poster.as_deref(),
+        }
+    }
+
+    pub fn mime_type(&self) -> Option<&'static str> {
+        crate::utils::video::VideoFormat::from_path(self.path.as_deref()?)
+            .map(|format| format.mime_type())
+    }
+
+    pub fn player_url(&self) -> Option<String> {
+        let provider = crate::utils::video::Provider::from_name(self.embed_provider.as_deref()?)?;
+
+        Some(provider.player_url(self.embed_id.as_deref()?))
+    }
 }
 
 pub async fn get_all(pool: &PgPool, category_id: Option<i16>) -> Vec<Project> {
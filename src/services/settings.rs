@@ -7,6 +7,10 @@ pub struct Settings {
     pub background_color: String,
     pub title_color: String,
     pub text_color: String,
+    /// `svg` or `png` when a raster favicon set has been generated
+    pub favicon_format: String,
+    /// Comments of the blog, the articles can also be closed one by one
+    pub comments_enabled: bool,
 }
 
 pub async fn get(pool: &PgPool) -> Result<Settings, Error> {
@@ -0,0 +1,51 @@
+use crate::utils::spam;
+use sqlx::{Error, PgPool};
+
+/// Record the nonce of the token a form has been sent with, the expired ones
+/// being deleted at the same time. `false` when the token is invalid or has
+/// already been used: it is replayed with its proof of work
+pub async fn consume(pool: &PgPool, token: &str) -> Result<bool, Error> {
+    let (nonce, expiration_date) = match spam::token_nonce(token) {
+        Some(nonce) => nonce,
+        None => return Ok(false),
+    };
+
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!("DELETE FROM spam_tokens WHERE expiration_date < NOW()")
+        .execute(&mut transaction)
+        .await?;
+
+    let res = sqlx::query!(
+        "INSERT INTO spam_tokens (nonce, expiration_date) VALUES ($1, $2)
+        ON CONFLICT (nonce) DO NOTHING",
+        nonce,
+        expiration_date
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{create_pool, utils::spam};
+    use dotenv::dotenv;
+
+    #[actix_rt::test]
+    async fn test_consume() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let token = spam::token();
+
+        assert!(super::consume(&pool, &token).await.unwrap());
+        // Replayed
+        assert!(!super::consume(&pool, &token).await.unwrap());
+        assert!(super::consume(&pool, &spam::token()).await.unwrap());
+        assert!(!super::consume(&pool, "forged.token").await.unwrap());
+    }
+}
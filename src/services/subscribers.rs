@@ -0,0 +1,147 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Error, PgPool};
+
+#[derive(Debug, Serialize)]
+pub struct Subscriber {
+    pub id: i32,
+    pub email: String,
+    /// `pending`, `confirmed` or `unsubscribed`
+    pub status: String,
+    pub confirmed_date: Option<DateTime<Utc>>,
+    pub unsubscribed_date: Option<DateTime<Utc>>,
+    pub date: DateTime<Utc>,
+}
+
+/// Subscribe an address, or subscribe it again. Returns the id of the
+/// subscriber to send the confirmation to, `None` for an address already
+/// confirmed or whose confirmation has been sent in the last 24 hours
+pub async fn subscribe(pool: &PgPool, email: &str) -> Result<Option<i32>, Error> {
+    let res = sqlx::query!(
+        r#"INSERT INTO subscribers (email) VALUES (LOWER($1))
+            ON CONFLICT (email) DO UPDATE
+            SET status = 'pending', "date" = NOW()
+            WHERE subscribers.status = 'unsubscribed'
+                OR (subscribers.status = 'pending' AND subscribers."date" < NOW() - interval '24 hours')
+            RETURNING id"#,
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(res.map(|res| res.id))
+}
+
+pub async fn get(pool: &PgPool, id: i32) -> Result<Subscriber, Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"SELECT id, email, status, confirmed_date, unsubscribed_date, "date"
+            FROM subscribers
+            WHERE id = $1"#,
+        id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Every subscriber, the most recent first
+pub async fn get_all(pool: &PgPool) -> Result<Vec<Subscriber>, Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"SELECT id, email, status, confirmed_date, unsubscribed_date, "date"
+            FROM subscribers
+            ORDER BY "date" DESC"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Subscribers to notify of the new articles
+pub async fn get_confirmed(pool: &PgPool) -> Result<Vec<Subscriber>, Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"SELECT id, email, status, confirmed_date, unsubscribed_date, "date"
+            FROM subscribers
+            WHERE status = 'confirmed'
+            ORDER BY id"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Confirm a pending subscription, a subscriber who unsubscribed since must
+/// subscribe again
+pub async fn confirm(pool: &PgPool, id: i32) -> Result<bool, Error> {
+    let res = sqlx::query!(
+        "UPDATE subscribers
+            SET status = 'confirmed', confirmed_date = NOW()
+            WHERE id = $1 AND status = 'pending'",
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+pub async fn unsubscribe(pool: &PgPool, id: i32) -> Result<bool, Error> {
+    let res = sqlx::query!(
+        "UPDATE subscribers
+            SET status = 'unsubscribed', unsubscribed_date = NOW()
+            WHERE id = $1 AND status <> 'unsubscribed'",
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+pub async fn delete(pool: &PgPool, id: i32) -> Result<bool, Error> {
+    let res = sqlx::query!("DELETE FROM subscribers WHERE id = $1", id)
+        .execute(pool)
+        .await?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::create_pool;
+    use dotenv::dotenv;
+
+    #[actix_rt::test]
+    async fn test_subscription() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let email = format!(
+            "Subscriber_{}@example.com",
+            chrono::Utc::now().timestamp_nanos()
+        );
+        let id = super::subscribe(&pool, &email).await.unwrap().unwrap();
+
+        // The confirmation is not sent twice
+        assert_eq!(super::subscribe(&pool, &email).await.unwrap(), None);
+        assert_eq!(
+            super::get(&pool, id).await.unwrap().email,
+            email.to_lowercase()
+        );
+
+        assert!(super::confirm(&pool, id).await.unwrap());
+        assert!(!super::confirm(&pool, id).await.unwrap());
+        assert!(super::get_confirmed(&pool)
+            .await
+            .unwrap()
+            .iter()
+            .any(|subscriber| subscriber.id == id));
+
+        assert!(super::unsubscribe(&pool, id).await.unwrap());
+        assert_eq!(super::get(&pool, id).await.unwrap().status, "unsubscribed");
+        // Subscribing again needs a new confirmation
+        assert_eq!(super::subscribe(&pool, &email).await.unwrap(), Some(id));
+        assert_eq!(super::get(&pool, id).await.unwrap().status, "pending");
+
+        assert!(super::delete(&pool, id).await.unwrap());
+    }
+}
@@ -0,0 +1,123 @@
+/// Tags kept in the content written by the users, the articles and the
+/// comments of the blog
+const ALLOWED_TAGS: &[&str] = &["b", "ul", "ol", "li", "a", "p", "br"];
+
+/// Tags kept in a line of text, e.g. a paragraph of an article
+const INLINE_TAGS: &[&str] = &["b", "i", "a", "br"];
+
+/// Tags kept in the articles written in Markdown
+const MARKDOWN_TAGS: &[&str] = &[
+    "h2",
+    "h3",
+    "h4",
+    "p",
+    "br",
+    "hr",
+    "strong",
+    "em",
+    "del",
+    "a",
+    "ul",
+    "ol",
+    "li",
+    "blockquote",
+    "pre",
+    "code",
+    "span",
+    "table",
+    "thead",
+    "tbody",
+    "tr",
+    "th",
+    "td",
+    "sup",
+    "div",
+];
+
+/// Content stripped of every tag but a few formatting ones, the links being
+/// made `noopener noreferrer`
+pub fn clean(content: &str) -> String {
+    ammonia::Builder::default()
+        .tags(ALLOWED_TAGS.iter().copied().collect())
+        .clean(content.trim())
+        .to_string()
+}
+
+/// Line of text stripped of every tag but the inline formatting ones
+pub fn clean_inline(content: &str) -> String {
+    ammonia::Builder::default()
+        .tags(INLINE_TAGS.iter().copied().collect())
+        .clean(content.trim())
+        .to_string()
+}
+
+/// Rendered Markdown stripped of every tag it does not produce, the classes
+/// of the highlighted code and the ids of the footnotes being kept
+pub fn clean_markdown(content: &str) -> String {
+    ammonia::Builder::default()
+        .tags(MARKDOWN_TAGS.iter().copied().collect())
+        .add_generic_attributes(&["class"])
+        .add_tag_attributes("div", &["id"])
+        .clean(content)
+        .to_string()
+}
+
+/// Text escaped to be inserted in HTML, as content or attribute value
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_clean() {
+        assert_eq!(
+            super::clean(" <p>Bonjour <b>à tous</b></p> "),
+            "<p>Bonjour <b>à tous</b></p>"
+        );
+        assert_eq!(
+            super::clean(r#"<script>alert(1)</script><img src="x" onerror="alert(1)">"#),
+            ""
+        );
+        assert_eq!(super::clean("1 < 2"), "1 &lt; 2");
+    }
+
+    #[test]
+    fn test_clean_inline() {
+        assert_eq!(
+            super::clean_inline("<p>Bonjour <b>à tous</b></p><ul><li>Lorem</li></ul>"),
+            "Bonjour <b>à tous</b>Lorem"
+        );
+    }
+
+    #[test]
+    fn test_clean_markdown() {
+        assert_eq!(
+            super::clean_markdown(
+                r#"<h2 onclick="alert(1)">Lorem</h2><pre class="highlight"><code><span class="hl-keyword">fn</span></code></pre><img src="x"><p id="comments">Ipsum</p>"#
+            ),
+            r#"<h2>Lorem</h2><pre class="highlight"><code><span class="hl-keyword">fn</span></code></pre><p>Ipsum</p>"#
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            super::escape(r#"<a href="x">L'été & co</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;L&#x27;été &amp; co&lt;/a&gt;"
+        );
+    }
+}
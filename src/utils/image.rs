@@ -1,43 +1,98 @@
-use image::{error::ImageError, DynamicImage, ImageFormat};
-use std::io::Write;
+use crate::utils::storage::Storage;
+use actix_web::{error::BlockingError, web};
+use image::{
+    codecs::ico::IcoEncoder, error::ImageError, imageops::FilterType, ColorType, DynamicImage,
+    GenericImageView, ImageFormat,
+};
+use std::{path::Path, sync::Arc};
 use webp::Encoder;
 
-pub fn remove_files(paths: &[String]) {
-    for path in paths {
-        let _ = std::fs::remove_file(path);
+/// Size (in pixels) of the largest side of the low-quality placeholder
+const PLACEHOLDER_SIZE: u32 = 16;
+
+/// Informations computed while processing an image, stored alongside the
+/// file so templates can reserve layout space and show a blurred preview
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    pub width: u32,
+    pub height: u32,
+    pub placeholder: String,
+}
+
+/// Sizes (in pixels) and names of the PNG favicons
+pub const FAVICONS: &[(u32, &str)] = &[
+    (32, "favicon-32x32.png"),
+    (180, "apple-touch-icon.png"),
+    (192, "android-chrome-192x192.png"),
+    (512, "android-chrome-512x512.png"),
+];
+
+fn delete_files(storage: &dyn Storage, keys: &[String]) {
+    for key in keys {
+        let _ = storage.delete(key);
     }
 }
 
+/// Delete the files in the thread pool, the requests of the S3 backend
+/// blocking the thread until it answers
+pub async fn remove_files(storage: Arc<dyn Storage>, keys: Vec<String>) {
+    if keys.is_empty() {
+        return;
+    }
+
+    let _ = web::block(move || -> Result<(), ()> {
+        delete_files(storage.as_ref(), &keys);
+
+        Ok(())
+    })
+    .await;
+}
+
+fn canceled() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, "blocking operation canceled")
+}
+
+/// Keys of every version generated by the `Uploader` for the given file
+pub fn variants(path: &str) -> Vec<String> {
+    let filename = path.split('.').next().unwrap_or(path);
+
+    vec![
+        format!("mobile/{}", path),
+        format!("mobile/{}.webp", filename),
+        path.to_string(),
+        format!("{}.webp", filename),
+    ]
+}
+
+/// Store the image resized to fit in `size`, returning its dimensions
 fn thumbnail(
+    storage: &dyn Storage,
     image: &DynamicImage,
     size: (u32, u32),
-    path: &str,
+    key: &str,
     format: ImageFormat,
-) -> Result<(), ImageError> {
-    image
-        .thumbnail(size.0, size.1)
-        .save_with_format(path, format)?;
+) -> Result<(u32, u32), ImageError> {
+    let mut data = Vec::new();
+    let resized = image.thumbnail(size.0, size.1);
+
+    resized.write_to(&mut data, format)?;
+    storage
+        .put(key, &data, crate::utils::storage::content_type(key))
+        .map_err(|e| ImageError::IoError(e.into()))?;
 
-    Ok(())
+    Ok(resized.dimensions())
 }
 
-fn webp_thumbnail(image: &DynamicImage, size: (u32, u32), path: &str) -> Result<(), ImageError> {
+fn webp_thumbnail(
+    storage: &dyn Storage,
+    image: &DynamicImage,
+    size: (u32, u32),
+    key: &str,
+) -> Result<(), ImageError> {
     match Encoder::from_image(&image.resize(size.0, size.1, image::imageops::CatmullRom)) {
-        Ok(encoder) => {
-            let image_webp = encoder.encode(80.0);
-            let v = image_webp.iter().copied().collect::<Vec<u8>>();
-
-            match std::fs::File::create(path) {
-                Ok(mut file) => {
-                    if let Err(e) = file.write_all(&v) {
-                        return Err(ImageError::IoError(e));
-                    }
-                }
-                Err(e) => return Err(ImageError::IoError(e)),
-            }
-
-            Ok(())
-        }
+        Ok(encoder) => storage
+            .put(key, &encoder.encode(80.0), "image/webp")
+            .map_err(|e| ImageError::IoError(e.into())),
         Err(e) => Err(ImageError::Encoding(image::error::EncodingError::new(
             image::error::ImageFormatHint::Name(e.to_string()),
             e,
@@ -45,104 +100,209 @@ fn webp_thumbnail(image: &DynamicImage, size: (u32, u32), path: &str) -> Result<
     }
 }
 
-pub struct Uploader {
-    files: Vec<String>,
+/// Generate a tiny WebP version of the image encoded as a base64 data URI
+pub fn placeholder(image: &DynamicImage) -> Result<String, ImageError> {
+    match Encoder::from_image(&image.thumbnail(PLACEHOLDER_SIZE, PLACEHOLDER_SIZE)) {
+        Ok(encoder) => Ok(format!(
+            "data:image/webp;base64,{}",
+            base64::encode(&*encoder.encode(20.0))
+        )),
+        Err(e) => Err(ImageError::Encoding(image::error::EncodingError::new(
+            image::error::ImageFormatHint::Name(e.to_string()),
+            e,
+        ))),
+    }
 }
 
-impl Uploader {
-    pub fn new() -> Uploader {
-        Uploader { files: Vec::new() }
+/// Generate the favicon set (ICO and PNGs) of a raster image in `directory`
+pub fn favicons(image: &DynamicImage, directory: &str) -> Result<(), ImageError> {
+    for (size, name) in FAVICONS {
+        image
+            .resize_to_fill(*size, *size, FilterType::Lanczos3)
+            .save_with_format(format!("{}/{}", directory, name), ImageFormat::Png)?;
     }
 
-    pub fn handle(
-        &mut self,
-        image: &DynamicImage,
-        name: &str,
-        max_mobile: Option<(u32, u32)>,
-        max_desktop: Option<(u32, u32)>,
-        with_webp: bool,
-    ) -> Result<(), ImageError> {
-        // let max_mobile = max_mobile.unwrap_or((500, 500));
-        // let max_desktop = max_desktop.unwrap_or((700, 700));
-        let mut paths: Vec<String> = vec![];
-        let mut new_name: String;
-        let has_alpha = image.color().has_alpha();
-
-        if let Some(max_mobile) = max_mobile {
-            new_name = format!(
-                "./uploads/mobile/{}.{}",
-                name,
-                if has_alpha { "png" } else { "jpg" }
-            );
-            if let Err(e) = thumbnail(
-                &image,
-                max_mobile,
-                &new_name,
-                if has_alpha {
-                    ImageFormat::Png
-                } else {
-                    ImageFormat::Jpeg
-                },
-            ) {
-                remove_files(&paths);
+    let icon = image
+        .resize_to_fill(32, 32, FilterType::Lanczos3)
+        .to_rgba8();
+    let file = std::fs::File::create(format!("{}/favicon.ico", directory))?;
+
+    IcoEncoder::new(file).encode(&icon, 32, 32, ColorType::Rgba8)
+}
+
+/// Resize and store the image, returning the keys of the stored files. The
+/// dimensions are the ones of the largest version, the one of the `<img>`
+fn store(
+    storage: &dyn Storage,
+    image: &DynamicImage,
+    name: &str,
+    max_mobile: Option<(u32, u32)>,
+    max_desktop: Option<(u32, u32)>,
+    with_webp: bool,
+) -> Result<(Metadata, Vec<String>), ImageError> {
+    // let max_mobile = max_mobile.unwrap_or((500, 500));
+    // let max_desktop = max_desktop.unwrap_or((700, 700));
+    let mut paths: Vec<String> = vec![];
+    let mut new_name: String;
+    let has_alpha = image.color().has_alpha();
+    let mut dimensions = image.dimensions();
+
+    if let Some(max_mobile) = max_mobile {
+        new_name = format!("mobile/{}.{}", name, if has_alpha { "png" } else { "jpg" });
+        match thumbnail(
+            storage,
+            &image,
+            max_mobile,
+            &new_name,
+            if has_alpha {
+                ImageFormat::Png
+            } else {
+                ImageFormat::Jpeg
+            },
+        ) {
+            Ok(size) => dimensions = size,
+            Err(e) => {
+                delete_files(storage, &paths);
+
+                return Err(e);
+            }
+        }
+        paths.push(new_name);
+
+        // Webp format
+        if with_webp {
+            new_name = format!("mobile/{}.webp", name);
+            if let Err(e) = webp_thumbnail(storage, &image, max_mobile, &new_name) {
+                delete_files(storage, &paths);
 
                 return Err(e);
             }
             paths.push(new_name);
+        }
+    }
 
-            // Webp format
-            if with_webp {
-                new_name = format!("./uploads/mobile/{}.webp", name);
-                if let Err(e) = webp_thumbnail(&image, max_mobile, &new_name) {
-                    remove_files(&paths);
+    if let Some(max_desktop) = max_desktop {
+        new_name = format!("{}.{}", name, if has_alpha { "png" } else { "jpg" });
+        match thumbnail(
+            storage,
+            &image,
+            max_desktop,
+            &new_name,
+            if has_alpha {
+                ImageFormat::Png
+            } else {
+                ImageFormat::Jpeg
+            },
+        ) {
+            Ok(size) => dimensions = size,
+            Err(e) => {
+                delete_files(storage, &paths);
 
-                    return Err(e);
-                }
-                paths.push(new_name);
+                return Err(e);
             }
         }
+        paths.push(new_name);
 
-        if let Some(max_desktop) = max_desktop {
-            new_name = format!(
-                "./uploads/{}.{}",
-                name,
-                if has_alpha { "png" } else { "jpg" }
-            );
-            if let Err(e) = thumbnail(
-                &image,
-                max_desktop,
-                &new_name,
-                if has_alpha {
-                    ImageFormat::Png
-                } else {
-                    ImageFormat::Jpeg
-                },
-            ) {
-                remove_files(&paths);
+        // Webp format
+        if with_webp {
+            new_name = format!("{}.webp", name);
+            if let Err(e) = webp_thumbnail(storage, &image, max_desktop, &new_name) {
+                delete_files(storage, &paths);
 
                 return Err(e);
             }
             paths.push(new_name);
+        }
+    }
 
-            // Webp format
-            if with_webp {
-                new_name = format!("./uploads/{}.webp", name);
-                if let Err(e) = webp_thumbnail(&image, max_desktop, &new_name) {
-                    remove_files(&paths);
+    let placeholder = match placeholder(image) {
+        Ok(placeholder) => placeholder,
+        Err(e) => {
+            delete_files(storage, &paths);
 
-                    return Err(e);
-                }
-                paths.push(new_name);
-            }
+            return Err(e);
         }
+    };
+    let (width, height) = dimensions;
+
+    Ok((
+        Metadata {
+            width,
+            height,
+            placeholder,
+        },
+        paths,
+    ))
+}
+
+pub struct Uploader {
+    storage: Arc<dyn Storage>,
+    files: Vec<String>,
+}
+
+impl Uploader {
+    pub fn new(storage: Arc<dyn Storage>) -> Uploader {
+        Uploader {
+            storage,
+            files: Vec::new(),
+        }
+    }
+
+    /// Store the resized versions of the image, the encoding and the requests
+    /// to the storage running in the thread pool
+    pub async fn handle(
+        &mut self,
+        image: &DynamicImage,
+        name: &str,
+        max_mobile: Option<(u32, u32)>,
+        max_desktop: Option<(u32, u32)>,
+        with_webp: bool,
+    ) -> Result<Metadata, ImageError> {
+        let storage = self.storage.clone();
+        let image = image.clone();
+        let name = name.to_string();
+        let (metadata, mut paths) = web::block(move || {
+            store(
+                storage.as_ref(),
+                &image,
+                &name,
+                max_mobile,
+                max_desktop,
+                with_webp,
+            )
+        })
+        .await
+        .map_err(|e| match e {
+            BlockingError::Error(e) => e,
+            BlockingError::Canceled => ImageError::IoError(canceled()),
+        })?;
 
         self.files.append(&mut paths);
 
-        Ok(())
+        Ok(metadata)
     }
 
-    pub fn attach(&mut self, path: &str) {
-        self.files.push(path.to_string());
+    /// Store a file as is, removed like the images if the upload is not
+    /// completed
+    pub async fn put_file(
+        &mut self,
+        key: &str,
+        path: &Path,
+        content_type: &'static str,
+    ) -> std::io::Result<()> {
+        let storage = self.storage.clone();
+        let (key, path) = (key.to_string(), path.to_path_buf());
+        let stored_key = key.clone();
+
+        web::block(move || storage.put_file(&stored_key, &path, content_type))
+            .await
+            .map_err(|e| match e {
+                BlockingError::Error(e) => e.into(),
+                BlockingError::Canceled => canceled(),
+            })?;
+        self.files.push(key);
+
+        Ok(())
     }
 
     pub fn clear(&mut self) {
@@ -152,8 +312,44 @@ impl Uploader {
 
 impl Drop for Uploader {
     fn drop(&mut self) {
-        for path in &self.files {
-            let _ = std::fs::remove_file(path);
+        if !self.files.is_empty() {
+            actix_web::rt::spawn(remove_files(
+                self.storage.clone(),
+                std::mem::take(&mut self.files),
+            ));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::storage::{LocalStorage, Storage};
+    use image::{DynamicImage, GenericImageView};
+
+    #[test]
+    fn test_store() {
+        let directory = tempfile::tempdir().unwrap();
+        let storage = LocalStorage::new(directory.path());
+        let image = DynamicImage::new_rgb8(4000, 3000);
+        let (metadata, paths) = super::store(
+            &storage,
+            &image,
+            "a",
+            Some((500, 250)),
+            Some((700, 350)),
+            true,
+        )
+        .unwrap();
+
+        // The dimensions are the ones of the desktop version
+        assert_eq!(metadata.height, 350);
+        assert!(metadata.width <= 700);
+        assert_eq!(paths, super::variants("a.jpg"));
+        assert_eq!(
+            image::load_from_memory(&storage.get("a.jpg").unwrap())
+                .unwrap()
+                .dimensions(),
+            (metadata.width, metadata.height)
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use super::{Email, MailError, Transport};
+use lettre::Transport as _;
+use std::path::PathBuf;
+
+/// Emails written to a directory, one JSON file per email
+pub struct FileTransport {
+    directory: PathBuf,
+}
+
+impl FileTransport {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        FileTransport {
+            directory: directory.into(),
+        }
+    }
+}
+
+impl Transport for FileTransport {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        let email = email.build()?;
+
+        std::fs::create_dir_all(&self.directory).map_err(|e| MailError::Send(e.to_string()))?;
+
+        lettre::FileTransport::new(&self.directory)
+            .send(email)
+            .map_err(|e| MailError::Send(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileTransport;
+    use crate::utils::mail::{Email, Transport};
+
+    #[test]
+    fn test_send() {
+        let directory = tempfile::tempdir().unwrap();
+        let transport = FileTransport::new(directory.path().join("mails"));
+
+        transport
+            .send(&Email {
+                to: "jean.dupont@example.com".to_string(),
+                from: "hello@ludivinefarat.fr".to_string(),
+                reply_to: None,
+                subject: "Test".to_string(),
+                text: Some("Lorem ipsum".to_string()),
+                html: None,
+            })
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_dir(directory.path().join("mails"))
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+}
@@ -0,0 +1,32 @@
+use super::{Email, MailError, Transport};
+use std::sync::Mutex;
+
+/// Emails kept in memory, to be read back by the tests
+#[derive(Default)]
+pub struct MemoryTransport {
+    sent: Mutex<Vec<Email>>,
+}
+
+impl MemoryTransport {
+    /// Emails sent so far, the oldest first
+    pub fn sent(&self) -> Vec<Email> {
+        self.sent
+            .lock()
+            .map(|sent| sent.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        // The email must be valid to be sent by the other transports
+        email.build()?;
+
+        self.sent
+            .lock()
+            .map_err(|e| MailError::Send(e.to_string()))?
+            .push(email.clone());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+//! Transports the emails of the outbox are sent with. The SMTP transport is
+//! used in production, the file and memory ones let the tests and the
+//! development server send emails without a mail server.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::sync::Arc;
+
+mod file;
+mod memory;
+mod smtp;
+pub mod templates;
+
+pub use file::FileTransport;
+pub use memory::MemoryTransport;
+pub use smtp::SmtpTransport;
+pub use templates::EmailTemplate;
+
+/// Address used when `MAIL_FROM` is not set
+const DEFAULT_ADDRESS: &str = "hello@ludivinefarat.fr";
+
+/// Origin used when `SITE_URL` is not set
+const DEFAULT_SITE_URL: &str = "https://ludivinefarat.fr";
+
+static EMAIL: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^(([^<>()\[\]\\.,;:\s@"]+(\.[^<>()\[\]\\.,;:\s@"]+)*)|(".+"))@((\[[0-9]{1,3}\.[0-9]{1,3}\.[0-9]{1,3}\.[0-9]{1,3}])|(([a-zA-Z\-0-9]+\.)+[a-zA-Z]{2,}))$"#).unwrap()
+});
+
+#[derive(Debug)]
+pub enum MailError {
+    /// The email could not be built, e.g. an invalid address
+    Build(String),
+    Send(String),
+    Config(String),
+}
+
+impl std::fmt::Display for MailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MailError::Build(e) => write!(f, "Invalid email: {}", e),
+            MailError::Send(e) => write!(f, "Email sending failed: {}", e),
+            MailError::Config(e) => write!(f, "Invalid mail configuration: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Email {
+    pub to: String,
+    pub from: String,
+    pub reply_to: Option<String>,
+    pub subject: String,
+    /// Plain text body, sent as an alternative to the HTML one when both are
+    /// given
+    pub text: Option<String>,
+    pub html: Option<String>,
+}
+
+impl Email {
+    /// Message sendable by the `lettre` transports
+    pub fn build(&self) -> Result<lettre::SendableEmail, MailError> {
+        let mut builder = lettre_email::EmailBuilder::new()
+            .to(self.to.as_str())
+            .from(self.from.as_str())
+            .subject(self.subject.as_str());
+
+        if let Some(reply_to) = &self.reply_to {
+            builder = builder.reply_to(reply_to.as_str());
+        }
+
+        builder = match (&self.text, &self.html) {
+            (Some(text), Some(html)) => builder.alternative(html.as_str(), text.as_str()),
+            (None, Some(html)) => builder.html(html.as_str()),
+            (Some(text), None) => builder.text(text.as_str()),
+            (None, None) => builder.text(""),
+        };
+
+        builder
+            .build()
+            .map(Into::into)
+            .map_err(|e| MailError::Build(e.to_string()))
+    }
+}
+
+/// Addresses the emails of the site are sent from and to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Addresses {
+    /// Sender of every email, an address of the domain of the site so that
+    /// the emails pass the SPF and DMARC checks
+    pub from: String,
+    pub reply_to: Option<String>,
+    /// Recipients of the notifications, e.g. of the contact messages
+    pub admins: Vec<String>,
+    /// Origin of the links of the emails, without trailing slash
+    pub site_url: String,
+}
+
+impl Addresses {
+    /// Addresses of the `MAIL_FROM`, `MAIL_REPLY_TO`, `MAIL_ADMINS` and
+    /// `SITE_URL` variables, the admins separated by commas
+    pub fn from_env() -> Self {
+        let from = std::env::var("MAIL_FROM").unwrap_or_else(|_| DEFAULT_ADDRESS.to_string());
+        let admins = std::env::var("MAIL_ADMINS")
+            .map(|admins| parse_list(&admins))
+            .unwrap_or_default();
+
+        Addresses {
+            admins: if admins.is_empty() {
+                vec![from.clone()]
+            } else {
+                admins
+            },
+            reply_to: std::env::var("MAIL_REPLY_TO")
+                .ok()
+                .filter(|reply_to| !reply_to.trim().is_empty()),
+            from,
+            site_url: std::env::var("SITE_URL")
+                .map(|url| url.trim().trim_end_matches('/').to_string())
+                .ok()
+                .filter(|url| !url.is_empty())
+                .unwrap_or_else(|| DEFAULT_SITE_URL.to_string()),
+        }
+    }
+
+    /// Absolute URL of a path of the site, for the links of the emails
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.site_url, path)
+    }
+
+    /// Email sent by the site, without body
+    pub fn email(&self, to: &str, subject: &str) -> Email {
+        Email {
+            to: to.to_string(),
+            from: self.from.clone(),
+            reply_to: self.reply_to.clone(),
+            subject: subject.to_string(),
+            text: None,
+            html: None,
+        }
+    }
+
+    /// Email sent by the site, rendered from a template
+    pub fn render(&self, to: &str, template: &dyn EmailTemplate) -> askama::Result<Email> {
+        // Line breaks in the subject would end the header
+        let subject = template.subject().replace(char::is_control, " ");
+
+        Ok(Email {
+            text: Some(template.text()?),
+            html: Some(template.html()?),
+            ..self.email(to, &subject)
+        })
+    }
+}
+
+/// Non empty addresses of a list separated by commas
+fn parse_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Check the address a visitor typed in a form
+pub fn is_valid_email(email: &str) -> bool {
+    EMAIL.is_match(email)
+}
+
+pub trait Transport: Send + Sync {
+    fn send(&self, email: &Email) -> Result<(), MailError>;
+}
+
+/// Create a transport by name: `smtp`, `file` or `memory`
+pub fn from_name(name: &str) -> Result<Arc<dyn Transport>, MailError> {
+    match name {
+        "smtp" => Ok(Arc::new(SmtpTransport::from_env()?)),
+        "file" => Ok(Arc::new(FileTransport::new(
+            std::env::var("MAIL_DIR").unwrap_or_else(|_| "./mails".to_string()),
+        ))),
+        "memory" => Ok(Arc::new(MemoryTransport::default())),
+        _ => Err(MailError::Config(format!("unknown transport {}", name))),
+    }
+}
+
+/// Create the transport selected by the `MAIL_TRANSPORT` variable (`smtp` by
+/// default)
+pub fn from_env() -> Result<Arc<dyn Transport>, MailError> {
+    from_name(&std::env::var("MAIL_TRANSPORT").unwrap_or_else(|_| "smtp".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Addresses;
+
+    #[test]
+    fn test_parse_list() {
+        assert_eq!(
+            super::parse_list(" hello@ludivinefarat.fr, ,contact@ludivinefarat.fr"),
+            vec!["hello@ludivinefarat.fr", "contact@ludivinefarat.fr"]
+        );
+        assert!(super::parse_list("").is_empty());
+    }
+
+    #[test]
+    fn test_is_valid_email() {
+        assert!(super::is_valid_email("jean.dupont@example.com"));
+        assert!(!super::is_valid_email("jean.dupont@example"));
+        assert!(!super::is_valid_email("jean dupont@example.com"));
+    }
+
+    #[test]
+    fn test_email() {
+        let addresses = Addresses {
+            from: "hello@ludivinefarat.fr".to_string(),
+            reply_to: Some("contact@ludivinefarat.fr".to_string()),
+            admins: vec![],
+            site_url: "https://ludivinefarat.fr".to_string(),
+        };
+        let email = addresses.email("jean.dupont@example.com", "Test");
+
+        assert_eq!(email.from, "hello@ludivinefarat.fr");
+        assert_eq!(email.reply_to.as_deref(), Some("contact@ludivinefarat.fr"));
+        assert!(email.build().is_ok());
+        assert_eq!(addresses.url("/blog"), "https://ludivinefarat.fr/blog");
+    }
+}
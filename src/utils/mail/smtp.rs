@@ -0,0 +1,140 @@
+use super::{Email, MailError, Transport};
+use lettre::{
+    smtp::authentication::Credentials, ClientSecurity, ClientTlsParameters, SmtpClient,
+    Transport as _,
+};
+use native_tls::TlsConnector;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Security {
+    /// Plain text connection, only for a server on the same host
+    None,
+    /// Plain text connection upgraded with `STARTTLS`, required
+    StartTls,
+    /// Implicit TLS connection
+    Tls,
+}
+
+impl Security {
+    /// Security from its name: `none`, `starttls` or `tls`
+    pub fn from_name(name: &str) -> Option<Security> {
+        match name {
+            "none" => Some(Security::None),
+            "starttls" => Some(Security::StartTls),
+            "tls" => Some(Security::Tls),
+            _ => None,
+        }
+    }
+
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Security::None => lettre::smtp::SMTP_PORT,
+            Security::StartTls => lettre::smtp::SUBMISSION_PORT,
+            Security::Tls => lettre::smtp::SUBMISSIONS_PORT,
+        }
+    }
+}
+
+/// Emails sent to a SMTP server, the one of the host by default
+pub struct SmtpTransport {
+    host: String,
+    port: u16,
+    security: Security,
+    credentials: Option<(String, String)>,
+}
+
+impl SmtpTransport {
+    /// Server configured by the `SMTP_HOST` (`localhost` by default),
+    /// `SMTP_SECURITY` (`none` for `localhost`, `starttls` otherwise),
+    /// `SMTP_PORT` (the default port of the security) and `SMTP_USERNAME` and
+    /// `SMTP_PASSWORD` variables
+    pub fn from_env() -> Result<Self, MailError> {
+        let host = std::env::var("SMTP_HOST").unwrap_or_else(|_| "localhost".to_string());
+        let security = match std::env::var("SMTP_SECURITY") {
+            Ok(name) => Security::from_name(&name)
+                .ok_or_else(|| MailError::Config(format!("unknown SMTP security {}", name)))?,
+            Err(_) if host == "localhost" => Security::None,
+            Err(_) => Security::StartTls,
+        };
+        let port = match std::env::var("SMTP_PORT") {
+            Ok(port) => port
+                .parse()
+                .map_err(|_| MailError::Config(format!("invalid SMTP port {}", port)))?,
+            Err(_) => security.default_port(),
+        };
+        let credentials = match (
+            std::env::var("SMTP_USERNAME"),
+            std::env::var("SMTP_PASSWORD"),
+        ) {
+            (Ok(username), Ok(password)) => Some((username, password)),
+            (Err(_), Err(_)) => None,
+            _ => {
+                return Err(MailError::Config(
+                    "SMTP_USERNAME and SMTP_PASSWORD must be set together".to_string(),
+                ))
+            }
+        };
+
+        if credentials.is_some() && security == Security::None && host != "localhost" {
+            return Err(MailError::Config(
+                "SMTP credentials can not be sent without TLS".to_string(),
+            ));
+        }
+
+        Ok(SmtpTransport {
+            host,
+            port,
+            security,
+            credentials,
+        })
+    }
+
+    fn client(&self) -> Result<SmtpClient, MailError> {
+        let tls_parameters = || -> Result<ClientTlsParameters, MailError> {
+            let connector = TlsConnector::new().map_err(|e| MailError::Config(e.to_string()))?;
+
+            Ok(ClientTlsParameters::new(self.host.clone(), connector))
+        };
+        let security = match self.security {
+            Security::None => ClientSecurity::None,
+            Security::StartTls => ClientSecurity::Required(tls_parameters()?),
+            Security::Tls => ClientSecurity::Wrapper(tls_parameters()?),
+        };
+        // The host is resolved here, a failure may only be temporary
+        let client = SmtpClient::new((self.host.as_str(), self.port), security)
+            .map_err(|e| MailError::Send(e.to_string()))?;
+
+        Ok(match &self.credentials {
+            Some((username, password)) => {
+                client.credentials(Credentials::new(username.clone(), password.clone()))
+            }
+            None => client,
+        })
+    }
+}
+
+impl Transport for SmtpTransport {
+    fn send(&self, email: &Email) -> Result<(), MailError> {
+        let email = email.build()?;
+
+        self.client()?
+            .transport()
+            .send(email)
+            .map(|_| ())
+            .map_err(|e| MailError::Send(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Security;
+
+    #[test]
+    fn test_security() {
+        assert_eq!(Security::from_name("starttls"), Some(Security::StartTls));
+        assert_eq!(Security::from_name("ssl"), None);
+        assert_eq!(Security::None.default_port(), 25);
+        assert_eq!(Security::StartTls.default_port(), 587);
+        assert_eq!(Security::Tls.default_port(), 465);
+    }
+}
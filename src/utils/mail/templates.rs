@@ -0,0 +1,285 @@
+//! Emails rendered from the `emails/{name}.html` templates, with the
+//! `emails/{name}.txt` ones as plain text alternative. Only the HTML templates
+//! escape the variables, the text templates are the same data as is.
+
+use askama::Template;
+use std::ops::Deref;
+
+/// Subject and bodies of an email
+pub trait EmailTemplate {
+    fn subject(&self) -> String;
+    fn html(&self) -> askama::Result<String>;
+    fn text(&self) -> askama::Result<String>;
+}
+
+/// Message sent with the contact form, to the admins
+#[derive(Template)]
+#[template(path = "emails/contact.html")]
+pub struct Contact<'a> {
+    pub firstname: &'a str,
+    pub lastname: &'a str,
+    pub phone_number: Option<&'a str>,
+    pub email: &'a str,
+    pub content: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/contact.txt")]
+struct ContactText<'a>(&'a Contact<'a>);
+
+impl<'a> Deref for ContactText<'a> {
+    type Target = Contact<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl EmailTemplate for Contact<'_> {
+    fn subject(&self) -> String {
+        format!("Nouveau message de {} {}", self.firstname, self.lastname)
+    }
+
+    fn html(&self) -> askama::Result<String> {
+        self.render()
+    }
+
+    fn text(&self) -> askama::Result<String> {
+        ContactText(self).render()
+    }
+}
+
+/// Acknowledgment of a message, to the visitor who sent it, with a copy of the
+/// message. The subject and the body are edited from the back office
+#[derive(Template)]
+#[template(path = "emails/auto_reply.html")]
+pub struct AutoReply<'a> {
+    pub subject: &'a str,
+    pub body: &'a str,
+    pub firstname: &'a str,
+    pub content: &'a str,
+}
+
+impl AutoReply<'_> {
+    /// Text of the back office, `{prénom}` replaced by the first name
+    fn fill(&self, text: &str) -> String {
+        text.replace("{prénom}", self.firstname)
+    }
+
+    fn filled_body(&self) -> String {
+        self.fill(self.body)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "emails/auto_reply.txt")]
+struct AutoReplyText<'a>(&'a AutoReply<'a>);
+
+impl<'a> Deref for AutoReplyText<'a> {
+    type Target = AutoReply<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl EmailTemplate for AutoReply<'_> {
+    fn subject(&self) -> String {
+        self.fill(self.subject)
+    }
+
+    fn html(&self) -> askama::Result<String> {
+        self.render()
+    }
+
+    fn text(&self) -> askama::Result<String> {
+        AutoReplyText(self).render()
+    }
+}
+
+/// Link to choose a new password
+#[derive(Template)]
+#[template(path = "emails/password_recovery.html")]
+pub struct PasswordRecovery<'a> {
+    pub url: &'a str,
+    pub validity_minutes: i64,
+}
+
+#[derive(Template)]
+#[template(path = "emails/password_recovery.txt")]
+struct PasswordRecoveryText<'a>(&'a PasswordRecovery<'a>);
+
+impl<'a> Deref for PasswordRecoveryText<'a> {
+    type Target = PasswordRecovery<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl EmailTemplate for PasswordRecovery<'_> {
+    fn subject(&self) -> String {
+        "Mot de passe oublié - Ludivine Farat".to_string()
+    }
+
+    fn html(&self) -> askama::Result<String> {
+        self.render()
+    }
+
+    fn text(&self) -> askama::Result<String> {
+        PasswordRecoveryText(self).render()
+    }
+}
+
+/// Link to confirm a subscription to the blog
+#[derive(Template)]
+#[template(path = "emails/subscription_confirmation.html")]
+pub struct SubscriptionConfirmation<'a> {
+    pub url: &'a str,
+    pub validity_days: i64,
+}
+
+#[derive(Template)]
+#[template(path = "emails/subscription_confirmation.txt")]
+struct SubscriptionConfirmationText<'a>(&'a SubscriptionConfirmation<'a>);
+
+impl<'a> Deref for SubscriptionConfirmationText<'a> {
+    type Target = SubscriptionConfirmation<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl EmailTemplate for SubscriptionConfirmation<'_> {
+    fn subject(&self) -> String {
+        "Confirmez votre inscription - Ludivine Farat".to_string()
+    }
+
+    fn html(&self) -> askama::Result<String> {
+        self.render()
+    }
+
+    fn text(&self) -> askama::Result<String> {
+        SubscriptionConfirmationText(self).render()
+    }
+}
+
+/// Article just published, to a subscriber
+#[derive(Template)]
+#[template(path = "emails/new_article.html")]
+pub struct NewArticle<'a> {
+    pub title: &'a str,
+    pub description: Option<&'a str>,
+    pub url: &'a str,
+    pub cover_url: &'a str,
+    pub unsubscribe_url: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/new_article.txt")]
+struct NewArticleText<'a>(&'a NewArticle<'a>);
+
+impl<'a> Deref for NewArticleText<'a> {
+    type Target = NewArticle<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl EmailTemplate for NewArticle<'_> {
+    fn subject(&self) -> String {
+        format!("Nouvel article : {}", self.title)
+    }
+
+    fn html(&self) -> askama::Result<String> {
+        self.render()
+    }
+
+    fn text(&self) -> askama::Result<String> {
+        NewArticleText(self).render()
+    }
+}
+
+/// Comment waiting for moderation, to the admins
+#[derive(Template)]
+#[template(path = "emails/new_comment.html")]
+pub struct NewComment<'a> {
+    pub author: &'a str,
+    /// Title of the article
+    pub article: &'a str,
+    pub url: &'a str,
+    pub content: &'a str,
+    pub moderation_url: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "emails/new_comment.txt")]
+struct NewCommentText<'a>(&'a NewComment<'a>);
+
+impl<'a> Deref for NewCommentText<'a> {
+    type Target = NewComment<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl EmailTemplate for NewComment<'_> {
+    fn subject(&self) -> String {
+        format!("Nouveau commentaire sur « {} »", self.article)
+    }
+
+    fn html(&self) -> askama::Result<String> {
+        self.render()
+    }
+
+    fn text(&self) -> askama::Result<String> {
+        NewCommentText(self).render()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AutoReply, Contact, EmailTemplate};
+
+    #[test]
+    fn test_contact() {
+        let contact = Contact {
+            firstname: "Jean",
+            lastname: "Dupont",
+            phone_number: None,
+            email: "jean.dupont@example.com",
+            content: "<script>alert('Lorem ipsum')</script>",
+        };
+        let html = contact.html().unwrap();
+        let text = contact.text().unwrap();
+
+        assert_eq!(contact.subject(), "Nouveau message de Jean Dupont");
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(!html.contains("Numéro"));
+        assert!(text.contains("<script>alert('Lorem ipsum')</script>"));
+    }
+
+    #[test]
+    fn test_auto_reply() {
+        let auto_reply = AutoReply {
+            subject: "Merci {prénom}",
+            body: "Bonjour {prénom},\n\nÀ bientôt",
+            firstname: "<b>Jean</b>",
+            content: "Lorem ipsum dolor sit amet",
+        };
+        let html = auto_reply.html().unwrap();
+
+        assert_eq!(auto_reply.subject(), "Merci <b>Jean</b>");
+        assert!(!html.contains("<b>"));
+        assert!(html.contains("Bonjour &lt;b&gt;Jean"));
+        assert!(html.contains("Lorem ipsum dolor sit amet"));
+        assert!(auto_reply
+            .text()
+            .unwrap()
+            .starts_with("Bonjour <b>Jean</b>,\n\nÀ bientôt"));
+    }
+}
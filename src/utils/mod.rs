@@ -1,4 +1,13 @@
+pub mod html;
 pub mod https;
 pub mod image;
+pub mod mail;
 pub mod patch;
+pub mod signature;
+pub mod spam;
+pub mod storage;
+pub mod svg;
+pub mod traffic;
 pub mod ua;
+pub mod upload;
+pub mod video;
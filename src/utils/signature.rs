@@ -0,0 +1,53 @@
+//! Tokens signed with HMAC-SHA256, `{payload}.{signature}`, to put in the
+//! links and the forms given to the visitors. The payload is not encrypted and
+//! must only contain URL safe characters.
+
+use ring::hmac;
+
+/// Key of the variable `name`, `None` when it is not set
+pub fn key_from_env(name: &str) -> Option<hmac::Key> {
+    std::env::var(name)
+        .ok()
+        .filter(|secret| !secret.is_empty())
+        .map(|secret| hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()))
+}
+
+pub fn sign(key: &hmac::Key, payload: &str) -> String {
+    let signature = hmac::sign(key, payload.as_bytes());
+
+    format!(
+        "{}.{}",
+        payload,
+        base64::encode_config(signature.as_ref(), base64::URL_SAFE_NO_PAD)
+    )
+}
+
+/// Payload of a token signed with the key
+pub fn verify<'a>(key: &hmac::Key, token: &'a str) -> Option<&'a str> {
+    let (payload, signature) = token.rsplit_once('.')?;
+    let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD).ok()?;
+
+    hmac::verify(key, payload.as_bytes(), &signature)
+        .ok()
+        .map(|_| payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::hmac;
+
+    #[test]
+    fn test_verify() {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"secret");
+        let other_key = hmac::Key::new(hmac::HMAC_SHA256, b"other secret");
+        let token = super::sign(&key, "unsubscribe.1");
+
+        assert_eq!(super::verify(&key, &token), Some("unsubscribe.1"));
+        assert_eq!(super::verify(&other_key, &token), None);
+        assert_eq!(
+            super::verify(&key, &token.replacen("unsubscribe.1", "unsubscribe.2", 1)),
+            None
+        );
+        assert_eq!(super::verify(&key, "unsubscribe"), None);
+    }
+}
@@ -0,0 +1,369 @@
+//! Spam checks of the contact form. Each one is cheap to pass for a visitor
+//! and costly to pass for a bot: a hidden field only bots fill, a signed token
+//! given with the form proving it has been displayed for a few seconds, a
+//! proof of work on this token when enabled and a few heuristics on the
+//! content. A message failing a check is kept in the spam folder.
+
+use super::signature;
+use crate::services::spam_tokens;
+use chrono::{DateTime, TimeZone, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use ring::{
+    digest, hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+use sqlx::PgPool;
+use std::sync::Mutex;
+
+/// Time (in seconds) a visitor needs at least to fill the form
+const MIN_SUBMIT_SECONDS: i64 = 3;
+
+/// Age (in seconds) of a token after which the page must be reloaded
+const MAX_TOKEN_AGE: i64 = 24 * 3600;
+
+/// Links a message can contain
+const MAX_LINKS: usize = 2;
+
+/// Leading zero bits a proof of work can require, more would take minutes
+const MAX_DIFFICULTY: u32 = 24;
+
+/// Words hardly ever found in a message to a graphic designer
+const SPAM_WORDS: &[&str] = &[
+    "viagra",
+    "cialis",
+    "casino",
+    "porn",
+    "escort",
+    "bitcoin",
+    "forex",
+    "backlinks?",
+    "payday",
+    "seo ranking",
+];
+
+static LINKS: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)https?://|www\.").unwrap());
+
+static KEYWORDS: Lazy<Regex> =
+    Lazy::new(|| Regex::new(&format!(r"(?i)\b({})\b", SPAM_WORDS.join("|"))).unwrap());
+
+/// Key of the tokens, from `CONTACT_SECRET` or generated at the first call
+static KEY: Mutex<Option<hmac::Key>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    /// The hidden field has been filled
+    Honeypot,
+    /// The token is missing, forged, expired or already used
+    Token,
+    /// The form has been sent right after being displayed
+    TooFast,
+    Proof,
+    Links,
+    Keywords,
+    /// The IP sent too many messages in the last hour
+    TooManyMessages,
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            Reason::Honeypot => "honeypot filled",
+            Reason::Token => "invalid token",
+            Reason::TooFast => "sent too fast",
+            Reason::Proof => "invalid proof of work",
+            Reason::Links => "too many links",
+            Reason::Keywords => "spam keywords",
+            Reason::TooManyMessages => "too many messages",
+        };
+
+        write!(f, "{}", reason)
+    }
+}
+
+/// Message sent with the contact form, with the fields of the checks
+pub struct Submission<'a> {
+    pub honeypot: Option<&'a str>,
+    pub token: Option<&'a str>,
+    pub proof: Option<u64>,
+    /// Single line fields, e.g. the name, which should never contain a link
+    pub fields: &'a [&'a str],
+    pub content: &'a str,
+}
+
+/// Key shared by the tokens of the process. Set `CONTACT_SECRET` to keep the
+/// tokens of the displayed forms valid after a restart
+fn key() -> hmac::Key {
+    let mut key = KEY.lock().unwrap_or_else(|e| e.into_inner());
+
+    key.get_or_insert_with(|| {
+        signature::key_from_env("CONTACT_SECRET").unwrap_or_else(|| {
+            hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+                .expect("Cannot generate the key of the contact tokens")
+        })
+    })
+    .clone()
+}
+
+fn token_at(timestamp: i64) -> String {
+    // Makes each token unique, so that a used token and its proof of work can
+    // be recorded and refused afterwards
+    let mut nonce = [0; 12];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .expect("Cannot generate the nonce of a contact token");
+    let nonce = base64::encode_config(nonce, base64::URL_SAFE_NO_PAD);
+
+    signature::sign(&key(), &format!("{}.{}", timestamp, nonce))
+}
+
+/// Token to give with the form, `{timestamp}.{nonce}.{signature}`
+pub fn token() -> String {
+    token_at(chrono::Utc::now().timestamp())
+}
+
+/// Timestamp and nonce of a token signed here
+fn parse_token(token: &str) -> Option<(i64, &str)> {
+    let (timestamp, nonce) = signature::verify(&key(), token)?.split_once('.')?;
+
+    Some((timestamp.parse().ok()?, nonce))
+}
+
+fn check_token_at(token: &str, now: i64) -> Result<(), Reason> {
+    let (timestamp, _) = parse_token(token).ok_or(Reason::Token)?;
+
+    if now - timestamp > MAX_TOKEN_AGE {
+        Err(Reason::Token)
+    } else if now - timestamp < MIN_SUBMIT_SECONDS {
+        Err(Reason::TooFast)
+    } else {
+        Ok(())
+    }
+}
+
+/// Check that the token has been signed here, neither too long nor too
+/// recently
+pub fn check_token(token: &str) -> Result<(), Reason> {
+    check_token_at(token, chrono::Utc::now().timestamp())
+}
+
+/// Nonce of a token signed here and the date it expires at, to record it once
+/// a form has been sent with it
+pub fn token_nonce(token: &str) -> Option<(String, DateTime<Utc>)> {
+    let (timestamp, nonce) = parse_token(token)?;
+
+    Some((
+        nonce.to_string(),
+        Utc.timestamp(timestamp + MAX_TOKEN_AGE, 0),
+    ))
+}
+
+/// Leading zero bits of the proof of work, set by `CONTACT_POW_DIFFICULTY`
+/// (0, the default, disables it)
+pub fn difficulty() -> u32 {
+    std::env::var("CONTACT_POW_DIFFICULTY")
+        .ok()
+        .and_then(|difficulty| difficulty.parse::<u32>().ok())
+        .unwrap_or(0)
+        .min(MAX_DIFFICULTY)
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+
+    for byte in hash {
+        bits += byte.leading_zeros();
+
+        if *byte != 0 {
+            break;
+        }
+    }
+
+    bits
+}
+
+/// Check that the SHA-256 of `{token}:{proof}` starts with `difficulty` zero
+/// bits
+pub fn check_proof(token: &str, proof: Option<u64>, difficulty: u32) -> Result<(), Reason> {
+    if difficulty == 0 {
+        return Ok(());
+    }
+
+    let proof = proof.ok_or(Reason::Proof)?;
+    let hash = digest::digest(&digest::SHA256, format!("{}:{}", token, proof).as_bytes());
+
+    if leading_zero_bits(hash.as_ref()) >= difficulty {
+        Ok(())
+    } else {
+        Err(Reason::Proof)
+    }
+}
+
+/// Heuristics on the content: too many links, links in the single line
+/// fields, and spam keywords
+pub fn check_content(fields: &[&str], content: &str) -> Result<(), Reason> {
+    if LINKS.find_iter(content).count() > MAX_LINKS
+        || fields.iter().any(|field| LINKS.is_match(field))
+    {
+        return Err(Reason::Links);
+    }
+
+    if fields
+        .iter()
+        .chain(std::iter::once(&content))
+        .any(|text| KEYWORDS.is_match(text))
+    {
+        return Err(Reason::Keywords);
+    }
+
+    Ok(())
+}
+
+/// Run every check, the first failing one gives the reason
+pub fn check(submission: &Submission) -> Result<(), Reason> {
+    if submission
+        .honeypot
+        .map_or(false, |honeypot| !honeypot.is_empty())
+    {
+        return Err(Reason::Honeypot);
+    }
+
+    let token = submission.token.ok_or(Reason::Token)?;
+
+    check_token(token)?;
+    check_proof(token, submission.proof, difficulty())?;
+    check_content(submission.fields, submission.content)
+}
+
+/// Verdict of a form sent by an IP with `attempts` submissions in the last
+/// hour: past `limit` it is a spam, otherwise every check is run and the token
+/// is used once they all passed. The outer error is a database one
+pub async fn verdict(
+    pool: &PgPool,
+    submission: &Submission<'_>,
+    attempts: i64,
+    limit: i64,
+) -> Result<Result<(), Reason>, sqlx::Error> {
+    if attempts >= limit {
+        return Ok(Err(Reason::TooManyMessages));
+    }
+
+    if let Err(reason) = check(submission) {
+        return Ok(Err(reason));
+    }
+
+    let token = submission.token.unwrap_or_default();
+
+    if spam_tokens::consume(pool, token).await? {
+        Ok(Ok(()))
+    } else {
+        Ok(Err(Reason::Token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Reason, Submission};
+    use crate::create_pool;
+    use dotenv::dotenv;
+
+    #[test]
+    fn test_token() {
+        let now = chrono::Utc::now().timestamp();
+        let token = super::token_at(now - 10);
+
+        assert_eq!(super::check_token_at(&token, now), Ok(()));
+        assert_eq!(
+            super::check_token_at(&super::token_at(now), now),
+            Err(Reason::TooFast)
+        );
+        assert_eq!(
+            super::check_token_at(&super::token_at(now - 2 * 24 * 3600), now),
+            Err(Reason::Token)
+        );
+
+        // The timestamp can not be changed without the key
+        let forged = token.replacen(&(now - 10).to_string(), &(now - 60).to_string(), 1);
+
+        assert_eq!(super::check_token_at(&forged, now), Err(Reason::Token));
+        assert_eq!(super::check_token_at("", now), Err(Reason::Token));
+    }
+
+    #[test]
+    fn test_token_nonce() {
+        let now = chrono::Utc::now().timestamp();
+        let (nonce, expiration_date) = super::token_nonce(&super::token_at(now)).unwrap();
+
+        assert_eq!(expiration_date.timestamp(), now + super::MAX_TOKEN_AGE);
+        assert_ne!(super::token_nonce(&super::token_at(now)).unwrap().0, nonce);
+        assert_eq!(super::token_nonce("forged.token"), None);
+    }
+
+    #[test]
+    fn test_proof() {
+        let token = super::token();
+        let proof = (0..).find(|proof| super::check_proof(&token, Some(*proof), 8).is_ok());
+
+        assert!(proof.is_some());
+        assert_eq!(super::check_proof(&token, None, 8), Err(Reason::Proof));
+        assert_eq!(super::check_proof(&token, None, 0), Ok(()));
+        assert_eq!(super::leading_zero_bits(&[0, 0b0001_0000, 0]), 11);
+    }
+
+    #[test]
+    fn test_content() {
+        assert_eq!(
+            super::check_content(
+                &["Jean", "Dupont"],
+                "Bonjour, voici mon site https://example.com"
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            super::check_content(
+                &["Jean"],
+                "https://a.example.com https://b.example.com www.c.example.com"
+            ),
+            Err(Reason::Links)
+        );
+        assert_eq!(
+            super::check_content(&["www.example.com"], "Bonjour"),
+            Err(Reason::Links)
+        );
+        assert_eq!(
+            super::check_content(&["Jean"], "Cheap VIAGRA here"),
+            Err(Reason::Keywords)
+        );
+        // Only whole words are matched
+        assert_eq!(super::check_content(&["Jean"], "Un escortage"), Ok(()));
+    }
+
+    #[actix_rt::test]
+    async fn test_verdict() {
+        dotenv().ok();
+
+        let pool = create_pool().await.unwrap();
+        let token = super::token_at(chrono::Utc::now().timestamp() - 10);
+        let submission = Submission {
+            honeypot: None,
+            token: Some(&token),
+            proof: None,
+            fields: &["Jean"],
+            content: "Bonjour",
+        };
+
+        assert_eq!(
+            super::verdict(&pool, &submission, 5, 5).await.unwrap(),
+            Err(Reason::TooManyMessages)
+        );
+        // The token is only used by a message passing the checks
+        assert_eq!(
+            super::verdict(&pool, &submission, 0, 5).await.unwrap(),
+            Ok(())
+        );
+        assert_eq!(
+            super::verdict(&pool, &submission, 0, 5).await.unwrap(),
+            Err(Reason::Token)
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use super::{Storage, StorageError};
+use actix_files::NamedFile;
+use actix_web::{
+    http::{
+        header::{CACHE_CONTROL, EXPIRES},
+        HeaderValue,
+    },
+    HttpRequest, HttpResponse,
+};
+use std::path::{Component, Path, PathBuf};
+
+/// Files stored in a directory of the server, served with `NamedFile`
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStorage { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        let is_relative = Path::new(key)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+
+        if key.is_empty() || !is_relative {
+            return Err(StorageError::InvalidKey(key.to_string()));
+        }
+
+        Ok(self.root.join(key))
+    }
+
+    fn list_directory(&self, directory: &Path, keys: &mut Vec<String>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                self.list_directory(&path, keys)?;
+            } else if let Ok(key) = path.strip_prefix(&self.root) {
+                keys.push(key.to_string_lossy().replace('\\', "/"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Storage for LocalStorage {
+    fn put(&self, key: &str, data: &[u8], _content_type: &str) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        Ok(std::fs::write(path, data)?)
+    }
+
+    fn put_file(&self, key: &str, source: &Path, _content_type: &str) -> Result<(), StorageError> {
+        let path = self.path(key)?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::copy(source, path)?;
+
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        Ok(std::fs::read(self.path(key)?)?)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), StorageError> {
+        match std::fs::remove_file(self.path(key)?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>, StorageError> {
+        let mut keys = Vec::new();
+
+        if self.root.exists() {
+            self.list_directory(&self.root, &mut keys)?;
+        }
+
+        Ok(keys)
+    }
+
+    fn serve(&self, req: &HttpRequest, key: &str) -> Result<HttpResponse, StorageError> {
+        use chrono::{Duration, Local};
+
+        let file = NamedFile::open(self.path(key)?)?;
+        let mut response = file
+            .into_response(req)
+            .map_err(|e| StorageError::Http(e.to_string()))?;
+        let expires = Local::now() + Duration::days(30);
+        let headers = response.headers_mut();
+        headers.append(
+            EXPIRES,
+            HeaderValue::from_str(&expires.to_rfc2822()).unwrap(),
+        );
+        headers.append(CACHE_CONTROL, HeaderValue::from_static("public"));
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LocalStorage, Storage, StorageError};
+
+    #[test]
+    fn test_put_get_delete() {
+        let directory = tempfile::tempdir().unwrap();
+        let storage = LocalStorage::new(directory.path());
+
+        storage.put("mobile/a.jpg", b"lorem", "image/jpeg").unwrap();
+        storage.put("a.jpg", b"ipsum", "image/jpeg").unwrap();
+
+        assert_eq!(storage.get("mobile/a.jpg").unwrap(), b"lorem");
+
+        let mut keys = storage.list().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["a.jpg", "mobile/a.jpg"]);
+
+        storage.delete("a.jpg").unwrap();
+        storage.delete("a.jpg").unwrap();
+        assert!(storage.get("a.jpg").is_err());
+    }
+
+    #[test]
+    fn test_reject_path_traversal() {
+        let directory = tempfile::tempdir().unwrap();
+        let storage = LocalStorage::new(directory.path());
+
+        assert!(matches!(
+            storage.get("../Cargo.toml"),
+            Err(StorageError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            storage.put("/etc/passwd", b"", "text/plain"),
+            Err(StorageError::InvalidKey(_))
+        ));
+    }
+}
@@ -0,0 +1,114 @@
+//! Storage of the uploaded files. Files are identified by a key relative to
+//! the uploads root (`{name}.jpg`, `mobile/{name}.webp`...), which is the
+//! value stored in `files.path` and the part of the URL after `/uploads/`.
+//! Every backend is served from this route, so the links of the pages do not
+//! depend on the backend.
+
+use actix_web::{HttpRequest, HttpResponse};
+use std::{path::Path, sync::Arc};
+
+mod local;
+mod s3;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(std::io::Error),
+    Http(String),
+    /// The key is not a valid relative path
+    InvalidKey(String),
+    Config(String),
+}
+
+impl std::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::Io(e) => write!(f, "Storage io error: {}", e),
+            StorageError::Http(e) => write!(f, "Storage http error: {}", e),
+            StorageError::InvalidKey(key) => write!(f, "Invalid storage key: {}", key),
+            StorageError::Config(e) => write!(f, "Invalid storage configuration: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self {
+        StorageError::Io(e)
+    }
+}
+
+impl From<StorageError> for std::io::Error {
+    fn from(e: StorageError) -> Self {
+        match e {
+            StorageError::Io(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::Other, e.to_string()),
+        }
+    }
+}
+
+/// The operations block the thread until they are completed, the handlers
+/// run them with `web::block`
+pub trait Storage: Send + Sync {
+    fn put(&self, key: &str, data: &[u8], content_type: &str) -> Result<(), StorageError>;
+
+    /// Store a file from the local filesystem, without loading it in memory
+    /// when the backend allows it
+    fn put_file(&self, key: &str, path: &Path, content_type: &str) -> Result<(), StorageError> {
+        self.put(key, &std::fs::read(path)?, content_type)
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, StorageError>;
+
+    /// Deleting a missing file is not an error
+    fn delete(&self, key: &str) -> Result<(), StorageError>;
+
+    /// Keys of every stored file
+    fn list(&self) -> Result<Vec<String>, StorageError>;
+
+    /// Response of `/uploads/{key}`
+    fn serve(&self, req: &HttpRequest, key: &str) -> Result<HttpResponse, StorageError>;
+}
+
+/// Content type of a stored file, from its extension
+pub fn content_type(key: &str) -> &'static str {
+    match key.rsplit('.').next().unwrap_or_default() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Create a backend by name: `local` or `s3`
+pub fn from_name(name: &str) -> Result<Arc<dyn Storage>, StorageError> {
+    match name {
+        "local" => Ok(Arc::new(LocalStorage::new(
+            std::env::var("UPLOADS_DIR").unwrap_or_else(|_| "./uploads".to_string()),
+        ))),
+        "s3" => Ok(Arc::new(S3Storage::from_env()?)),
+        _ => Err(StorageError::Config(format!("unknown backend {}", name))),
+    }
+}
+
+/// Create the backend selected by the `STORAGE` variable (`local` by default)
+pub fn from_env() -> Result<Arc<dyn Storage>, StorageError> {
+    from_name(&std::env::var("STORAGE").unwrap_or_else(|_| "local".to_string()))
+}
+
+/// Copy every file of a backend to another one and return the number of
+/// copied files. Files are not removed from the source backend
+pub fn migrate(from: &dyn Storage, to: &dyn Storage) -> Result<usize, StorageError> {
+    let keys = from.list()?;
+
+    for key in &keys {
+        to.put(key, &from.get(key)?, content_type(key))?;
+    }
+
+    Ok(keys.len())
+}
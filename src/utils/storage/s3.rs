@@ -0,0 +1,239 @@
+use super::{Storage, StorageError};
+use actix_web::{HttpRequest, HttpResponse};
+use rusty_s3::{actions::ListObjectsV2, Bucket, Credentials, S3Action, UrlStyle};
+use std::{io::Read, path::Path, time::Duration};
+
+/// Validity of the presigned URLs
+const SIGNATURE_DURATION: Duration = Duration::from_secs(60 * 60);
+
+/// Files stored in a S3-compatible bucket (AWS, Scaleway, MinIO...). Requests
+/// are signed with `rusty-s3` and sent with a blocking client, like the
+/// local backend does its filesystem operations
+pub struct S3Storage {
+    bucket: Bucket,
+    credentials: Credentials,
+    /// Base URL of a public bucket (or of a CDN in front of it), presigned
+    /// URLs are generated when it is missing
+    public_url: Option<String>,
+    agent: ureq::Agent,
+}
+
+fn http_error(e: ureq::Error) -> StorageError {
+    StorageError::Http(e.to_string())
+}
+
+impl S3Storage {
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        credentials: Credentials,
+        public_url: Option<String>,
+    ) -> Result<Self, StorageError> {
+        let endpoint = endpoint
+            .parse()
+            .map_err(|_| StorageError::Config(format!("invalid endpoint {}", endpoint)))?;
+        // Path style URLs are supported by every implementation, MinIO included
+        let bucket = Bucket::new(
+            endpoint,
+            UrlStyle::Path,
+            bucket.to_string(),
+            region.to_string(),
+        )
+        .ok_or_else(|| StorageError::Config(format!("invalid bucket {}", bucket)))?;
+
+        Ok(S3Storage {
+            bucket,
+            credentials,
+            public_url: public_url.map(|url| url.trim_end_matches('/').to_string()),
+            agent: ureq::agent(),
+        })
+    }
+
+    /// Configuration from `S3_ENDPOINT`, `S3_BUCKET`, `S3_REGION`,
+    /// `S3_ACCESS_KEY`, `S3_SECRET_KEY` and optionally `S3_PUBLIC_URL`
+    pub fn from_env() -> Result<Self, StorageError> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|_| StorageError::Config(format!("{} not found", name)))
+        };
+
+        S3Storage::new(
+            &var("S3_ENDPOINT")?,
+            &var("S3_BUCKET")?,
+            &var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+            Credentials::new(var("S3_ACCESS_KEY")?, var("S3_SECRET_KEY")?),
+            std::env::var("S3_PUBLIC_URL").ok(),
+        )
+    }
+
+    /// URL `/uploads/{key}` redirects to
+    fn url(&self, key: &str) -> String {
+        match &self.public_url {
+            Some(public_url) => format!("{}/{}", public_url, key),
+            None => self
+                .bucket
+                .get_object(Some(&self.credentials), key)
+                .sign(SIGNATURE_DURATION)
+                .to_string(),
+        }
+    }
+}
+
+impl Storage for S3Storage {
+    fn put(&self, key: &str, data: &[u8], content_type: &str) -> Result<(), StorageError> {
+        let url = self
+            .bucket
+            .put_object(Some(&self.credentials), key)
+            .sign(SIGNATURE_DURATION);
+
+        self.agent
+            .put(url.as_str())
+            .set("Content-Type", content_type)
+            .send_bytes(data)
+            .map_err(http_error)?;
+
+        Ok(())
+    }
+
+    fn put_file(&self, key: &str, path: &Path, content_type: &str) -> Result<(), StorageError> {
+        let file = std::fs::File::open(path)?;
+        let length = file.metadata()?.len();
+        let url = self
+            .bucket
+            .put_object(Some(&self.credentials), key)
+            .sign(SIGNATURE_DURATION);
+
+        // The length is set so the body is not sent with a chunked encoding,
+        // which presigned uploads do not support
+        self.agent
+            .put(url.as_str())
+            .set("Content-Type", content_type)
+            .set("Content-Length", &length.to_string())
+            .send(file)
+            .map_err(http_error)?;
+
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        let url = self
+            .bucket
+            .get_object(Some(&self.credentials), key)
+            .sign(SIGNATURE_DURATION);
+        let mut data = Vec::new();
+
+        self.agent
+            .get(url.as_str())
+            .call()
+            .map_err(http_error)?
+            .into_reader()
+            .read_to_end(&mut data)?;
+
+        Ok(data)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), StorageError> {
+        let url = self
+            .bucket
+            .delete_object(Some(&self.credentials), key)
+            .sign(SIGNATURE_DURATION);
+
+        match self.agent.delete(url.as_str()).call() {
+            Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+            Err(e) => Err(http_error(e)),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<String>, StorageError> {
+        let mut keys = Vec::new();
+        let mut continuation_token: Option<String> = None;
+
+        loop {
+            let mut action = self.bucket.list_objects_v2(Some(&self.credentials));
+
+            if let Some(token) = &continuation_token {
+                action.with_continuation_token(token.clone());
+            }
+
+            let body = self
+                .agent
+                .get(action.sign(SIGNATURE_DURATION).as_str())
+                .call()
+                .map_err(http_error)?
+                .into_string()?;
+            let response = ListObjectsV2::parse_response(&body)
+                .map_err(|e| StorageError::Http(e.to_string()))?;
+
+            keys.extend(response.contents.into_iter().map(|object| object.key));
+
+            match response.next_continuation_token {
+                Some(token) => continuation_token = Some(token),
+                None => break,
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn serve(&self, _req: &HttpRequest, key: &str) -> Result<HttpResponse, StorageError> {
+        Ok(HttpResponse::Found()
+            .header("location", self.url(key))
+            .finish())
+    }
+}
+
+/// Run against a S3-compatible server configured with the `S3_*` variables,
+/// e.g. a local MinIO:
+/// `docker run -p 9000:9000 -e MINIO_ROOT_USER=minio -e MINIO_ROOT_PASSWORD=password minio/minio server /data`
+/// with a `uploads-test` bucket. Skipped when `S3_ENDPOINT` is not defined
+#[cfg(test)]
+mod tests {
+    use super::{S3Storage, Storage};
+    use dotenv::dotenv;
+
+    fn storage() -> Option<S3Storage> {
+        dotenv().ok();
+
+        std::env::var("S3_ENDPOINT").ok()?;
+
+        Some(S3Storage::from_env().unwrap())
+    }
+
+    #[test]
+    fn test_put_get_delete() {
+        let storage = match storage() {
+            Some(storage) => storage,
+            None => return,
+        };
+        let key = format!("mobile/test_{}.jpg", chrono::Utc::now().timestamp_nanos());
+
+        storage.put(&key, b"lorem ipsum", "image/jpeg").unwrap();
+
+        assert_eq!(storage.get(&key).unwrap(), b"lorem ipsum");
+        assert!(storage.list().unwrap().contains(&key));
+
+        storage.delete(&key).unwrap();
+        storage.delete(&key).unwrap();
+
+        assert!(storage.get(&key).is_err());
+    }
+
+    #[test]
+    fn test_put_file() {
+        use std::io::Write;
+
+        let storage = match storage() {
+            Some(storage) => storage,
+            None => return,
+        };
+        let key = format!("test_{}.mp4", chrono::Utc::now().timestamp_nanos());
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&[0; 4096]).unwrap();
+
+        storage.put_file(&key, file.path(), "video/mp4").unwrap();
+
+        assert_eq!(storage.get(&key).unwrap().len(), 4096);
+
+        storage.delete(&key).unwrap();
+    }
+}
@@ -0,0 +1,298 @@
+//! Sanitization of the SVG files uploaded from the back office (logo and
+//! favicon), which are served publicly from the root of the site.
+
+use once_cell::sync::Lazy;
+use quick_xml::{
+    events::{attributes::Attribute, BytesStart, Event},
+    Reader, Writer,
+};
+use regex::Regex;
+use std::io::Cursor;
+
+/// Elements removed with their whole content
+const FORBIDDEN_ELEMENTS: &[&str] = &[
+    "script",
+    "foreignobject",
+    "iframe",
+    "embed",
+    "object",
+    "handler",
+    "listener",
+];
+
+/// Animations which could change a reference once the document is sanitized
+const ANIMATION_ELEMENTS: &[&str] = &["animate", "set"];
+
+static URL: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?i)url\(\s*['"]?([^'")]*)"#).unwrap());
+
+#[derive(Debug)]
+pub enum SvgError {
+    /// The document root is not an `<svg>` element
+    NotSvg,
+    Xml(quick_xml::Error),
+}
+
+impl std::fmt::Display for SvgError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SvgError::NotSvg => write!(f, "The document is not a SVG"),
+            SvgError::Xml(e) => write!(f, "Invalid SVG: {}", e),
+        }
+    }
+}
+
+impl From<quick_xml::Error> for SvgError {
+    fn from(e: quick_xml::Error) -> Self {
+        SvgError::Xml(e)
+    }
+}
+
+/// Only references to the document itself or to embedded images are allowed
+fn is_external(target: &str) -> bool {
+    let target: String = target
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_lowercase();
+
+    !(target.starts_with('#')
+        || target.starts_with("data:image/png")
+        || target.starts_with("data:image/jpeg")
+        || target.starts_with("data:image/gif")
+        || target.starts_with("data:image/webp"))
+}
+
+/// Check whether CSS (a style sheet or any attribute, presentation attributes
+/// such as `fill` or `filter` accepting `url()` too) loads external resources
+fn has_external_url(css: &str) -> bool {
+    css.to_lowercase().contains("@import")
+        || css.to_lowercase().contains("expression(")
+        || URL
+            .captures_iter(css)
+            .any(|captures| is_external(&captures[1]))
+}
+
+/// Copy an element without its event handlers and external references
+fn clean_element(element: &BytesStart) -> Result<BytesStart<'static>, SvgError> {
+    let name = element.name().to_vec();
+    let mut cleaned = BytesStart::owned(name.clone(), name.len());
+
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        let key = String::from_utf8_lossy(attribute.key).to_lowercase();
+        let value = String::from_utf8_lossy(&attribute.unescaped_value()?).to_string();
+
+        let is_forbidden = key.starts_with("on")
+            || ((key == "href" || key.ends_with(":href")) && is_external(&value))
+            || has_external_url(&value)
+            || value.to_lowercase().contains("javascript:");
+
+        if !is_forbidden {
+            cleaned.push_attribute(Attribute {
+                key: attribute.key,
+                value: attribute.value,
+            });
+        }
+    }
+
+    Ok(cleaned)
+}
+
+fn local_name(element: &BytesStart) -> String {
+    String::from_utf8_lossy(element.local_name()).to_lowercase()
+}
+
+/// Whether the element is removed with its whole content
+fn is_forbidden(element: &BytesStart, name: &str) -> Result<bool, SvgError> {
+    if FORBIDDEN_ELEMENTS.contains(&name) {
+        return Ok(true);
+    }
+
+    if ANIMATION_ELEMENTS.contains(&name) {
+        for attribute in element.attributes() {
+            let attribute = attribute?;
+
+            if String::from_utf8_lossy(attribute.key).to_lowercase() == "attributename" {
+                let target = String::from_utf8_lossy(&attribute.unescaped_value()?)
+                    .trim()
+                    .to_lowercase();
+
+                if target == "href" || target.ends_with(":href") {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// Parse the SVG and rewrite it without scripts, event handlers, foreign
+/// objects, external references, comments and doctype
+pub fn sanitize(data: &[u8]) -> Result<Vec<u8>, SvgError> {
+    let mut reader = Reader::from_reader(data);
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    let mut buffer = Vec::new();
+    let mut has_root = false;
+    let mut depth = 0;
+    // Depth of the forbidden element being skipped
+    let mut skipped: Option<usize> = None;
+    let mut in_style = false;
+
+    reader.check_end_names(true);
+
+    loop {
+        let event = reader.read_event(&mut buffer)?;
+
+        match event {
+            Event::Start(ref element) | Event::Empty(ref element) => {
+                let is_empty = matches!(event, Event::Empty(_));
+                let name = local_name(element);
+
+                if !has_root {
+                    if name != "svg" {
+                        return Err(SvgError::NotSvg);
+                    }
+
+                    has_root = true;
+                } else if depth == 0 {
+                    return Err(SvgError::NotSvg);
+                }
+
+                if !is_empty {
+                    depth += 1;
+                }
+
+                if skipped.is_none() {
+                    if is_forbidden(element, &name)? {
+                        if !is_empty {
+                            skipped = Some(depth);
+                        }
+                    } else {
+                        let cleaned = clean_element(element)?;
+
+                        if is_empty {
+                            writer.write_event(Event::Empty(cleaned))?;
+                        } else {
+                            in_style = name == "style";
+                            writer.write_event(Event::Start(cleaned))?;
+                        }
+                    }
+                }
+            }
+            Event::End(ref element) => {
+                if skipped == Some(depth) {
+                    skipped = None;
+                } else if skipped.is_none() {
+                    in_style = false;
+                    writer.write_event(Event::End(element.clone()))?;
+                }
+
+                depth -= 1;
+            }
+            Event::Text(ref text) if skipped.is_none() && depth > 0 => {
+                // Style sheets loading external resources are dropped
+                if !in_style || !has_external_url(&String::from_utf8_lossy(&text.unescaped()?)) {
+                    writer.write_event(Event::Text(text.clone()))?;
+                }
+            }
+            Event::CData(ref data) if skipped.is_none() && depth > 0 => {
+                if !in_style || !has_external_url(&String::from_utf8_lossy(data)) {
+                    writer.write_event(Event::CData(data.clone()))?;
+                }
+            }
+            Event::Decl(ref declaration) => {
+                writer.write_event(Event::Decl(declaration.clone()))?;
+            }
+            Event::Eof => break,
+            // Comments, processing instructions, doctype (and its entities)
+            _ => (),
+        }
+
+        buffer.clear();
+    }
+
+    if !has_root || depth != 0 {
+        return Err(SvgError::NotSvg);
+    }
+
+    Ok(writer.into_inner().into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sanitize, SvgError};
+
+    fn sanitize_str(svg: &str) -> String {
+        String::from_utf8(sanitize(svg.as_bytes()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_keep_valid_svg() {
+        let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10"><defs><linearGradient id="g"/></defs><rect width="10" height="10" fill="url(#g)"/><use href="#g"/></svg>"##;
+
+        assert_eq!(sanitize_str(svg), svg);
+    }
+
+    #[test]
+    fn test_strip_scripts_and_handlers() {
+        let svg = sanitize_str(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" onload="alert(1)"><script>alert(1)</script><foreignObject><div><iframe/></div></foreignObject><a href="javascript:alert(1)"><circle r="2" onclick="alert(1)"/></a></svg>"#,
+        );
+
+        assert_eq!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg"><a><circle r="2"/></a></svg>"#
+        );
+    }
+
+    #[test]
+    fn test_strip_external_references() {
+        let svg = sanitize_str(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"><image xlink:href="https://example.com/a.png"/><rect style="fill: url(https://example.com/a.svg#p)"/></svg>"#,
+        );
+
+        assert_eq!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"><image/><rect/></svg>"#
+        );
+    }
+
+    #[test]
+    fn test_strip_external_presentation_attributes() {
+        let svg = sanitize_str(
+            r##"<svg xmlns="http://www.w3.org/2000/svg"><rect fill="url(https://example.com/x#a)" stroke="url(#g)" filter="url( 'https://example.com/x#f')" mask="URL(//example.com/x#m)" clip-path="url(https://example.com/x#c)" marker-start="url(https://example.com/x#s)" cursor="url(https://example.com/c.cur), auto"/></svg>"##,
+        );
+
+        assert_eq!(
+            svg,
+            r##"<svg xmlns="http://www.w3.org/2000/svg"><rect stroke="url(#g)"/></svg>"##
+        );
+    }
+
+    #[test]
+    fn test_strip_href_animations() {
+        let svg = sanitize_str(
+            r##"<svg xmlns="http://www.w3.org/2000/svg"><a href="#"><set attributeName="href" to="javascript:alert(1)"/><animate attributeName="xlink:href" values="https://example.com"><set/></animate><animate attributeName="opacity" from="0" to="1"/><text>Lorem</text></a></svg>"##,
+        );
+
+        assert_eq!(
+            svg,
+            r##"<svg xmlns="http://www.w3.org/2000/svg"><a href="#"><animate attributeName="opacity" from="0" to="1"/><text>Lorem</text></a></svg>"##
+        );
+    }
+
+    #[test]
+    fn test_reject_non_svg() {
+        assert!(matches!(
+            sanitize(b"<html><svg></svg></html>"),
+            Err(SvgError::NotSvg)
+        ));
+        assert!(matches!(sanitize(b"not a svg"), Err(SvgError::NotSvg)));
+        assert!(matches!(
+            sanitize(b"<svg></svg><svg></svg>"),
+            Err(SvgError::NotSvg)
+        ));
+        assert!(sanitize(b"<svg><g></svg>").is_err());
+    }
+}
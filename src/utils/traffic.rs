@@ -0,0 +1,262 @@
+//! Source of the traffic of a view: UTM parameters of the landing URL and
+//! classification of the referrer.
+
+use actix_web::web::Query;
+use serde::Deserialize;
+
+/// Maximum length of a stored UTM parameter
+const UTM_MAX_LENGTH: usize = 100;
+
+/// Domains (without `www.`) of the search engines, subdomains included
+const SEARCH_ENGINES: [&str; 11] = [
+    "google",
+    "bing.com",
+    "duckduckgo.com",
+    "qwant.com",
+    "ecosia.org",
+    "search.yahoo.com",
+    "yandex",
+    "baidu.com",
+    "startpage.com",
+    "search.brave.com",
+    "lilo.org",
+];
+
+/// Domains of the social networks, subdomains included
+const SOCIAL_NETWORKS: [&str; 18] = [
+    "facebook.com",
+    "fb.com",
+    "instagram.com",
+    "t.co",
+    "twitter.com",
+    "x.com",
+    "linkedin.com",
+    "lnkd.in",
+    "pinterest",
+    "pin.it",
+    "behance.net",
+    "dribbble.com",
+    "youtube.com",
+    "vimeo.com",
+    "tiktok.com",
+    "reddit.com",
+    "threads.net",
+    "whatsapp.com",
+];
+
+/// `utm_*` parameters of the landing URL of a visit
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct Utm {
+    #[serde(rename = "utm_source")]
+    pub source: Option<String>,
+    #[serde(rename = "utm_medium")]
+    pub medium: Option<String>,
+    #[serde(rename = "utm_campaign")]
+    pub campaign: Option<String>,
+    #[serde(rename = "utm_term")]
+    pub term: Option<String>,
+    #[serde(rename = "utm_content")]
+    pub content: Option<String>,
+}
+
+impl Utm {
+    /// Parameters of a query string, with or without its leading `?`. Values
+    /// are lowercased, so `Instagram` and `instagram` are the same source
+    pub fn from_query(query: &str) -> Self {
+        let utm = Query::<Utm>::from_query(query.trim_start_matches('?'))
+            .map(Query::into_inner)
+            .unwrap_or_default();
+        let clean = |value: Option<String>| {
+            value
+                .map(|value| {
+                    value
+                        .trim()
+                        .to_lowercase()
+                        .chars()
+                        .take(UTM_MAX_LENGTH)
+                        .collect::<String>()
+                })
+                .filter(|value| !value.is_empty())
+        };
+
+        Utm {
+            source: clean(utm.source),
+            medium: clean(utm.medium),
+            campaign: clean(utm.campaign),
+            term: clean(utm.term),
+            content: clean(utm.content),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Utm::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReferrerKind {
+    /// No referrer: typed URL, bookmark, application...
+    Direct,
+    /// Another page of the site
+    Internal,
+    Search,
+    Social,
+    /// Any other website
+    Website,
+}
+
+impl ReferrerKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReferrerKind::Direct => "direct",
+            ReferrerKind::Internal => "internal",
+            ReferrerKind::Search => "search",
+            ReferrerKind::Social => "social",
+            ReferrerKind::Website => "website",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Referrer {
+    /// Lowercased host without `www.`, `m.` or `l.` (links redirectors)
+    pub domain: Option<String>,
+    pub kind: ReferrerKind,
+}
+
+impl Referrer {
+    pub fn direct() -> Self {
+        Referrer {
+            domain: None,
+            kind: ReferrerKind::Direct,
+        }
+    }
+
+    /// Navigation from another page of the site
+    pub fn internal() -> Self {
+        Referrer {
+            domain: None,
+            kind: ReferrerKind::Internal,
+        }
+    }
+
+    /// Classify the referrer of a view of the site served on `host`
+    pub fn parse(referrer: Option<&str>, host: &str) -> Self {
+        let domain = match referrer.and_then(domain) {
+            Some(domain) => domain,
+            None => return Referrer::direct(),
+        };
+        let matches = |patterns: &[&str]| {
+            patterns.iter().any(|pattern| {
+                domain == *pattern
+                    || domain.ends_with(&format!(".{}", pattern))
+                    || (!pattern.contains('.') && domain.split('.').any(|label| label == *pattern))
+            })
+        };
+        let kind = if domain == normalize(host) {
+            ReferrerKind::Internal
+        } else if matches(&SEARCH_ENGINES) {
+            ReferrerKind::Search
+        } else if matches(&SOCIAL_NETWORKS) {
+            ReferrerKind::Social
+        } else {
+            ReferrerKind::Website
+        };
+
+        Referrer {
+            domain: Some(domain),
+            kind,
+        }
+    }
+}
+
+/// Host without the port and the `www.`, `m.`, `l.` and `lm.` prefixes
+fn normalize(host: &str) -> String {
+    let mut host = host
+        .split(':')
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('.')
+        .to_lowercase();
+
+    for prefix in &["www.", "m.", "l.", "lm.", "mobile."] {
+        if let Some(stripped) = host.strip_prefix(prefix) {
+            host = stripped.to_string();
+        }
+    }
+
+    host
+}
+
+/// Normalized host of an http(s) URL
+fn domain(url: &str) -> Option<String> {
+    let url = url.trim();
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let authority = rest.split(|c| c == '/' || c == '?' || c == '#').next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = normalize(host);
+
+    (!host.is_empty()).then(|| host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Referrer, ReferrerKind, Utm};
+
+    #[test]
+    fn test_utm_from_query() {
+        let utm = Utm::from_query(
+            "?utm_source=Instagram&utm_medium=social&utm_campaign=spring%202022&page=2",
+        );
+
+        assert_eq!(utm.source.as_deref(), Some("instagram"));
+        assert_eq!(utm.medium.as_deref(), Some("social"));
+        assert_eq!(utm.campaign.as_deref(), Some("spring 2022"));
+        assert_eq!(utm.term, None);
+        assert!(Utm::from_query("").is_empty());
+        assert!(Utm::from_query("utm_source=&page=2").is_empty());
+    }
+
+    #[test]
+    fn test_referrer_parse() {
+        let host = "ludivinefarat.fr:8443";
+        let parse = |referrer| Referrer::parse(referrer, host);
+
+        assert_eq!(parse(None), Referrer::direct());
+        assert_eq!(parse(Some("android-app://com.slack")), Referrer::direct());
+        assert_eq!(
+            parse(Some("https://www.ludivinefarat.fr/portfolio")).kind,
+            ReferrerKind::Internal
+        );
+        assert_eq!(
+            parse(Some("https://www.google.fr/")),
+            Referrer {
+                domain: Some("google.fr".to_string()),
+                kind: ReferrerKind::Search
+            }
+        );
+        assert_eq!(
+            parse(Some(
+                "https://l.instagram.com/?u=https%3A%2F%2Fludivinefarat.fr"
+            )),
+            Referrer {
+                domain: Some("instagram.com".to_string()),
+                kind: ReferrerKind::Social
+            }
+        );
+        assert_eq!(parse(Some("https://t.co/abc")).kind, ReferrerKind::Social);
+        assert_eq!(
+            parse(Some("https://fr.pinterest.com/pin/1")).kind,
+            ReferrerKind::Social
+        );
+        assert_eq!(
+            parse(Some("http://blog.example.com:8080/article?id=1")),
+            Referrer {
+                domain: Some("blog.example.com".to_string()),
+                kind: ReferrerKind::Website
+            }
+        );
+    }
+}
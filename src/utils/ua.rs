@@ -5,11 +5,27 @@ use std::convert::From;
 use woothee::parser::{Parser, WootheeResult};
 use woothee::woothee::VALUE_UNKNOWN;
 
+/// Patterns of the user agents of the bots, see `bots.txt`
+const BOT_PATTERNS: &str = include_str!("bots.txt");
+
+/// The user agent matches one of the patterns of `bots.txt`
+pub fn matches_bot_pattern(user_agent: &str) -> bool {
+    let user_agent = user_agent.to_lowercase();
+
+    BOT_PATTERNS
+        .lines()
+        .map(str::trim)
+        .filter(|pattern| !pattern.is_empty() && !pattern.starts_with('#'))
+        .any(|pattern| user_agent.contains(pattern))
+}
+
 #[derive(Debug)]
 pub struct UserAgent {
     pub name: Option<String>,
     pub category: Option<String>,
     pub os: Option<String>,
+    /// Crawler according to woothee or to the patterns of `bots.txt`
+    pub is_bot: bool,
 }
 
 impl From<WootheeResult<'_>> for UserAgent {
@@ -32,6 +48,7 @@ impl From<WootheeResult<'_>> for UserAgent {
 
                 (os != VALUE_UNKNOWN).then(|| os)
             },
+            is_bot: result.category == "crawler",
         }
     }
 }
@@ -42,14 +59,73 @@ impl FromRequest for UserAgent {
     type Config = ();
 
     fn from_request(req: &HttpRequest, _: &mut dev::Payload) -> Self::Future {
-        if let Some(ua) = req.headers().get("User-Agent") {
+        if let Some(Ok(ua)) = req.headers().get("User-Agent").map(|ua| ua.to_str()) {
             let parser = Parser::new();
+            let is_bot = matches_bot_pattern(ua);
 
-            if let Some(result) = parser.parse(ua.to_str().unwrap()) {
-                return ok(UserAgent::from(result));
+            match parser.parse(ua) {
+                Some(result) => {
+                    let mut user_agent = UserAgent::from(result);
+                    user_agent.is_bot |= is_bot;
+
+                    return ok(user_agent);
+                }
+                // Unknown bots are kept to be recorded as such
+                None if is_bot => {
+                    return ok(UserAgent {
+                        name: None,
+                        category: Some("crawler".to_string()),
+                        os: None,
+                        is_bot,
+                    })
+                }
+                None => (),
             }
         }
 
         err(ErrorBadRequest("no luck"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{matches_bot_pattern, UserAgent};
+    use actix_web::{test, FromRequest};
+
+    async fn parse(user_agent: &str) -> UserAgent {
+        let req = test::TestRequest::default()
+            .header("User-Agent", user_agent)
+            .to_http_request();
+
+        UserAgent::from_request(&req, &mut actix_web::dev::Payload::None)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_matches_bot_pattern() {
+        assert!(matches_bot_pattern(
+            "UptimeRobot/2.0; http://www.uptimerobot.com/"
+        ));
+        assert!(matches_bot_pattern("facebookexternalhit/1.1"));
+        assert!(matches_bot_pattern("curl/7.68.0"));
+        assert!(!matches_bot_pattern(
+            "Mozilla/5.0 (X11; Linux x86_64; rv:95.0) Gecko/20100101 Firefox/95.0"
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn test_user_agent_is_bot() {
+        assert!(
+            parse("Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)")
+                .await
+                .is_bot
+        );
+        assert!(parse("python-requests/2.26.0").await.is_bot);
+        assert!(
+            !parse("Mozilla/5.0 (X11; Linux x86_64; rv:95.0) Gecko/20100101 Firefox/95.0")
+                .await
+                .is_bot
+        );
+    }
+}
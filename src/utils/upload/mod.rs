@@ -0,0 +1,480 @@
+//! Streaming multipart extractor. Uploaded files are written to temporary
+//! files while they are received instead of being buffered in memory, and
+//! are removed once the request has been handled.
+
+use crate::utils::video::VideoFormat;
+use actix_identity::RequestIdentity;
+use actix_web::{
+    dev::Payload, http::StatusCode, FromRequest, HttpRequest, HttpResponse, ResponseError,
+};
+use futures::{future::LocalBoxFuture, StreamExt};
+use image::DynamicImage;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{Map, Value};
+use std::{
+    io::Write,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+};
+use tempfile::{NamedTempFile, TempPath};
+
+mod de;
+
+const MEGABYTE: usize = 1_000_000;
+
+/// Maximum size (in bytes) of a text field
+const FIELD_LIMIT: usize = 64_000;
+
+/// Number of bytes kept from the beginning of each file to detect its type
+const SNIFF_LENGTH: usize = 512;
+
+pub const IMAGES: &[&str] = &["image/png", "image/jpeg", "image/webp"];
+
+/// Detect the MIME type of a file from its first bytes
+pub fn sniff(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+        return Some("image/png");
+    }
+
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some("image/jpeg");
+    }
+
+    if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        return Some("image/gif");
+    }
+
+    if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+
+    if data.starts_with(&[0x00, 0x00, 0x01, 0x00]) {
+        return Some("image/x-icon");
+    }
+
+    if let Some(format) = VideoFormat::sniff(data) {
+        return Some(format.mime_type());
+    }
+
+    let text = String::from_utf8_lossy(data);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+
+    if (text.starts_with("<?xml") || text.starts_with("<svg") || text.starts_with("<!--"))
+        && text.contains("<svg")
+    {
+        return Some("image/svg+xml");
+    }
+
+    None
+}
+
+#[derive(Debug)]
+pub enum UploadError {
+    /// The uploads are reserved to the administrators
+    Unauthorized,
+    /// The file or the whole payload exceeds the limit (in bytes)
+    PayloadTooLarge(usize),
+    /// The file type is not one of the accepted MIME types
+    UnsupportedMediaType(&'static [&'static str]),
+    Invalid(String),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for UploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UploadError::Unauthorized => write!(f, "Upload without being logged in"),
+            UploadError::PayloadTooLarge(limit) => {
+                write!(f, "Payload exceeds the limit of {} bytes", limit)
+            }
+            UploadError::UnsupportedMediaType(accepted) => {
+                write!(
+                    f,
+                    "Unsupported media type, accepted: {}",
+                    accepted.join(", ")
+                )
+            }
+            UploadError::Invalid(e) => write!(f, "Invalid multipart payload: {}", e),
+            UploadError::Io(e) => write!(f, "Upload io error: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for UploadError {
+    fn from(e: std::io::Error) -> Self {
+        UploadError::Io(e)
+    }
+}
+
+impl ResponseError for UploadError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UploadError::Unauthorized => StatusCode::UNAUTHORIZED,
+            UploadError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            UploadError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            UploadError::Invalid(_) => StatusCode::BAD_REQUEST,
+            UploadError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            UploadError::Unauthorized => HttpResponse::Unauthorized().finish(),
+            UploadError::PayloadTooLarge(limit) => {
+                HttpResponse::PayloadTooLarge().json(serde_json::json!({
+                    "error": "payload_too_large",
+                    "limit": limit,
+                }))
+            }
+            UploadError::UnsupportedMediaType(accepted) => HttpResponse::UnsupportedMediaType()
+                .json(serde_json::json!({
+                    "error": "unsupported_media_type",
+                    "accepted": accepted,
+                })),
+            UploadError::Invalid(_) => HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "invalid_payload",
+            })),
+            UploadError::Io(e) => {
+                eprintln!("{}", e);
+                HttpResponse::InternalServerError().finish()
+            }
+        }
+    }
+}
+
+/// Size limits of the uploads of a scope, registered with `.app_data()`
+#[derive(Clone, Debug)]
+pub struct UploadConfig {
+    file_limit: usize,
+    total_limit: usize,
+}
+
+impl UploadConfig {
+    pub fn new(file_limit: usize, total_limit: usize) -> Self {
+        UploadConfig {
+            file_limit,
+            total_limit,
+        }
+    }
+
+    /// Limits can be overridden with `UPLOAD_<SCOPE>_FILE_LIMIT` and
+    /// `UPLOAD_<SCOPE>_TOTAL_LIMIT` (in bytes)
+    pub fn from_env(scope: &str, file_limit: usize, total_limit: usize) -> Self {
+        let read = |name: &str, default: usize| {
+            std::env::var(format!("UPLOAD_{}_{}", scope, name))
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+
+        UploadConfig::new(
+            read("FILE_LIMIT", file_limit),
+            read("TOTAL_LIMIT", total_limit),
+        )
+    }
+
+    pub fn file_limit(&self) -> usize {
+        self.file_limit
+    }
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        UploadConfig::new(10 * MEGABYTE, 20 * MEGABYTE)
+    }
+}
+
+/// An uploaded file, stored in a temporary file
+#[derive(Deserialize, Debug)]
+pub struct File {
+    name: String,
+    mime_type: Option<String>,
+    size: usize,
+    path: PathBuf,
+}
+
+impl File {
+    /// Name of the file on the client side
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// MIME type detected from the content of the file
+    pub fn file_type(&self) -> &str {
+        self.mime_type
+            .as_deref()
+            .unwrap_or("application/octet-stream")
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn read(&self) -> std::io::Result<Vec<u8>> {
+        std::fs::read(&self.path)
+    }
+
+    /// Check the detected MIME type against the accepted ones
+    pub fn accept(&self, accepted: &'static [&'static str]) -> Result<(), UploadError> {
+        if accepted.contains(&self.file_type()) {
+            Ok(())
+        } else {
+            Err(UploadError::UnsupportedMediaType(accepted))
+        }
+    }
+
+    /// Check the file against a limit stricter than the one of the scope
+    pub fn limit(&self, limit: usize) -> Result<(), UploadError> {
+        if self.size > limit {
+            Err(UploadError::PayloadTooLarge(limit))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn image(&self) -> image::ImageResult<DynamicImage> {
+        image::io::Reader::open(&self.path)?
+            .with_guessed_format()?
+            .decode()
+    }
+}
+
+/// Multipart form deserialized into `T`. Fields named `name[]` are collected
+/// into a sequence, files are deserialized as [`File`]. Only the logged in
+/// administrators can upload, the payload of the others is not read
+pub struct Multipart<T> {
+    data: T,
+    _files: Vec<TempPath>,
+}
+
+impl<T> Deref for Multipart<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data
+    }
+}
+
+impl<T> DerefMut for Multipart<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for Multipart<T> {
+    type Error = UploadError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = UploadConfig;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        if req.get_identity().is_none() {
+            return Box::pin(async { Err(UploadError::Unauthorized) });
+        }
+
+        let config = req.app_data::<UploadConfig>().cloned().unwrap_or_default();
+        let multipart = actix_multipart::Multipart::new(req.headers(), payload.take());
+
+        Box::pin(async move { parse(multipart, config).await })
+    }
+}
+
+fn invalid(e: impl std::fmt::Display) -> UploadError {
+    UploadError::Invalid(e.to_string())
+}
+
+async fn parse<T: DeserializeOwned>(
+    mut multipart: actix_multipart::Multipart,
+    config: UploadConfig,
+) -> Result<Multipart<T>, UploadError> {
+    let mut fields = Map::new();
+    let mut files = Vec::new();
+    let mut total = 0;
+
+    while let Some(field) = multipart.next().await {
+        let mut field = field.map_err(invalid)?;
+        let disposition = field
+            .content_disposition()
+            .ok_or_else(|| invalid("missing content disposition"))?;
+        let name = disposition
+            .get_name()
+            .ok_or_else(|| invalid("missing field name"))?;
+        let (name, is_sequence) = match name.strip_suffix("[]") {
+            Some(name) => (name.to_string(), true),
+            None => (name.to_string(), false),
+        };
+
+        let value = match disposition.get_filename() {
+            Some(filename) => {
+                let mut file = NamedTempFile::new()?;
+                let mut head = Vec::with_capacity(SNIFF_LENGTH);
+                let mut size = 0;
+
+                while let Some(chunk) = field.next().await {
+                    let chunk = chunk.map_err(invalid)?;
+
+                    size += chunk.len();
+                    total += chunk.len();
+
+                    if size > config.file_limit {
+                        return Err(UploadError::PayloadTooLarge(config.file_limit));
+                    }
+                    if total > config.total_limit {
+                        return Err(UploadError::PayloadTooLarge(config.total_limit));
+                    }
+
+                    if head.len() < SNIFF_LENGTH {
+                        let end = chunk.len().min(SNIFF_LENGTH - head.len());
+                        head.extend_from_slice(&chunk[..end]);
+                    }
+
+                    file.write_all(&chunk)?;
+                }
+
+                // A file input left empty is still sent by the browser
+                if filename.is_empty() && size == 0 {
+                    continue;
+                }
+
+                let path = file.into_temp_path();
+                let value = serde_json::json!({
+                    "name": filename,
+                    "mime_type": sniff(&head),
+                    "size": size,
+                    "path": path.to_path_buf(),
+                });
+
+                files.push(path);
+
+                value
+            }
+            None => {
+                let mut data = Vec::new();
+
+                while let Some(chunk) = field.next().await {
+                    let chunk = chunk.map_err(invalid)?;
+
+                    total += chunk.len();
+
+                    if data.len() + chunk.len() > FIELD_LIMIT {
+                        return Err(UploadError::PayloadTooLarge(FIELD_LIMIT));
+                    }
+                    if total > config.total_limit {
+                        return Err(UploadError::PayloadTooLarge(config.total_limit));
+                    }
+
+                    data.extend_from_slice(&chunk);
+                }
+
+                Value::String(String::from_utf8(data).map_err(invalid)?)
+            }
+        };
+
+        if is_sequence {
+            match fields
+                .entry(name)
+                .or_insert_with(|| Value::Array(Vec::new()))
+            {
+                Value::Array(values) => values.push(value),
+                _ => return Err(invalid("field sent both as a value and a sequence")),
+            }
+        } else {
+            fields.insert(name, value);
+        }
+    }
+
+    let data = T::deserialize(de::Field(Value::Object(fields))).map_err(invalid)?;
+
+    Ok(Multipart {
+        data,
+        _files: files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{File, Multipart};
+    use crate::CookieIdentityPolicy;
+    use crate::IdentityService;
+    use actix_identity::Identity;
+    use actix_web::{cookie::Cookie, http, http::StatusCode, test, web, App, HttpResponse};
+    use serde::Deserialize;
+    use std::str::FromStr;
+
+    #[derive(Deserialize)]
+    struct Form {
+        file: File,
+    }
+
+    async fn upload(form: Multipart<Form>) -> HttpResponse {
+        HttpResponse::Ok().body(form.file.len().to_string())
+    }
+
+    async fn login(session: Identity) -> HttpResponse {
+        session.remember(String::from("1"));
+        HttpResponse::Ok().finish()
+    }
+
+    fn request() -> test::TestRequest {
+        test::TestRequest::post()
+            .uri("/upload")
+            .header(
+                http::header::CONTENT_TYPE,
+                "multipart/form-data; boundary=boundary",
+            )
+            .set_payload(
+                "--boundary\r\n\
+                Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                Content-Type: text/plain\r\n\r\n\
+                Lorem\r\n\
+                --boundary--\r\n",
+            )
+    }
+
+    #[actix_rt::test]
+    async fn test_upload_not_logged() {
+        let mut app = test::init_service(
+            App::new()
+                .wrap(IdentityService::new(
+                    CookieIdentityPolicy::new(&[0; 32]).name("auth-cookie"),
+                ))
+                .route("/upload", web::post().to(upload)),
+        )
+        .await;
+        let res = request().send_request(&mut app).await;
+
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_rt::test]
+    async fn test_upload_logged() {
+        let mut app = test::init_service(
+            App::new()
+                .wrap(IdentityService::new(
+                    CookieIdentityPolicy::new(&[0; 32]).name("auth-cookie"),
+                ))
+                .route("/login", web::post().to(login))
+                .route("/upload", web::post().to(upload)),
+        )
+        .await;
+        let res = test::TestRequest::post()
+            .uri("/login")
+            .send_request(&mut app)
+            .await;
+        let cookie = res.headers().get(http::header::SET_COOKIE).unwrap();
+        let res = request()
+            .cookie(Cookie::from_str(cookie.to_str().unwrap()).unwrap())
+            .send_request(&mut app)
+            .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test::read_body(res).await, "5");
+    }
+}
@@ -0,0 +1,228 @@
+use image::DynamicImage;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::{path::Path, process::Command};
+
+/// Maximum size (in bytes) of an uploaded video
+pub const MAX_SIZE: usize = 100_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VideoFormat {
+    Mp4,
+    WebM,
+}
+
+impl VideoFormat {
+    /// Detect the container format from the first bytes of the file instead of
+    /// trusting the content type sent by the browser
+    pub fn sniff(data: &[u8]) -> Option<Self> {
+        if data.len() >= 12 && &data[4..8] == b"ftyp" {
+            return Some(VideoFormat::Mp4);
+        }
+
+        if data.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+            return Some(VideoFormat::WebM);
+        }
+
+        None
+    }
+
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        match mime_type {
+            "video/mp4" => Some(VideoFormat::Mp4),
+            "video/webm" => Some(VideoFormat::WebM),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &str) -> Option<Self> {
+        match path.rsplit('.').next()? {
+            "mp4" => Some(VideoFormat::Mp4),
+            "webm" => Some(VideoFormat::WebM),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            VideoFormat::Mp4 => "mp4",
+            VideoFormat::WebM => "webm",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            VideoFormat::Mp4 => "video/mp4",
+            VideoFormat::WebM => "video/webm",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum VideoError {
+    Io(std::io::Error),
+    Ffmpeg(String),
+    Image(image::error::ImageError),
+}
+
+impl VideoError {
+    /// Whether the video could not be decoded, the other errors being the ones
+    /// of the server
+    pub fn is_invalid_video(&self) -> bool {
+        matches!(self, VideoError::Ffmpeg(_))
+    }
+}
+
+impl std::fmt::Display for VideoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VideoError::Io(e) => write!(f, "{}", e),
+            VideoError::Ffmpeg(e) => write!(f, "ffmpeg: {}", e),
+            VideoError::Image(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Extract a representative frame of the video to be used as poster, ffmpeg
+/// needs to be installed on the server. Blocks until ffmpeg exits, to be run
+/// with `web::block`
+pub fn poster(path: &Path) -> Result<DynamicImage, VideoError> {
+    let output = Command::new("ffmpeg")
+        .args(&["-v", "error", "-i"])
+        .arg(path)
+        .args(&[
+            "-vf",
+            "thumbnail",
+            "-frames:v",
+            "1",
+            "-f",
+            "image2pipe",
+            "-vcodec",
+            "png",
+            "-",
+        ])
+        .output()
+        .map_err(VideoError::Io)?;
+
+    if !output.status.success() || output.stdout.is_empty() {
+        return Err(VideoError::Ffmpeg(
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        ));
+    }
+
+    image::load_from_memory(&output.stdout).map_err(VideoError::Image)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Provider {
+    Vimeo,
+    YouTube,
+}
+
+impl Provider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Provider::Vimeo => "vimeo",
+            Provider::YouTube => "youtube",
+        }
+    }
+
+    pub fn from_name(value: &str) -> Option<Self> {
+        match value {
+            "vimeo" => Some(Provider::Vimeo),
+            "youtube" => Some(Provider::YouTube),
+            _ => None,
+        }
+    }
+
+    pub fn player_url(&self, id: &str) -> String {
+        match self {
+            Provider::Vimeo => format!(
+                "https://player.vimeo.com/video/{}?title=0&byline=0&portrait=0",
+                id
+            ),
+            Provider::YouTube => format!("https://www.youtube-nocookie.com/embed/{}", id),
+        }
+    }
+}
+
+static VIMEO: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^https://(?:www\.|player\.)?vimeo\.com/(?:video/)?(\d{1,12})(?:[/?#].*)?$")
+        .unwrap()
+});
+
+static YOUTUBE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^https://(?:(?:www\.|m\.)?youtube(?:-nocookie)?\.com/(?:watch\?(?:.*&)?v=|embed/|shorts/)|youtu\.be/)([A-Za-z0-9_-]{11})(?:[&?#].*)?$").unwrap()
+});
+
+/// Extract the provider and the video id of a Vimeo or YouTube link
+pub fn parse_embed(url: &str) -> Option<(Provider, String)> {
+    if let Some(captures) = VIMEO.captures(url.trim()) {
+        return Some((Provider::Vimeo, captures[1].to_string()));
+    }
+
+    if let Some(captures) = YOUTUBE.captures(url.trim()) {
+        return Some((Provider::YouTube, captures[1].to_string()));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Provider;
+
+    #[test]
+    fn test_parse_embed() {
+        let accepted = [
+            ("https://vimeo.com/76979871", Provider::Vimeo, "76979871"),
+            (
+                "https://player.vimeo.com/video/76979871?h=8272103f6e",
+                Provider::Vimeo,
+                "76979871",
+            ),
+            (
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+                Provider::YouTube,
+                "dQw4w9WgXcQ",
+            ),
+            (
+                "https://m.youtube.com/watch?feature=share&v=dQw4w9WgXcQ",
+                Provider::YouTube,
+                "dQw4w9WgXcQ",
+            ),
+            (
+                "https://youtube.com/shorts/dQw4w9WgXcQ",
+                Provider::YouTube,
+                "dQw4w9WgXcQ",
+            ),
+            (
+                " https://youtu.be/dQw4w9WgXcQ ",
+                Provider::YouTube,
+                "dQw4w9WgXcQ",
+            ),
+        ];
+
+        for (url, provider, id) in accepted.iter() {
+            assert_eq!(
+                super::parse_embed(url),
+                Some((*provider, id.to_string())),
+                "{}",
+                url
+            );
+        }
+
+        let rejected = [
+            "http://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://www.youtube.com.example.com/watch?v=dQw4w9WgXcQ",
+            "https://www.youtube.com/watch?v=short",
+            "https://vimeo.com/1234567890123",
+            "https://example.com/vimeo.com/76979871",
+            "javascript:alert(1)",
+        ];
+
+        for url in rejected.iter() {
+            assert_eq!(super::parse_embed(url), None, "{}", url);
+        }
+    }
+}
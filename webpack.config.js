@@ -28,7 +28,12 @@ module.exports = {
         'admin/my_little_plus': `${entry_path}/admin/my_little_plus.js`,
         'admin/settings': `${entry_path}/admin/settings.js`,
         'admin/blog': `${entry_path}/admin/blog.js`,
-        'admin/motion_design': `${entry_path}/admin/motion_design.js`
+        'admin/motion_design': `${entry_path}/admin/motion_design.js`,
+        'admin/statistics': `${entry_path}/admin/statistics.js`,
+        'admin/messages': `${entry_path}/admin/messages.js`,
+        'admin/subscribers': `${entry_path}/admin/subscribers.js`,
+        'admin/comments': `${entry_path}/admin/comments.js`,
+        'admin/sidenav': `${entry_path}/admin/sidenav.js`
     },
     watch: process.env.NODE_ENV === 'development',
     watchOptions: {